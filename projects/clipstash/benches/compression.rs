@@ -0,0 +1,140 @@
+use clipstash::{
+    data::{query, AppDatabase, DatabasePool, DbId},
+    domain::{clip::field, Clip},
+    service::ask,
+};
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const CLIPS: usize = 200;
+const READS: usize = 2000;
+
+/// * Large log dumps are what compression is meant for, so every clip is a ~24KiB log
+fn log_dump(clip: usize) -> String {
+    (0..300)
+        .map(|line| {
+            format!(
+                "2026-10-18T12:{:02}:{:02}Z INFO request_id={clip}-{line} GET /clip/{clip} status=200 elapsed={}ms\n",
+                line / 60 % 60,
+                line % 60,
+                (clip * 31 + line * 7) % 500
+            )
+        })
+        .collect()
+}
+
+async fn new_db(path: &Path) -> AppDatabase {
+    let _ = std::fs::remove_file(path);
+    let database = AppDatabase::new(&format!("sqlite:{}?mode=rwc", path.display())).await;
+    database
+        .migrate(Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/migrations"
+        )))
+        .await
+        .expect("failed to run migrations");
+    database
+}
+
+// NOTE The benchmark measures the size of SQLite's file, so it only runs against SQLite
+fn sqlite(pool: &DatabasePool) -> &SqlitePool {
+    match pool {
+        DatabasePool::Sqlite(pool) => pool,
+        DatabasePool::Postgres(_) => unreachable!("the benchmark creates a SQLite database"),
+    }
+}
+
+/// * Inserts the content as is, like clips were stored before compression
+async fn insert_plain(pool: &DatabasePool) {
+    for clip in 0..CLIPS {
+        sqlx::query(
+            "INSERT INTO clips (clip_id, shortcode, content, posted, hits) VALUES (?, ?, ?, ?, 0)",
+        )
+        .bind(String::from(DbId::new()))
+        .bind(format!("clip{clip}"))
+        .bind(log_dump(clip))
+        .bind(chrono::Utc::now().timestamp())
+        .execute(sqlite(pool))
+        .await
+        .expect("failed to insert clip");
+    }
+}
+
+async fn insert_compressed(pool: &DatabasePool) {
+    for clip in 0..CLIPS {
+        let req = ask::NewClip {
+            shortcode: format!("clip{clip}").parse().unwrap(),
+            ..ask::NewClip::new(field::Content::new(&log_dump(clip)).unwrap())
+        };
+        query::new_clip(req, pool)
+            .await
+            .expect("failed to insert clip");
+    }
+}
+
+async fn db_size(pool: &DatabasePool) -> i64 {
+    let pool = sqlite(pool);
+    sqlx::query("VACUUM")
+        .execute(pool)
+        .await
+        .expect("failed to vacuum");
+    let (page_count,): (i64,) = sqlx::query_as("PRAGMA page_count")
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    let (page_size,): (i64,) = sqlx::query_as("PRAGMA page_size")
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    page_count * page_size
+}
+
+/// * Reads and converts clips to the domain type, which is where content gets decompressed
+async fn get_clip_latency(pool: &DatabasePool) -> Duration {
+    let start = Instant::now();
+    for read in 0..READS {
+        let row = query::get_clip(format!("clip{}", read % CLIPS), pool)
+            .await
+            .expect("failed to get clip");
+        let clip = Clip::try_from(row).expect("failed to convert clip");
+        assert!(!clip.content.as_str().is_empty());
+    }
+    start.elapsed() / READS as u32
+}
+
+fn bench_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("clipstash-bench-{name}.db"))
+}
+
+fn main() {
+    let rt = tokio::runtime::Runtime::new().expect("failed to spawn tokio runtime");
+
+    rt.block_on(async {
+        println!(
+            "{CLIPS} clips of ~{}KiB, {READS} reads",
+            log_dump(0).len() / 1024
+        );
+        for (name, compressed) in [("plain", false), ("compressed", true)] {
+            let path = bench_path(name);
+            let database = new_db(&path).await;
+            let pool = database.get_pool();
+            if compressed {
+                insert_compressed(pool).await;
+            } else {
+                insert_plain(pool).await;
+            }
+
+            let size = db_size(pool).await;
+            let latency = get_clip_latency(pool).await;
+            println!(
+                "{name:>10}: db size {:>8}KiB, get_clip {:>6.1}µs",
+                size / 1024,
+                latency.as_secs_f64() * 1_000_000.0
+            );
+
+            pool.close().await;
+            let _ = std::fs::remove_file(&path);
+        }
+    });
+}
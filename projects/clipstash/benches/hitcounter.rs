@@ -0,0 +1,182 @@
+use clipstash::{
+    data::{query, AppDatabase, DatabasePool},
+    domain::clip::field,
+    service::ask,
+    web::hitcounter::{HitCounter, HitCounterSettings},
+    Shortcode,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Sender, TryRecvError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+
+const CLIPS: usize = 2000;
+const HITS: usize = 200_000;
+/// * How long the replaced hit counter slept whenever its channel was empty, the same as the default `hit_commit_interval`
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+async fn new_db(path: &Path) -> AppDatabase {
+    let _ = std::fs::remove_file(path);
+    let database = AppDatabase::new(&format!("sqlite:{}?mode=rwc", path.display())).await;
+    database
+        .migrate(Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/migrations"
+        )))
+        .await
+        .expect("failed to run migrations");
+    database
+}
+
+fn shortcode(clip: usize) -> Shortcode {
+    format!("clip{clip}").parse().unwrap()
+}
+
+async fn insert_clips(pool: &DatabasePool) {
+    for clip in 0..CLIPS {
+        let req = ask::NewClip {
+            shortcode: format!("clip{clip}").parse().unwrap(),
+            ..ask::NewClip::new(field::Content::new("content").unwrap())
+        };
+        query::new_clip(req, pool)
+            .await
+            .expect("failed to insert clip");
+    }
+}
+
+async fn total_hits(pool: &DatabasePool) -> i64 {
+    let DatabasePool::Sqlite(pool) = pool else {
+        unreachable!("the benchmark creates a SQLite database");
+    };
+    let (total,): (i64,) = sqlx::query_as("SELECT SUM(hits) FROM clips")
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    total
+}
+
+/// * Writes one row at a time on the pool, like the replaced hit counter did
+async fn commit_per_clip(hits: &[(Shortcode, u32)], pool: &DatabasePool) {
+    let DatabasePool::Sqlite(pool) = pool else {
+        unreachable!("the benchmark creates a SQLite database");
+    };
+    for (shortcode, count) in hits {
+        sqlx::query("UPDATE clips SET hits = hits + ? WHERE shortcode = ?")
+            .bind(*count as i64)
+            .bind(shortcode.as_str())
+            .execute(pool)
+            .await
+            .expect("failed to increase hit count");
+    }
+}
+
+enum PollingMsg {
+    Commit,
+    Hit(Shortcode, u32),
+    Stop,
+}
+
+/// * The hit counter that `HitCounter` replaced, a thread busy-polls an unbounded channel and sleeps whenever it's empty
+/// * After each sleep it blocks on the runtime to write the collected hits one clip at a time
+struct PollingCounter {
+    tx: Sender<PollingMsg>,
+    thread: JoinHandle<()>,
+}
+
+impl PollingCounter {
+    fn new(pool: DatabasePool, handle: Handle) -> Self {
+        let (tx, rx) = channel();
+        let commits = tx.clone();
+        let thread = std::thread::spawn(move || {
+            let mut hits = HashMap::new();
+            loop {
+                match rx.try_recv() {
+                    Ok(PollingMsg::Hit(shortcode, count)) => {
+                        *hits.entry(shortcode).or_insert(0) += count;
+                    }
+                    Ok(PollingMsg::Commit) => {
+                        let batch = hits.drain().collect::<Vec<_>>();
+                        handle.block_on(commit_per_clip(&batch, &pool));
+                    }
+                    Ok(PollingMsg::Stop) | Err(TryRecvError::Disconnected) => break,
+                    Err(TryRecvError::Empty) => {
+                        std::thread::sleep(POLL_INTERVAL);
+                        let _ = commits.send(PollingMsg::Commit);
+                    }
+                }
+            }
+        });
+        Self { tx, thread }
+    }
+
+    fn hit(&self, shortcode: Shortcode, count: u32) {
+        let _ = self.tx.send(PollingMsg::Hit(shortcode, count));
+    }
+
+    // ? The replaced thread ran until the process exited, it's stopped here so the database can be removed
+    fn stop(self) {
+        let _ = self.tx.send(PollingMsg::Stop);
+        self.thread.join().expect("polling thread panicked");
+    }
+}
+
+fn bench_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("clipstash-bench-hits-{name}.db"))
+}
+
+/// * Sends every hit and waits until they're all in the database, both counters commit every 5 seconds
+fn run(name: &str, rt: &tokio::runtime::Runtime, pool: &DatabasePool, hit: impl Fn(Shortcode)) {
+    let start = Instant::now();
+    for hit_number in 0..HITS {
+        hit(shortcode(hit_number % CLIPS));
+    }
+    let sent = start.elapsed();
+    while rt.block_on(total_hits(pool)) < HITS as i64 {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let committed = start.elapsed();
+    assert_eq!(rt.block_on(total_hits(pool)), HITS as i64);
+    println!(
+        "{name:>8}: hit {:>6.2}µs, every hit committed after {:>8.1}ms",
+        sent.as_secs_f64() * 1_000_000.0 / HITS as f64,
+        committed.as_secs_f64() * 1000.0
+    );
+}
+
+fn main() {
+    let rt = tokio::runtime::Runtime::new().expect("failed to spawn tokio runtime");
+    println!("{HITS} hits over {CLIPS} clips");
+
+    for name in ["polling", "counter"] {
+        let path = bench_path(name);
+        let database = rt.block_on(new_db(&path));
+        let pool = database.get_pool().clone();
+        rt.block_on(insert_clips(&pool));
+
+        // NOTE Hits are sent the way request handlers send them, from outside the runtime's tasks
+        match name {
+            "polling" => {
+                let counter = PollingCounter::new(pool.clone(), rt.handle().clone());
+                run(name, &rt, &pool, |shortcode| counter.hit(shortcode, 1));
+                counter.stop();
+            }
+            _ => {
+                let counter = HitCounter::new(
+                    pool.clone(),
+                    rt.handle().clone(),
+                    HitCounterSettings {
+                        queue_capacity: HITS,
+                        ..Default::default()
+                    },
+                );
+                run(name, &rt, &pool, |shortcode| counter.hit(shortcode, 1));
+                counter.shutdown();
+            }
+        }
+
+        rt.block_on(pool.close());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE clips ADD COLUMN max_views BIGINT;
+ALTER TABLE clips ADD COLUMN views BIGINT NOT NULL DEFAULT 0;
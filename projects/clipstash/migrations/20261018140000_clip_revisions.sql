@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS clip_revisions
+(
+    clip_id   TEXT NOT NULL,
+    revision  BIGINT NOT NULL,
+    content   TEXT NOT NULL,
+    title     TEXT,
+    expires   DATETIME,
+    replaced  DATETIME NOT NULL,
+    PRIMARY KEY (clip_id, revision)
+);
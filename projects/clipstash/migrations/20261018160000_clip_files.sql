@@ -0,0 +1,11 @@
+-- Add migration script here
+ALTER TABLE clips ADD COLUMN file_name TEXT;
+ALTER TABLE clips ADD COLUMN mime_type TEXT;
+ALTER TABLE clips ADD COLUMN file_size BIGINT;
+
+-- ? The file contents are kept apart so viewing a clip doesn't load them
+CREATE TABLE IF NOT EXISTS clip_files
+(
+    clip_id TEXT PRIMARY KEY NOT NULL,
+    data    BLOB NOT NULL
+);
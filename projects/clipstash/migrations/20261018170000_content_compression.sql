@@ -0,0 +1,6 @@
+-- Add migration script here
+-- ? Compressed content is stored as a BLOB, `content` is left empty for those rows
+ALTER TABLE clips ADD COLUMN content_encoding TEXT;
+ALTER TABLE clips ADD COLUMN content_compressed BLOB;
+ALTER TABLE clip_revisions ADD COLUMN content_encoding TEXT;
+ALTER TABLE clip_revisions ADD COLUMN content_compressed BLOB;
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- ? Encrypted clips store ciphertext, the key only exists in the clip's URL fragment
+ALTER TABLE clips ADD COLUMN encrypted BOOLEAN NOT NULL DEFAULT 0;
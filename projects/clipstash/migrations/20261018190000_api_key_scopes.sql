@@ -0,0 +1,19 @@
+-- Add migration script here
+-- ? SQLite can't add NOT NULL columns without a constant default, so the table is rebuilt
+CREATE TABLE api_keys_scoped
+(
+    api_key   BLOB PRIMARY KEY,
+    name      TEXT UNIQUE NOT NULL,
+    created   DATETIME NOT NULL,
+    expires   DATETIME,
+    last_used DATETIME,
+    scopes    TEXT NOT NULL
+);
+
+-- * Existing keys keep the access they had, every scope but admin
+INSERT INTO api_keys_scoped (api_key, name, created, scopes)
+SELECT api_key, 'legacy-' || lower(hex(substr(api_key, 1, 4))), strftime('%s', 'now'), 'read,create,update,delete'
+FROM api_keys;
+
+DROP TABLE api_keys;
+ALTER TABLE api_keys_scoped RENAME TO api_keys;
@@ -0,0 +1,23 @@
+-- Add migration script here
+-- ? Full text index over the title and content of clips, it's kept up to date by the application
+-- ? since compressed content can't be read from SQL. Only the title of password protected and
+-- ? encrypted clips is indexed, so searching can't reveal their content
+CREATE VIRTUAL TABLE clips_fts USING fts5(
+    clip_id UNINDEXED,
+    title,
+    content,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+-- NOTE Clips are deleted from several places (expiry, burn after reading), the trigger covers all of them
+CREATE TRIGGER clips_fts_delete AFTER DELETE ON clips BEGIN
+    DELETE FROM clips_fts WHERE clip_id = old.clip_id;
+END;
+
+-- ? Compressed clips only get their title indexed here, `httpd reindex` indexes their content
+INSERT INTO clips_fts (clip_id, title, content)
+SELECT
+    clip_id,
+    coalesce(title, ''),
+    CASE WHEN password IS NULL AND NOT encrypted THEN content ELSE '' END
+FROM clips;
@@ -0,0 +1,23 @@
+-- Add migration script here
+-- ? Passwords are stored as Argon2 hashes, usernames are unique regardless of case
+CREATE TABLE users
+(
+    user_id  TEXT PRIMARY KEY NOT NULL,
+    username TEXT UNIQUE NOT NULL COLLATE NOCASE,
+    password TEXT NOT NULL,
+    created  DATETIME NOT NULL
+);
+
+-- ? Only a SHA-256 hash of the session token is stored, the token itself only lives in the user's cookie
+CREATE TABLE sessions
+(
+    session_hash BLOB PRIMARY KEY NOT NULL,
+    user_id      TEXT NOT NULL REFERENCES users (user_id),
+    created      DATETIME NOT NULL,
+    expires      DATETIME NOT NULL
+);
+
+-- NOTE Clips and API keys created before accounts existed stay anonymous
+ALTER TABLE clips ADD COLUMN owner TEXT REFERENCES users (user_id);
+ALTER TABLE api_keys ADD COLUMN owner TEXT REFERENCES users (user_id);
+CREATE INDEX clips_owner ON clips (owner);
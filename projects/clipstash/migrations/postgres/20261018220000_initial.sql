@@ -0,0 +1,85 @@
+-- Add migration script here
+-- ? Postgres was added once SQLite's schema had settled, so its whole schema starts from this migration
+-- ? Times are TIMESTAMP in UTC, SQLite stores them as Unix timestamps
+CREATE TABLE users
+(
+    user_id  TEXT PRIMARY KEY NOT NULL,
+    username TEXT NOT NULL,
+    password TEXT NOT NULL,
+    created  TIMESTAMP NOT NULL
+);
+
+-- NOTE Usernames are unique regardless of case, like SQLite's COLLATE NOCASE
+CREATE UNIQUE INDEX users_username ON users (lower(username));
+
+CREATE TABLE sessions
+(
+    session_hash BYTEA PRIMARY KEY NOT NULL,
+    user_id      TEXT NOT NULL REFERENCES users (user_id),
+    created      TIMESTAMP NOT NULL,
+    expires      TIMESTAMP NOT NULL
+);
+
+CREATE TABLE clips
+(
+    clip_id            TEXT PRIMARY KEY NOT NULL,
+    shortcode          TEXT UNIQUE NOT NULL,
+    content            TEXT NOT NULL,
+    title              TEXT,
+    posted             TIMESTAMP NOT NULL,
+    expires            TIMESTAMP,
+    password           TEXT,
+    hits               BIGINT NOT NULL,
+    edit_secret        TEXT,
+    max_views          BIGINT,
+    views              BIGINT NOT NULL DEFAULT 0,
+    language           TEXT,
+    file_name          TEXT,
+    mime_type          TEXT,
+    file_size          BIGINT,
+    content_encoding   TEXT,
+    content_compressed BYTEA,
+    encrypted          BOOLEAN NOT NULL DEFAULT FALSE,
+    owner              TEXT REFERENCES users (user_id)
+);
+
+CREATE INDEX clips_owner ON clips (owner);
+
+CREATE TABLE clip_revisions
+(
+    clip_id            TEXT NOT NULL,
+    revision           BIGINT NOT NULL,
+    content            TEXT NOT NULL,
+    title              TEXT,
+    expires            TIMESTAMP,
+    replaced           TIMESTAMP NOT NULL,
+    content_encoding   TEXT,
+    content_compressed BYTEA,
+    PRIMARY KEY (clip_id, revision)
+);
+
+CREATE TABLE clip_files
+(
+    clip_id TEXT PRIMARY KEY NOT NULL,
+    data    BYTEA NOT NULL
+);
+
+-- ? Takes the place of SQLite's FTS5 table, the foreign key removes deleted clips like SQLite's trigger does
+CREATE TABLE clips_fts
+(
+    clip_id  TEXT PRIMARY KEY NOT NULL REFERENCES clips (clip_id) ON DELETE CASCADE,
+    document TSVECTOR NOT NULL
+);
+
+CREATE INDEX clips_fts_document ON clips_fts USING GIN (document);
+
+CREATE TABLE api_keys
+(
+    api_key   BYTEA PRIMARY KEY,
+    name      TEXT UNIQUE NOT NULL,
+    created   TIMESTAMP NOT NULL,
+    expires   TIMESTAMP,
+    last_used TIMESTAMP,
+    scopes    TEXT NOT NULL,
+    owner     TEXT REFERENCES users (user_id)
+);
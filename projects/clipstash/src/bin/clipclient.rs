@@ -1,44 +1,153 @@
 use clipstash::{
-    domain::clip::field::{Content, Expires, Password, Shortcode, Title},
-    service::ask::{GetClip, NewClip, UpdateClip},
-    web::{
-        api::{ApiKey, API_KEY_HEADER},
-        PASSWORD_COOKIE,
+    domain::{
+        clip::field::{
+            ClipKey, Content, EditSecret, Encrypted, Expires, Language, MaxViews, Password,
+            Shortcode, Title, Upload, VanityShortcode,
+        },
+        ClipOrder, Cursor,
     },
-    Clip,
+    service::ask::{DeleteClip, GetClip, ListClips, NewClip, UpdateClip},
+    web::api::{
+        v1::{self, Clip, ClipPage, NewClipResponse, Revision},
+        ApiKey, ErrorResponse, API_KEY_HEADER, EDIT_SECRET_HEADER, PASSWORD_HEADER,
+    },
+    Time,
 };
-use std::error::Error;
+use serde::de::DeserializeOwned;
+use std::{error::Error, path::PathBuf};
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
 enum Command {
     Get {
-        shortcode: Shortcode,
+        #[structopt(
+            help = "shortcode or URL of the clip, encrypted clips need the full URL with its #key"
+        )]
+        clip: String,
         #[structopt(short, long, help = "password")]
         password: Option<String>,
     },
     New {
-        #[structopt(help = "content")]
-        clip: String,
+        #[structopt(help = "content, defaults to the file name when uploading a file")]
+        clip: Option<String>,
+        #[structopt(short, long, parse(from_os_str), help = "file to upload")]
+        file: Option<PathBuf>,
         #[structopt(short, long, help = "password")]
         password: Option<Password>,
-        #[structopt(short, long, help = "expiration date")]
+        #[structopt(
+            short,
+            long,
+            help = "expiration date, RFC 3339 timestamp or duration such as 10m, 2h or 7d"
+        )]
         expires: Option<Expires>,
         #[structopt(short, long, help = "title")]
         title: Option<Title>,
+        #[structopt(
+            short,
+            long,
+            help = "views before the clip is deleted, 1 burns it after reading"
+        )]
+        max_views: Option<MaxViews>,
+        #[structopt(
+            short,
+            long,
+            help = "language used to highlight the clip, detected when unset"
+        )]
+        language: Option<Language>,
+        #[structopt(
+            short,
+            long,
+            help = "custom shortcode, a random one is generated when unset"
+        )]
+        shortcode: Option<VanityShortcode>,
+        #[structopt(
+            long,
+            help = "encrypt the content locally, the key is only part of the printed URL"
+        )]
+        encrypt: bool,
     },
     Update {
         shortcode: Shortcode,
         clip: String,
+        #[structopt(
+            short = "s",
+            long,
+            help = "edit secret returned when the clip was created, not needed with a key of the clip's owner"
+        )]
+        edit_secret: Option<EditSecret>,
         #[structopt(short, long, help = "password")]
         password: Option<Password>,
-        #[structopt(short, long, help = "expiration date")]
+        #[structopt(
+            short,
+            long,
+            help = "expiration date, RFC 3339 timestamp or duration such as 10m, 2h or 7d"
+        )]
         expires: Option<Expires>,
         #[structopt(short, long, help = "title")]
         title: Option<Title>,
+        #[structopt(short, long, help = "language used to highlight the clip")]
+        language: Option<Language>,
+    },
+    History {
+        shortcode: Shortcode,
+        #[structopt(short, long, help = "password")]
+        password: Option<String>,
+        #[structopt(short, long, help = "only show this revision")]
+        revision: Option<u32>,
+    },
+    Delete {
+        shortcode: Shortcode,
+        #[structopt(
+            short = "s",
+            long,
+            help = "edit secret returned when the clip was created, not needed with a key of the clip's owner"
+        )]
+        edit_secret: Option<EditSecret>,
+    },
+    /// Lists the available clips, newest first by default
+    List {
+        #[structopt(flatten)]
+        options: ListOptions,
+    },
+    /// Searches the title and content of the available clips
+    Search {
+        query: String,
+        #[structopt(flatten)]
+        options: ListOptions,
     },
 }
 
+#[derive(StructOpt, Debug)]
+struct ListOptions {
+    #[structopt(short, long, help = "only clips whose title contains this text")]
+    title: Option<String>,
+    #[structopt(
+        short,
+        long,
+        help = "only clips posted after this RFC 3339 timestamp or YYYY-MM-DD date"
+    )]
+    after: Option<Time>,
+    #[structopt(short, long, help = "newest, oldest or hits", default_value = "newest")]
+    order: ClipOrder,
+    #[structopt(short, long, help = "cursor printed with the previous page")]
+    cursor: Option<Cursor>,
+    #[structopt(short, long, help = "clips per page, at most 100")]
+    limit: Option<u32>,
+}
+
+impl ListOptions {
+    fn into_request(self, q: Option<String>) -> ListClips {
+        ListClips {
+            q,
+            title: self.title,
+            posted_after: self.after,
+            order: self.order,
+            cursor: self.cursor,
+            limit: self.limit,
+        }
+    }
+}
+
 #[derive(StructOpt, Debug)]
 #[structopt(name = "clipclient", about = "ClipStash API Client")]
 struct Opt {
@@ -50,30 +159,145 @@ struct Opt {
     api_key: ApiKey,
 }
 
+/// * Clips are referenced by shortcode or by URL, the fragment of the URL holds the key of encrypted clips
+fn parse_clip_ref(
+    clip: &str,
+    addr: &str,
+) -> Result<(String, Shortcode, Option<ClipKey>), Box<dyn Error>> {
+    if !clip.contains("://") {
+        return Ok((addr.to_owned(), clip.parse()?, None));
+    }
+
+    let url = reqwest::Url::parse(clip)?;
+    let shortcode = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .ok_or("the URL doesn't point to a clip")?
+        .parse()?;
+    let key = url.fragment().map(str::parse).transpose()?;
+    Ok((url.origin().ascii_serialization(), shortcode, key))
+}
+
+/// * Failed requests are returned as the API's error, instead of failing to decode the expected body
+fn parse_response<T: DeserializeOwned>(
+    response: reqwest::blocking::Response,
+) -> Result<T, Box<dyn Error>> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response.json()?);
+    }
+    let body = response.text()?;
+    match serde_json::from_str::<ErrorResponse>(&body) {
+        Ok(error) => Err(error.into()),
+        Err(_) => Err(format!("request failed with {status}: {body}").into()),
+    }
+}
+
 fn get_clip(addr: &str, ask_svc: GetClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
     let client = reqwest::blocking::Client::builder().build()?;
-    let addr = format!("{}/api/clip/{}", addr, ask_svc.shortcode.into_inner());
+    let addr = format!("{}{}/{}", addr, v1::BASE, ask_svc.shortcode.into_inner());
 
     let mut request = client.get(addr);
 
     if let Some(password) = ask_svc.password.into_inner() {
-        request = request.header(
-            reqwest::header::COOKIE,
-            format!("{PASSWORD_COOKIE}={password}"),
-        );
+        request = request.header(PASSWORD_HEADER, password);
     }
 
     request = add_api_key_header(request, api_key);
-    Ok(request.send()?.json()?)
+    parse_response(request.send()?)
 }
 
-fn new_clip(addr: &str, ask_svc: NewClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
+fn get_revisions(
+    addr: &str,
+    ask_svc: GetClip,
+    revision: Option<u32>,
+    api_key: ApiKey,
+) -> Result<Vec<Revision>, Box<dyn Error>> {
     let client = reqwest::blocking::Client::builder().build()?;
-    let addr = format!("{}/api/clip", addr);
+    let mut addr = format!(
+        "{}{}/{}/revisions",
+        addr,
+        v1::BASE,
+        ask_svc.shortcode.into_inner()
+    );
+    if let Some(revision) = revision {
+        addr = format!("{addr}/{revision}");
+    }
+
+    let mut request = client.get(addr);
+
+    if let Some(password) = ask_svc.password.into_inner() {
+        request = request.header(PASSWORD_HEADER, password);
+    }
+
+    request = add_api_key_header(request, api_key);
+    let response = request.send()?;
+    match revision {
+        Some(_) => Ok(vec![parse_response(response)?]),
+        None => parse_response(response),
+    }
+}
+
+fn new_clip(
+    addr: &str,
+    ask_svc: NewClip,
+    api_key: ApiKey,
+) -> Result<NewClipResponse, Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder().build()?;
+    let addr = format!("{}{}", addr, v1::BASE);
 
     let mut request = client.post(addr);
     request = add_api_key_header(request, api_key);
-    Ok(request.json(&ask_svc).send()?.json()?)
+    parse_response(request.json(&ask_svc).send()?)
+}
+
+// NOTE Files can't be sent as JSON, so clips with a file are sent as a multipart form
+fn upload_clip(
+    addr: &str,
+    ask_svc: NewClip,
+    api_key: ApiKey,
+) -> Result<NewClipResponse, Box<dyn Error>> {
+    use reqwest::blocking::multipart::{Form, Part};
+
+    let client = reqwest::blocking::Client::builder().build()?;
+    let addr = format!("{}{}", addr, v1::BASE);
+
+    let mut form = Form::new().text("content", ask_svc.content.into_inner());
+    let fields = [
+        ("title", ask_svc.title.into_inner()),
+        (
+            "expires",
+            ask_svc
+                .expires
+                .into_inner()
+                .map(|time| time.into_inner().to_rfc3339()),
+        ),
+        ("password", ask_svc.password.into_inner()),
+        (
+            "max_views",
+            ask_svc.max_views.into_inner().map(|n| n.to_string()),
+        ),
+        ("language", ask_svc.language.into_inner()),
+        (
+            "shortcode",
+            ask_svc.shortcode.into_inner().map(Shortcode::into_inner),
+        ),
+    ];
+    for (name, value) in fields {
+        if let Some(value) = value {
+            form = form.text(name, value);
+        }
+    }
+    if let Some((attachment, data)) = ask_svc.file.into_inner() {
+        let part = Part::bytes(data)
+            .file_name(attachment.name().to_owned())
+            .mime_str(attachment.mime_type())?;
+        form = form.part("file", part);
+    }
+
+    let mut request = client.post(addr).multipart(form);
+    request = add_api_key_header(request, api_key);
+    parse_response(request.send()?)
 }
 
 fn add_api_key_header(
@@ -85,57 +309,160 @@ fn add_api_key_header(
 
 fn update_clip(addr: &str, ask_svc: UpdateClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
     let client = reqwest::blocking::Client::builder().build()?;
-    let addr = format!("{}/api/clip", addr);
+    let addr = format!("{}{}", addr, v1::BASE);
 
     let mut request = client.put(addr);
     request = add_api_key_header(request, api_key);
-    Ok(request.json(&ask_svc).send()?.json()?)
+    parse_response(request.json(&ask_svc).send()?)
+}
+
+fn delete_clip(addr: &str, ask_svc: DeleteClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder().build()?;
+    let addr = format!("{}{}/{}", addr, v1::BASE, ask_svc.shortcode.into_inner());
+
+    let mut request = client.delete(addr);
+
+    if let Some(edit_secret) = ask_svc.edit_secret.into_inner() {
+        request = request.header(EDIT_SECRET_HEADER, edit_secret);
+    }
+
+    request = add_api_key_header(request, api_key);
+    parse_response(request.send()?)
+}
+
+fn list_clips(addr: &str, ask_svc: ListClips, api_key: ApiKey) -> Result<ClipPage, Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder().build()?;
+    let addr = format!("{}{}", addr, v1::BASE);
+
+    let mut request = client.get(addr).query(&ask_svc);
+    request = add_api_key_header(request, api_key);
+    parse_response(request.send()?)
+}
+
+fn print_page(page: ClipPage) {
+    for clip in &page.clips {
+        let title = clip.title.clone().into_inner().unwrap_or_default();
+        let protected = if clip.protected { " (protected)" } else { "" };
+        println!(
+            "{:<16} {:<20} {:>8} hits  {title}{protected}",
+            clip.shortcode.as_str(),
+            clip.posted
+                .clone()
+                .into_inner()
+                .into_inner()
+                .format("%Y-%m-%d %H:%M"),
+            clip.hits.clone().into_inner(),
+        );
+    }
+    if page.clips.is_empty() {
+        println!("no clips found");
+    }
+    if let Some(next) = page.next {
+        println!("next page: --cursor {next}");
+    }
 }
 
 // NOTE Boxing errors makes it easier to handle errors from different crates
 fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
     match opt.command {
-        Command::Get {
-            shortcode,
-            password,
-        } => {
+        Command::Get { clip, password } => {
+            let (addr, shortcode, key) = parse_clip_ref(&clip, &opt.addr)?;
             let req = GetClip {
                 password: Password::new(password.unwrap_or_default())?,
                 shortcode,
+                token: None,
             };
-            let clip = get_clip(opt.addr.as_str(), req, opt.api_key)?;
+            let mut clip = get_clip(addr.as_str(), req, opt.api_key)?;
+            // * The server only has the ciphertext, the clip is decrypted here
+            if clip.encrypted.is_set() {
+                let key =
+                    key.ok_or("the clip is encrypted, pass its full URL including the #key")?;
+                clip.content = key.decrypt(&clip.content)?;
+            }
             // NOTE `:#?` is a pretty formatted debug print
             println!("{:#?}", clip);
             Ok(())
         }
         Command::New {
             clip,
+            file,
             password,
             expires,
             title,
+            max_views,
+            language,
+            shortcode,
+            encrypt,
         } => {
+            if encrypt && file.is_some() {
+                return Err("files can't be encrypted".into());
+            }
+            let file = match file {
+                Some(path) => {
+                    let name = path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .ok_or("invalid file name")?;
+                    Upload::from_bytes(name, None, std::fs::read(&path)?)?
+                }
+                None => Upload::default(),
+            };
+            let content = match (clip, file.attachment()) {
+                (Some(clip), _) => Content::new(&clip)?,
+                (None, Some(attachment)) => Content::new(attachment.name())?,
+                (None, None) => return Err("either the content or a file is required".into()),
+            };
+            let key = encrypt.then(ClipKey::generate);
+            let content = match &key {
+                Some(key) => key.encrypt(content.as_str())?,
+                None => content,
+            };
             let req = NewClip {
-                content: Content::new(&clip)?,
+                content,
                 title: title.unwrap_or_default(),
                 expires: expires.unwrap_or_default(),
                 password: password.unwrap_or_default(),
+                max_views: max_views.unwrap_or_default(),
+                language: language.unwrap_or_default(),
+                shortcode: shortcode.unwrap_or_default(),
+                encrypted: Encrypted::new(encrypt),
+                file,
+                edit_secret: EditSecret::default(),
+                owner: None,
             };
-            let clip = new_clip(opt.addr.as_str(), req, opt.api_key)?;
-            println!("{:#?}", clip);
+            let res = match req.file.attachment() {
+                Some(_) => upload_clip(opt.addr.as_str(), req, opt.api_key)?,
+                None => new_clip(opt.addr.as_str(), req, opt.api_key)?,
+            };
+            println!("{:#?}", res.clip);
+            // * The server only stores a hash of the edit secret, so this is the only chance to save it
+            println!("edit secret: {}", res.edit_secret);
+            if let Some(key) = key {
+                // NOTE Fragments are never sent to the server, the URL is the only copy of the key
+                println!(
+                    "url: {}/clip/{}#{}",
+                    opt.addr,
+                    res.clip.shortcode.as_str(),
+                    key.to_base64()
+                );
+            }
             Ok(())
         }
         Command::Update {
             shortcode,
             clip,
+            edit_secret,
             password,
             expires,
             title,
+            language,
         } => {
             let password = password.unwrap_or_default();
 
             let svc_req = GetClip {
                 password: password.clone(),
                 shortcode: shortcode.clone(),
+                token: None,
             };
             let original_clip = get_clip(opt.addr.as_str(), svc_req, opt.api_key.clone())?;
 
@@ -143,13 +470,54 @@ fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
                 content: Content::new(clip.as_str())?,
                 expires: expires.unwrap_or(original_clip.expires),
                 title: title.unwrap_or(original_clip.title),
+                language: language.unwrap_or(original_clip.language),
                 password,
                 shortcode,
+                edit_secret: edit_secret.unwrap_or_default(),
+                user: None,
+                keep_password: false,
             };
             let clip = update_clip(opt.addr.as_str(), req, opt.api_key)?;
             println!("{:#?}", clip);
             Ok(())
         }
+        Command::History {
+            shortcode,
+            password,
+            revision,
+        } => {
+            let req = GetClip {
+                password: Password::new(password.unwrap_or_default())?,
+                shortcode,
+                token: None,
+            };
+            let revisions = get_revisions(opt.addr.as_str(), req, revision, opt.api_key)?;
+            println!("{:#?}", revisions);
+            Ok(())
+        }
+        Command::Delete {
+            shortcode,
+            edit_secret,
+        } => {
+            let req = DeleteClip {
+                shortcode,
+                edit_secret: edit_secret.unwrap_or_default(),
+                user: None,
+            };
+            let clip = delete_clip(opt.addr.as_str(), req, opt.api_key)?;
+            println!("deleted clip {}", clip.shortcode.as_str());
+            Ok(())
+        }
+        Command::List { options } => {
+            let page = list_clips(opt.addr.as_str(), options.into_request(None), opt.api_key)?;
+            print_page(page);
+            Ok(())
+        }
+        Command::Search { query, options } => {
+            let req = options.into_request(Some(query));
+            print_page(list_clips(opt.addr.as_str(), req, opt.api_key)?);
+            Ok(())
+        }
     }
 }
 
@@ -1,47 +1,317 @@
 use clipstash::{
-    data::AppDatabase,
-    domain::{maintenance::Maintenance},
-    web::{hitcounter::HitCounter, renderer::Renderer},
+    config::ConfigErr,
+    data::{query::RevocationStatus, AppDatabase},
+    domain::{clip::field::Expires, maintenance::Maintenance, ApiKeyInfo, ApiScopes, Username},
+    service::{self, ask, token::AccessTokenSigner},
+    web::{hitcounter::HitCounter, logging, openapi, ratelimit::RateLimiter, renderer::Renderer},
+    Config, Time,
 };
 use dotenv::dotenv;
+use figment::providers::Serialized;
+use serde::Serialize;
+use std::error::Error;
+use std::net::IpAddr;
 use std::path::PathBuf;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "httpd")]
 struct Opt {
-    #[structopt(default_value = "sqlite:data.db")]
-    connection_string: String,
+    /// Config file, `clipstash.toml` is read when it exists and no file is given
+    #[structopt(short, long, env = "CLIPSTASH_CONFIG", parse(from_os_str))]
+    config: Option<PathBuf>,
+    #[structopt(flatten)]
+    overrides: Overrides,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+// NOTE Every setting of `Config` can be overridden here, values are checked along with the rest of the config
+// ? Settings that aren't given are left out so they don't hide the ones of the lower layers
+#[derive(StructOpt, Serialize, Debug)]
+struct Overrides {
+    /// Database to connect to, e.g. `sqlite:data.db` or `postgres://localhost/clipstash`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connection_string: Option<String>,
+    /// Address the server listens on
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<IpAddr>,
+    /// Port the server listens on
+    #[structopt(short, long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    port: Option<u16>,
+    /// Directory of the page templates
     // ? short enables this argument as `-t` and long as `--template-directory`
-    #[structopt(short, long, parse(from_os_str), default_value = "templates/")]
-    template_directory: PathBuf,
+    #[structopt(short, long, parse(from_os_str))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    template_directory: Option<PathBuf>,
+    /// Directory of the files served under `/static`
+    #[structopt(long, parse(from_os_str))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    static_directory: Option<PathBuf>,
+    /// Base64 key used to sign clip access tokens, a random key is generated when omitted
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token_key: Option<String>,
+    /// Characters used to generate shortcodes
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shortcode_alphabet: Option<String>,
+    /// Length of generated shortcodes
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shortcode_length: Option<usize>,
+    /// Longest title a clip can have
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_title_length: Option<usize>,
+    /// Largest file that can be uploaded as a clip, e.g. `512KiB` or `10MiB`
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_upload_size: Option<String>,
+    /// Requests allowed for each scope, per API key on the API and per IP elsewhere, e.g. `create=30/m`
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rate_limits: Option<String>,
+    /// Header with the client's IP set by a trusted proxy, e.g. `X-Real-IP`, only set it behind a proxy that overwrites it
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ip_header: Option<String>,
+    /// How often collected hits are written to the database, e.g. `5s`
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hit_commit_interval: Option<String>,
+    /// Clips whose hits are collected before they're written, even if the commit interval hasn't passed
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hit_batch_size: Option<usize>,
+    /// Hits waiting to be collected, hits that don't fit are dropped
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hit_queue_capacity: Option<usize>,
+    /// How often expired clips are deleted, e.g. `10s`
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    maintenance_interval: Option<String>,
+    /// How long running requests get to finish once the server is asked to stop, e.g. `5s`
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shutdown_grace: Option<String>,
+    /// Format of the log lines written to stderr, `pretty` or `json`, Rocket keeps writing its own to stdout
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    log_format: Option<String>,
+    /// Most verbose level that's logged: error, warn, info, debug or trace, `RUST_LOG` takes precedence
+    #[structopt(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    log_level: Option<String>,
+}
+
+fn load_config(opt: &Opt) -> Result<Config, ConfigErr> {
+    let figment =
+        Config::figment(opt.config.as_deref())?.merge(Serialized::defaults(&opt.overrides));
+    Config::from_figment(&figment)
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Applies the migrations the database doesn't have yet, Postgres' are read from the `postgres` subdirectory
+    Migrate {
+        /// Directory of SQLite's migrations
+        #[structopt(long, default_value = "migrations", parse(from_os_str))]
+        directory: PathBuf,
+    },
+    /// Compresses the content of clips stored before compression was enabled, then exits
+    Compress,
+    /// Rebuilds the search index of every clip, then exits
+    Reindex,
+    /// Manages the keys used to access the API
+    Keys(KeysCommand),
+    /// Prints the OpenAPI document of the API, the copy in `openapi.json` must be regenerated when the API changes
+    Openapi,
+}
+
+#[derive(StructOpt, Debug)]
+enum KeysCommand {
+    /// Lists the API keys, keys are only shown once when they're created
+    List,
+    /// Creates an API key and prints it
+    Create {
+        name: String,
+        /// Comma separated scopes: read, create, update, delete and admin, which grants all of them
+        #[structopt(short, long)]
+        scopes: ApiScopes,
+        /// Expiration date, RFC 3339 timestamp or duration such as 12h or 30d
+        #[structopt(short, long)]
+        expires: Option<Expires>,
+        /// Username of the account that owns the clips created with the key
+        #[structopt(short, long)]
+        user: Option<Username>,
+    },
+    /// Revokes an API key by name
+    Revoke { name: String },
+}
+
+fn format_time(time: Option<&Time>) -> String {
+    time.map(|time| {
+        time.clone()
+            .into_inner()
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    })
+    .unwrap_or_else(|| "-".to_owned())
+}
+
+fn print_api_key(info: &ApiKeyInfo) {
+    println!(
+        "{:<24} {:<32} {:<16} {:<16} {:<16} {}",
+        info.name,
+        info.scopes.to_string(),
+        info.owner_name.as_ref().map_or("-", |name| name.as_str()),
+        format_time(Some(&info.created)),
+        format_time(info.expires.as_ref()),
+        format_time(info.last_used.as_ref())
+    );
+}
+
+// NOTE One-off commands run against the database and exit instead of launching the server
+async fn run_command(command: Command, database: AppDatabase) -> Result<(), Box<dyn Error>> {
+    let pool = database.get_pool();
+    match command {
+        Command::Migrate { directory } => {
+            database.migrate(&directory).await?;
+            println!("database is up to date");
+        }
+        Command::Compress => {
+            let compressed = service::action::compress_stored_content(pool).await?;
+            println!("compressed {compressed} rows");
+        }
+        Command::Reindex => {
+            let indexed = service::action::reindex_clips(pool).await?;
+            println!("indexed {indexed} clips");
+        }
+        Command::Keys(KeysCommand::List) => {
+            println!(
+                "{:<24} {:<32} {:<16} {:<16} {:<16} LAST USED",
+                "NAME", "SCOPES", "OWNER", "CREATED", "EXPIRES"
+            );
+            for info in service::action::list_api_keys(pool).await? {
+                print_api_key(&info);
+            }
+        }
+        Command::Keys(KeysCommand::Create {
+            name,
+            scopes,
+            expires,
+            user,
+        }) => {
+            let req = ask::NewApiKey {
+                name: name.trim().to_owned(),
+                scopes,
+                expires: expires.unwrap_or_default(),
+                owner: user,
+            };
+            let (api_key, info) = service::action::generate_api_key(req, pool).await?;
+            print_api_key(&info);
+            // * Only the key's info is listed afterwards, so this is the only chance to copy it
+            println!("api key: {}", api_key.to_base64());
+        }
+        Command::Openapi => unreachable!("handled before connecting to the database"),
+        Command::Keys(KeysCommand::Revoke { name }) => {
+            match service::action::revoke_api_key(&name, pool).await? {
+                RevocationStatus::Revoked => println!("revoked api key '{name}'"),
+                RevocationStatus::NotFound => {
+                    return Err(format!("no api key named '{name}'").into())
+                }
+            }
+        }
+    }
+    Ok(())
 }
 
 fn main() {
     dotenv().ok();
     let opt = Opt::from_args();
 
+    // ? The document is built from the routes alone, so it doesn't need the database
+    if let Some(Command::Openapi) = opt.command {
+        let spec =
+            serde_json::to_string_pretty(&openapi::spec()).expect("failed to serialize spec");
+        println!("{spec}");
+        return;
+    }
+
+    let config = match load_config(&opt) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    let subscriber = logging::subscriber(config.log_format, config.log_level, std::io::stderr);
+    tracing::subscriber::set_global_default(subscriber).expect("failed to install the logger");
+
     let rt = tokio::runtime::Runtime::new().expect("failed to spawn tokio runtime");
 
+    if let Some(command) = opt.command {
+        let res = rt.block_on(async {
+            let database = AppDatabase::new(&config.connection_string).await;
+            run_command(command, database).await
+        });
+        if let Err(e) = res {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let handle = rt.handle().clone();
-    let renderer = Renderer::new(opt.template_directory.clone());
-    let database = rt.block_on(async move { AppDatabase::new(&opt.connection_string).await });
-    // NOTE This will manage the hit counter state in a separate thread, deferring database writes
-    let hit_counter = HitCounter::new(database.get_pool().clone(), handle.clone());
-    let maintenance = Maintenance::spawn(database.get_pool().clone(), handle);
+    let renderer = Renderer::new(config.template_directory.clone());
+    let database = rt.block_on(async { AppDatabase::new(&config.connection_string).await });
+    // NOTE This will manage the hit counter state in a background task, deferring database writes
+    let hit_counter = HitCounter::new(
+        database.get_pool().clone(),
+        handle.clone(),
+        config.hit_counter(),
+    );
+    let maintenance = Maintenance::spawn(
+        database.get_pool().clone(),
+        handle,
+        config.maintenance_interval,
+    );
+    let token_ttl = chrono::Duration::hours(1);
+    // ? The key, the shortcode settings and the title limit were checked when the config was loaded
+    let token_signer = match config.token_key().expect("token key is validated") {
+        Some(key) => AccessTokenSigner::new(key, token_ttl),
+        None => AccessTokenSigner::random(token_ttl),
+    };
+    let shortcodes = config
+        .shortcodes()
+        .expect("shortcode settings are validated");
+    let title_limit = config.title_limit().expect("title limit is validated");
 
     let config = clipstash::RocketConfig {
         renderer,
         database,
         hit_counter,
         maintenance,
+        token_signer,
+        shortcodes,
+        title_limit,
+        rate_limiter: RateLimiter::new(config.rate_limits.clone()),
+        config,
     };
 
     // NOTE runs a future and blocks the thread until it completes, similar to spawning a thread
-    rt.block_on(async move {
+    let rocket = rt.block_on(async move {
         clipstash::rocket(config)
             .launch()
             .await
             .expect("failed to launch rocket server")
     });
+    // NOTE `launch` returns once a signal stopped the server and its requests were answered
+    clipstash::shutdown(&rocket);
+    tracing::info!("server stopped");
 }
@@ -0,0 +1,312 @@
+use crate::domain::clip::field::{Expires, ShortcodeGenerator, TitleLimit};
+use crate::web::{hitcounter::HitCounterSettings, logging::LogFormat, ratelimit::RateLimits};
+use figment::{
+    providers::{Env, Format, Serialized, Toml},
+    Figment,
+};
+use rocket::data::{ByteUnit, ToByteUnit};
+use serde::{Deserialize, Deserializer};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+pub const DEFAULT_FILE: &str = "clipstash.toml";
+const ENV_PREFIX: &str = "CLIPSTASH_";
+/// * Keys that can be set with `CLIPSTASH_` variables, the prefix is also used by variables that aren't part of the config
+const KEYS: &[&str] = &[
+    "connection_string",
+    "address",
+    "port",
+    "template_directory",
+    "static_directory",
+    "token_key",
+    "shortcode_alphabet",
+    "shortcode_length",
+    "max_title_length",
+    "max_upload_size",
+    "rate_limits",
+    "ip_header",
+    "hit_commit_interval",
+    "hit_batch_size",
+    "hit_queue_capacity",
+    "maintenance_interval",
+    "shutdown_grace",
+    "log_format",
+    "log_level",
+];
+// NOTE Values of environment variables are read like TOML values, so an alphabet of digits would become a number
+// ? These are kept as they were written instead
+const RAW_KEYS: &[&str] = &["shortcode_alphabet", "token_key"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigErr {
+    #[error("config file '{0}' not found")]
+    MissingFile(PathBuf),
+    #[error("{0}")]
+    Load(#[from] Box<figment::Error>),
+    #[error("invalid config:\n{}", .0.join("\n"))]
+    Invalid(Vec<String>),
+}
+
+/// * Settings of `httpd`, from lowest to highest priority they're read from:
+///     * the defaults below
+///     * `clipstash.toml`, or the file passed with `--config`
+///     * `CLIPSTASH_` environment variables, e.g. `CLIPSTASH_PORT`
+///     * the command line
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub connection_string: String,
+    pub address: IpAddr,
+    pub port: u16,
+    pub template_directory: PathBuf,
+    pub static_directory: PathBuf,
+    /// * Base64 key used to sign clip access tokens, a random key is generated when omitted
+    pub token_key: Option<String>,
+    pub shortcode_alphabet: String,
+    pub shortcode_length: usize,
+    pub max_title_length: usize,
+    pub max_upload_size: ByteUnit,
+    #[serde(deserialize_with = "parsed")]
+    pub rate_limits: RateLimits,
+    /// * Header with the client's IP set by a trusted proxy, e.g. `X-Real-IP`, the address of the connection is used without it
+    pub ip_header: Option<String>,
+    /// * How often the hits collected by the hit counter are written to the database
+    #[serde(deserialize_with = "interval")]
+    pub hit_commit_interval: Duration,
+    /// * Clips whose hits are collected before they're written, even if the interval hasn't passed
+    pub hit_batch_size: usize,
+    /// * Hits waiting to be collected, hits that don't fit are dropped
+    pub hit_queue_capacity: usize,
+    /// * How often expired clips are deleted
+    #[serde(deserialize_with = "interval")]
+    pub maintenance_interval: Duration,
+    /// * How long requests that are still running get to finish once the server is asked to stop
+    #[serde(deserialize_with = "interval")]
+    pub shutdown_grace: Duration,
+    #[serde(deserialize_with = "parsed")]
+    pub log_format: LogFormat,
+    #[serde(deserialize_with = "parsed")]
+    pub log_level: tracing::Level,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            connection_string: "sqlite:data.db".to_owned(),
+            address: Ipv4Addr::LOCALHOST.into(),
+            port: 8000,
+            template_directory: "templates/".into(),
+            static_directory: "static".into(),
+            token_key: None,
+            shortcode_alphabet: "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789"
+                .to_owned(),
+            shortcode_length: 10,
+            max_title_length: TitleLimit::DEFAULT,
+            max_upload_size: 10.mebibytes(),
+            rate_limits: "read=300/m,create=30/m,update=60/m,delete=60/m"
+                .parse()
+                .expect("default rate limits are valid"),
+            ip_header: None,
+            hit_commit_interval: Duration::from_secs(5),
+            hit_batch_size: 500,
+            hit_queue_capacity: 10_000,
+            maintenance_interval: Duration::from_secs(10),
+            shutdown_grace: Duration::from_secs(5),
+            log_format: LogFormat::Pretty,
+            log_level: tracing::Level::INFO,
+        }
+    }
+}
+
+impl Config {
+    /// * Layers the config file and the environment, the command line is merged on top by the caller
+    pub fn figment(file: Option<&Path>) -> Result<Figment, ConfigErr> {
+        let file = match file {
+            Some(file) if !file.is_file() => return Err(ConfigErr::MissingFile(file.to_owned())),
+            Some(file) => file,
+            // ? The default file is optional, the defaults are used without it
+            None => Path::new(DEFAULT_FILE),
+        };
+        let raw = Env::prefixed(ENV_PREFIX)
+            .only(RAW_KEYS)
+            .iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect::<BTreeMap<_, _>>();
+        Ok(Figment::new()
+            .merge(Toml::file(file))
+            .merge(Env::prefixed(ENV_PREFIX).only(KEYS).ignore(RAW_KEYS))
+            .merge(Serialized::defaults(raw)))
+    }
+
+    pub fn from_figment(figment: &Figment) -> Result<Self, ConfigErr> {
+        let config: Self = figment.extract().map_err(Box::new)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// * Checks the settings that can only be checked together or against the system, every problem is reported at once
+    pub fn validate(&self) -> Result<(), ConfigErr> {
+        let mut errors = vec![];
+        if let Err(e) = self.shortcodes() {
+            errors.push(format!("shortcode_alphabet, shortcode_length: {e}"));
+        }
+        if let Err(e) = self.title_limit() {
+            errors.push(format!("max_title_length: {e}"));
+        }
+        for (key, value) in [
+            ("hit_batch_size", self.hit_batch_size),
+            ("hit_queue_capacity", self.hit_queue_capacity),
+        ] {
+            if value == 0 {
+                errors.push(format!("{key}: must be at least 1"));
+            }
+        }
+        if self.max_upload_size == 0 {
+            errors.push("max_upload_size: must be more than 0 bytes".to_owned());
+        }
+        for (key, interval) in [
+            ("hit_commit_interval", self.hit_commit_interval),
+            ("maintenance_interval", self.maintenance_interval),
+        ] {
+            if interval.is_zero() {
+                errors.push(format!("{key}: must be longer than 0s"));
+            }
+        }
+        for (key, dir) in [
+            ("template_directory", &self.template_directory),
+            ("static_directory", &self.static_directory),
+        ] {
+            if !dir.is_dir() {
+                errors.push(format!("{key}: '{}' is not a directory", dir.display()));
+            }
+        }
+        if let Err(e) = self.token_key() {
+            errors.push(format!("token_key: {e}"));
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(ConfigErr::Invalid(errors)),
+        }
+    }
+
+    pub fn shortcodes(&self) -> Result<ShortcodeGenerator, crate::ClipErr> {
+        ShortcodeGenerator::new(&self.shortcode_alphabet, self.shortcode_length)
+    }
+
+    pub fn title_limit(&self) -> Result<TitleLimit, crate::ClipErr> {
+        TitleLimit::new(self.max_title_length)
+    }
+
+    pub fn hit_counter(&self) -> HitCounterSettings {
+        HitCounterSettings {
+            commit_interval: self.hit_commit_interval,
+            batch_size: self.hit_batch_size,
+            queue_capacity: self.hit_queue_capacity,
+        }
+    }
+
+    pub fn token_key(&self) -> Result<Option<Vec<u8>>, String> {
+        match &self.token_key {
+            None => Ok(None),
+            Some(key) => match base64::decode(key) {
+                Ok(key) if key.is_empty() => Err("must not be empty".to_owned()),
+                Ok(key) => Ok(Some(key)),
+                Err(e) => Err(format!("must be valid base64, {e}")),
+            },
+        }
+    }
+}
+
+// NOTE Settings with their own syntax are read as strings and parsed like they are on the command line
+fn parsed<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = String::deserialize(deserializer)?;
+    value.parse().map_err(serde::de::Error::custom)
+}
+
+/// * Intervals are durations such as `5s` or `10m`, like the relative expiration of clips
+fn interval<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    Expires::parse_relative(&value)
+        .and_then(|interval| interval.to_std().ok())
+        .ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "invalid interval '{value}', expected a duration such as 30s or 10m"
+            ))
+        })
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn load(toml: &str) -> Result<Config, ConfigErr> {
+        Config::from_figment(&Figment::new().merge(Toml::string(toml)))
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        let config = load("").unwrap();
+        assert_eq!(config.port, 8000);
+        assert_eq!(config.hit_commit_interval, Duration::from_secs(5));
+        assert_eq!(config.max_title_length, 200);
+    }
+
+    #[test]
+    fn later_layers_override_earlier_ones() {
+        let figment = Figment::new()
+            .merge(Toml::string(
+                r#"
+                port = 9000
+                shortcode_length = 8
+                maintenance_interval = "1m"
+                rate_limits = "read=10/s"
+                "#,
+            ))
+            .merge(Serialized::defaults(serde_json::json!({ "port": 9001 })));
+        let config = Config::from_figment(&figment).unwrap();
+        assert_eq!(config.port, 9001);
+        assert_eq!(config.shortcode_length, 8);
+        assert_eq!(config.maintenance_interval, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn errors_name_the_setting() {
+        let err = load("prot = 9000").unwrap_err().to_string();
+        assert!(err.contains("prot"), "{err}");
+
+        let err = load(r#"hit_commit_interval = "soon""#)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("hit_commit_interval"), "{err}");
+
+        let err = load(
+            r#"
+            shortcode_length = 2
+            max_title_length = 0
+            static_directory = "missing/"
+            token_key = "not base64!"
+            "#,
+        )
+        .unwrap_err()
+        .to_string();
+        for key in [
+            "shortcode_length",
+            "max_title_length",
+            "static_directory",
+            "token_key",
+        ] {
+            assert!(err.contains(key), "{err}");
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use std::io::{Read, Write};
+
+/// * Content smaller than this is stored as is, compressing it wouldn't save much
+pub const COMPRESSION_THRESHOLD: usize = 4 * 1024;
+pub const DEFLATE: &str = "deflate";
+
+/// * Content as it's stored in the database
+/// * Compressed rows keep an empty `content` and the compressed bytes in `content_compressed`
+pub struct StoredContent {
+    pub content: String,
+    pub encoding: Option<String>,
+    pub compressed: Option<Vec<u8>>,
+}
+
+impl StoredContent {
+    pub fn plain(content: String) -> Self {
+        Self {
+            content,
+            encoding: None,
+            compressed: None,
+        }
+    }
+}
+
+pub fn compress(content: String) -> StoredContent {
+    if content.len() < COMPRESSION_THRESHOLD {
+        return StoredContent::plain(content);
+    }
+
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    let compressed = encoder
+        .write_all(content.as_bytes())
+        .and_then(|_| encoder.finish());
+    match compressed {
+        // ? Content that doesn't shrink, such as random data, is kept uncompressed
+        Ok(compressed) if compressed.len() < content.len() => StoredContent {
+            content: String::new(),
+            encoding: Some(DEFLATE.to_owned()),
+            compressed: Some(compressed),
+        },
+        _ => StoredContent::plain(content),
+    }
+}
+
+pub fn decompress(
+    content: String,
+    encoding: Option<&str>,
+    compressed: Option<Vec<u8>>,
+) -> Result<String, String> {
+    match (encoding, compressed) {
+        (None, _) => Ok(content),
+        (Some(DEFLATE), Some(compressed)) => {
+            let mut content = String::new();
+            DeflateDecoder::new(compressed.as_slice())
+                .read_to_string(&mut content)
+                .map_err(|e| e.to_string())?;
+            Ok(content)
+        }
+        (Some(DEFLATE), None) => Err("compressed content is missing".to_owned()),
+        (Some(encoding), _) => Err(format!("unknown content encoding {encoding}")),
+    }
+}
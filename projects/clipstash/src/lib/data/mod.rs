@@ -1,3 +1,4 @@
+pub mod compression;
 pub mod model;
 pub mod query;
 
@@ -5,7 +6,7 @@ use std::str::FromStr;
 
 use derive_more::{Display, From};
 use serde::{Deserialize, Serialize};
-use sqlx::Sqlite;
+use sqlx::{Postgres, Sqlite};
 use uuid::Uuid;
 
 #[derive(Debug, thiserror::Error)]
@@ -14,37 +15,146 @@ pub enum DataErr {
     Database(#[from] sqlx::Error),
 }
 
-pub type AppDatabase = Database<Sqlite>;
+impl DataErr {
+    /// * Whether a UNIQUE constraint failed, e.g. when a shortcode is already taken
+    pub fn is_unique_violation(&self) -> bool {
+        // NOTE 2067 is SQLITE_CONSTRAINT_UNIQUE, 23505 is Postgres' unique_violation
+        matches!(self, Self::Database(sqlx::Error::Database(e)) if matches!(e.code().as_deref(), Some("2067" | "23505")))
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Self::Database(sqlx::Error::RowNotFound))
+    }
+}
+
+pub type AppDatabase = Database;
+
+/// * Storage backends, picked from the scheme of the connection string
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Sqlite,
+    Postgres,
+}
+
+impl Backend {
+    pub fn from_connection_str(connection_str: &str) -> Self {
+        match connection_str.starts_with("postgres:") || connection_str.starts_with("postgresql:") {
+            true => Self::Postgres,
+            false => Self::Sqlite,
+        }
+    }
+}
+
 // NOTE DatabasePool is a pool of connections
-pub type DatabasePool = sqlx::sqlite::SqlitePool;
-pub type Transaction<'t> = sqlx::Transaction<'t, Sqlite>;
-pub type AppDatabaseRow = sqlx::sqlite::SqliteRow;
-pub type AppQueryResult = sqlx::sqlite::SqliteQueryResult;
+// NOTE Cloning a pool is cheap, the clones share the same connections
+#[derive(Clone, Debug)]
+pub enum DatabasePool {
+    Sqlite(sqlx::SqlitePool),
+    Postgres(sqlx::PgPool),
+}
+
+impl DatabasePool {
+    pub fn backend(&self) -> Backend {
+        match self {
+            Self::Sqlite(_) => Backend::Sqlite,
+            Self::Postgres(_) => Backend::Postgres,
+        }
+    }
+
+    /// * Connections that are open, idle or not
+    pub fn size(&self) -> u32 {
+        match self {
+            Self::Sqlite(pool) => pool.size(),
+            Self::Postgres(pool) => pool.size(),
+        }
+    }
+
+    pub fn num_idle(&self) -> usize {
+        match self {
+            Self::Sqlite(pool) => pool.num_idle(),
+            Self::Postgres(pool) => pool.num_idle(),
+        }
+    }
+
+    pub async fn close(&self) {
+        match self {
+            Self::Sqlite(pool) => pool.close().await,
+            Self::Postgres(pool) => pool.close().await,
+        }
+    }
 
-pub struct Database<D: sqlx::Database>(sqlx::Pool<D>);
+    pub async fn begin(&self) -> Result<Transaction<'static>, sqlx::Error> {
+        Ok(match self {
+            Self::Sqlite(pool) => Transaction::Sqlite(pool.begin().await?),
+            Self::Postgres(pool) => Transaction::Postgres(Box::new(pool.begin().await?)),
+        })
+    }
+}
 
-impl Database<Sqlite> {
+// ? Postgres' transactions are much larger than SQLite's, so they're boxed to keep the enum small
+pub enum Transaction<'t> {
+    Sqlite(sqlx::Transaction<'t, Sqlite>),
+    Postgres(Box<sqlx::Transaction<'t, Postgres>>),
+}
+
+impl Transaction<'_> {
+    pub async fn commit(self) -> Result<(), sqlx::Error> {
+        match self {
+            Self::Sqlite(transaction) => transaction.commit().await,
+            Self::Postgres(transaction) => transaction.commit().await,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Database(DatabasePool);
+
+impl Database {
     pub async fn new(connection_str: &str) -> Self {
-        let pool = sqlx::sqlite::SqlitePoolOptions::new()
-            .connect(connection_str)
-            .await
-            .unwrap_or_else(|e| Self::handle_connection_error(e));
-        Self(pool)
+        let pool = match Backend::from_connection_str(connection_str) {
+            Backend::Sqlite => sqlx::sqlite::SqlitePoolOptions::new()
+                .connect(connection_str)
+                .await
+                .map(DatabasePool::Sqlite),
+            Backend::Postgres => sqlx::postgres::PgPoolOptions::new()
+                .connect(connection_str)
+                .await
+                .map(DatabasePool::Postgres),
+        };
+        Self(pool.unwrap_or_else(|e| Self::handle_connection_error(e)))
     }
 
     pub fn get_pool(&self) -> &DatabasePool {
         &self.0
     }
 
+    /// * Runs the migrations of the database's backend, `dir` is the directory of SQLite's migrations
+    /// * Postgres' migrations are kept in its `postgres` subdirectory, the migrator skips subdirectories
+    pub async fn migrate(&self, dir: &std::path::Path) -> Result<(), sqlx::migrate::MigrateError> {
+        use sqlx::migrate::Migrator;
+
+        let migrations = match self.0.backend() {
+            Backend::Sqlite => dir.to_path_buf(),
+            Backend::Postgres => dir.join("postgres"),
+        };
+        let migrator = Migrator::new(migrations).await?;
+        match &self.0 {
+            DatabasePool::Sqlite(pool) => migrator.run(pool).await,
+            DatabasePool::Postgres(pool) => migrator.run(pool).await,
+        }
+    }
+
     // NOTE `-> !` is the never type, indicates that the function panics
     fn handle_connection_error(e: sqlx::Error) -> ! {
-        eprintln!("Error: {}", e);
-        eprintln!("\nIf the database has not yet been created, run:\n $ sqlx database setup\n");
+        tracing::error!(
+            error = %e,
+            "database connection failed, if the database has not yet been created run `sqlx database setup`"
+        );
         panic!("database connection failed")
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize, From, Display)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, From, Display)]
 pub struct DbId(Uuid);
 impl DbId {
     pub fn new() -> Self {
@@ -80,16 +190,125 @@ pub mod test {
     use crate::data::*;
     use tokio::runtime::Handle;
 
-    // NOTE Creating an in-memory database for testing
-    pub fn new_db(handle: &Handle) -> AppDatabase {
-        use sqlx::migrate::Migrator;
-        use std::path::Path;
+    /// * Connection string of a Postgres database
+    /// * When it's set the data and service tests run against both backends, the others run against Postgres instead of SQLite
+    pub const POSTGRES_URL_VAR: &str = "CLIPSTASH_TEST_POSTGRES";
+
+    /// * A database used by a single test, its Postgres schema is dropped along with it
+    pub struct TestDb {
+        db: AppDatabase,
+        // ? Connection string and name of the Postgres schema
+        schema: Option<(String, String)>,
+    }
+
+    impl std::ops::Deref for TestDb {
+        type Target = AppDatabase;
+
+        fn deref(&self) -> &Self::Target {
+            &self.db
+        }
+    }
+
+    impl Drop for TestDb {
+        fn drop(&mut self) {
+            use sqlx::{Connection, Executor};
+
+            let Some((url, schema)) = self.schema.take() else {
+                return;
+            };
+            let drop_schema = format!("DROP SCHEMA {schema} CASCADE");
+            // NOTE Tests drop their database both inside and outside of a runtime, so a thread of its own drops the schema
+            let dropped = std::thread::spawn(move || {
+                crate::test::async_runtime().block_on(async move {
+                    let mut conn = sqlx::PgConnection::connect(&url).await?;
+                    conn.execute(drop_schema.as_str()).await
+                })
+            })
+            .join();
+            // ? Panicking while a failed test unwinds would abort the whole run
+            if !matches!(dropped, Ok(Ok(_))) {
+                eprintln!("the {schema} test schema couldn't be dropped");
+            }
+        }
+    }
+
+    // NOTE Creating an in-memory database for testing, or a Postgres one when it's configured
+    pub fn new_db(handle: &Handle) -> TestDb {
+        match std::env::var(POSTGRES_URL_VAR) {
+            Ok(url) => new_postgres_db(handle, &url),
+            Err(_) => new_sqlite_db(handle),
+        }
+    }
+
+    /// * A database of every backend that can be tested, SQLite always and Postgres when it's configured
+    pub fn test_dbs(handle: &Handle) -> Vec<TestDb> {
+        let mut dbs = vec![new_sqlite_db(handle)];
+        if let Ok(url) = std::env::var(POSTGRES_URL_VAR) {
+            dbs.push(new_postgres_db(handle, &url));
+        }
+        dbs
+    }
+
+    fn new_sqlite_db(handle: &Handle) -> TestDb {
+        handle.block_on(async move {
+            // NOTE Every connection to `:memory:` opens a separate database, so the pool is pinned to a single connection
+            let pool = sqlx::sqlite::SqlitePoolOptions::new()
+                .max_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+                .connect(":memory:")
+                .await
+                .unwrap();
+            migrated(DatabasePool::Sqlite(pool), None).await
+        })
+    }
+
+    /// * Every test gets its own schema, so tests running at the same time don't see each other's rows
+    fn new_postgres_db(handle: &Handle, url: &str) -> TestDb {
+        use sqlx::Executor;
+
         handle.block_on(async move {
-            let db = Database::new(":memory:").await;
-            let migrator = Migrator::new(Path::new("./migrations")).await.unwrap();
-            let pool = db.get_pool();
-            migrator.run(pool).await.unwrap();
-            db
+            let schema = format!("test_{}", Uuid::new_v4().simple());
+            let pool = sqlx::postgres::PgPoolOptions::new()
+                .max_connections(4)
+                .after_connect({
+                    let schema = schema.clone();
+                    move |conn| {
+                        let search_path = format!("SET search_path TO {schema}");
+                        Box::pin(
+                            async move { conn.execute(search_path.as_str()).await.map(|_| ()) },
+                        )
+                    }
+                })
+                .connect(url)
+                .await
+                .unwrap();
+            pool.execute(format!("CREATE SCHEMA {schema}").as_str())
+                .await
+                .unwrap();
+            migrated(DatabasePool::Postgres(pool), Some((url.to_owned(), schema))).await
+        })
+    }
+
+    // ? The database is wrapped before it's migrated, so the schema is dropped even if the migrations fail
+    async fn migrated(pool: DatabasePool, schema: Option<(String, String)>) -> TestDb {
+        let db = TestDb {
+            db: Database(pool),
+            schema,
+        };
+        db.migrate(std::path::Path::new("./migrations"))
+            .await
+            .unwrap();
+        db
+    }
+
+    /// * Runs a statement that's valid on every backend, for rows the queries can't set up
+    pub async fn execute(sql: &str, pool: &DatabasePool) -> Result<u64, sqlx::Error> {
+        use sqlx::Executor;
+
+        Ok(match pool {
+            DatabasePool::Sqlite(pool) => pool.execute(sql).await?.rows_affected(),
+            DatabasePool::Postgres(pool) => pool.execute(sql).await?.rows_affected(),
         })
     }
 }
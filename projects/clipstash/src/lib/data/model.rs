@@ -1,6 +1,15 @@
-use crate::data::DbId;
+use crate::data::{compression, DbId};
+use crate::domain::api_key::{ApiKeyInfoErr, ApiScopes};
+use crate::domain::{
+    clip::field,
+    listing,
+    user::{self, UserErr},
+    UserId, Username,
+};
+use crate::service::token::SessionToken;
+use crate::web;
 use crate::{ClipErr, Shortcode, Time};
-use chrono::{NaiveDateTime, Utc};
+use chrono::{Duration, NaiveDateTime, Utc};
 use std::convert::TryFrom;
 use std::str::FromStr;
 
@@ -16,23 +25,87 @@ pub struct Clip {
     pub(in crate::data) expires: Option<NaiveDateTime>,
     pub(in crate::data) password: Option<String>,
     pub(in crate::data) hits: i64,
+    pub(in crate::data) edit_secret: Option<String>,
+    pub(in crate::data) max_views: Option<i64>,
+    pub(in crate::data) views: i64,
+    pub(in crate::data) language: Option<String>,
+    pub(in crate::data) file_name: Option<String>,
+    pub(in crate::data) mime_type: Option<String>,
+    pub(in crate::data) file_size: Option<i64>,
+    pub(in crate::data) content_encoding: Option<String>,
+    pub(in crate::data) content_compressed: Option<Vec<u8>>,
+    pub(in crate::data) encrypted: bool,
+    pub(in crate::data) owner: Option<String>,
 }
 
 // NOTE implementing a conversion from the database Clip to the domain Clip
 impl TryFrom<Clip> for crate::domain::Clip {
     type Error = ClipErr;
     fn try_from(row: Clip) -> Result<Self, Self::Error> {
-        use crate::domain::clip::field;
-
+        let content = compression::decompress(
+            row.content,
+            row.content_encoding.as_deref(),
+            row.content_compressed,
+        )
+        .map_err(ClipErr::ContentEncoding)?;
         Ok(Self {
             clip_id: field::ClipId::new(DbId::from_str(row.clip_id.as_str())?),
             shortcode: field::Shortcode::from(row.shortcode),
-            content: field::Content::new(row.content.as_str())?,
-            title: field::Title::new(row.title)?,
+            content: field::Content::new(content.as_str())?,
+            encrypted: field::Encrypted::new(row.encrypted),
+            title: field::Title::from(row.title),
             posted: field::Posted::new(Time::from_naive_utc(row.posted)),
             expires: field::Expires::new(row.expires.map(Time::from_naive_utc)),
             password: field::Password::new(row.password)?,
             hits: field::Hits::new(u64::try_from(row.hits)?),
+            edit_secret: field::EditSecret::new(row.edit_secret),
+            max_views: field::MaxViews::new(row.max_views.map(u32::try_from).transpose()?)?,
+            views: field::Hits::new(u64::try_from(row.views)?),
+            language: field::Language::new(row.language)?,
+            attachment: match (row.file_name, row.mime_type, row.file_size) {
+                (Some(name), Some(mime_type), Some(size)) => Some(field::Attachment::new(
+                    &name,
+                    &mime_type,
+                    u64::try_from(size)?,
+                )?),
+                _ => None,
+            },
+            owner: row
+                .owner
+                .as_deref()
+                .map(DbId::from_str)
+                .transpose()?
+                .map(UserId::from),
+        })
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct Revision {
+    pub(in crate::data) revision: i64,
+    pub(in crate::data) content: String,
+    pub(in crate::data) title: Option<String>,
+    pub(in crate::data) expires: Option<NaiveDateTime>,
+    pub(in crate::data) replaced: NaiveDateTime,
+    pub(in crate::data) content_encoding: Option<String>,
+    pub(in crate::data) content_compressed: Option<Vec<u8>>,
+}
+
+impl TryFrom<Revision> for crate::domain::Revision {
+    type Error = ClipErr;
+    fn try_from(row: Revision) -> Result<Self, Self::Error> {
+        let content = compression::decompress(
+            row.content,
+            row.content_encoding.as_deref(),
+            row.content_compressed,
+        )
+        .map_err(ClipErr::ContentEncoding)?;
+        Ok(Self {
+            revision: u32::try_from(row.revision)?,
+            content: field::Content::new(content.as_str())?,
+            title: field::Title::from(row.title),
+            expires: field::Expires::new(row.expires.map(Time::from_naive_utc)),
+            replaced: Some(Time::from_naive_utc(row.replaced)),
         })
     }
 }
@@ -65,14 +138,35 @@ impl From<String> for GetClip {
     }
 }
 
+#[derive(Clone)]
 pub struct NewClip {
     pub(in crate::data) clip_id: String,
     pub(in crate::data) shortcode: String,
     pub(in crate::data) content: String,
+    pub(in crate::data) content_encoding: Option<String>,
+    pub(in crate::data) content_compressed: Option<Vec<u8>>,
     pub(in crate::data) title: Option<String>,
     pub(in crate::data) posted: i64,
     pub(in crate::data) expires: Option<i64>,
     pub(in crate::data) password: Option<String>,
+    pub(in crate::data) edit_secret: Option<String>,
+    pub(in crate::data) max_views: Option<i64>,
+    pub(in crate::data) language: Option<String>,
+    pub(in crate::data) file_name: Option<String>,
+    pub(in crate::data) mime_type: Option<String>,
+    pub(in crate::data) file_data: Option<Vec<u8>>,
+    pub(in crate::data) encrypted: bool,
+    pub(in crate::data) owner: Option<String>,
+    pub(in crate::data) search: SearchIndex,
+}
+
+impl NewClip {
+    pub fn with_shortcode(self, shortcode: Shortcode) -> Self {
+        Self {
+            shortcode: shortcode.into(),
+            ..self
+        }
+    }
 }
 
 impl From<ask::NewClip> for NewClip {
@@ -83,18 +177,42 @@ impl From<ask::NewClip> for NewClip {
             title,
             expires,
             password,
+            edit_secret,
+            max_views,
+            language,
+            shortcode,
+            file,
+            encrypted,
+            owner,
             // ? Would be needed if `req` had more fields
             // ..
         } = req;
+        let search = SearchIndex::new(
+            &title,
+            &content,
+            password.has_password() || encrypted.is_set() || max_views.is_limited(),
+        );
+        let stored = compression::compress(content.into_inner());
 
         Self {
             clip_id: DbId::new().into(),
-            content: content.into_inner(),
+            content: stored.content,
+            content_encoding: stored.encoding,
+            content_compressed: stored.compressed,
             title: title.into_inner(),
             expires: expires.into_inner().map(|time| time.timestamp()),
             password: password.into_inner(),
-            shortcode: Shortcode::default().into(),
+            edit_secret: edit_secret.into_inner(),
+            max_views: max_views.into_inner().map(i64::from),
+            language: language.into_inner(),
+            file_name: file.attachment().map(|file| file.name().to_owned()),
+            mime_type: file.attachment().map(|file| file.mime_type().to_owned()),
+            file_data: file.into_inner().map(|(_, data)| data),
+            encrypted: encrypted.into_inner(),
+            owner: owner.map(String::from),
+            shortcode: shortcode.into_inner().unwrap_or_default().into(),
             posted: Utc::now().timestamp(),
+            search,
         }
     }
 }
@@ -102,27 +220,201 @@ impl From<ask::NewClip> for NewClip {
 pub struct UpdateClip {
     pub(in crate::data) shortcode: String,
     pub(in crate::data) content: String,
+    pub(in crate::data) content_encoding: Option<String>,
+    pub(in crate::data) content_compressed: Option<Vec<u8>>,
     pub(in crate::data) title: Option<String>,
     pub(in crate::data) expires: Option<i64>,
     pub(in crate::data) password: Option<String>,
+    pub(in crate::data) language: Option<String>,
+    pub(in crate::data) search: SearchIndex,
 }
 
-impl From<ask::UpdateClip> for UpdateClip {
-    fn from(req: ask::UpdateClip) -> Self {
+impl UpdateClip {
+    /// * `max_views` is the stored limit of the clip, it can't be updated but it keeps the content out of the index
+    pub fn new(req: ask::UpdateClip, max_views: &field::MaxViews) -> Self {
         let ask::UpdateClip {
             shortcode,
             content,
             title,
             expires,
             password,
+            language,
+            ..
         } = req;
+        // NOTE Encrypted clips can't be updated, so they don't need to be hidden here
+        let search = SearchIndex::new(
+            &title,
+            &content,
+            password.has_password() || max_views.is_limited(),
+        );
+        let stored = compression::compress(content.into_inner());
 
         Self {
             shortcode: shortcode.into(),
-            content: content.into_inner(),
+            content: stored.content,
+            content_encoding: stored.encoding,
+            content_compressed: stored.compressed,
             title: title.into_inner(),
             expires: expires.into_inner().map(|time| time.timestamp()),
             password: password.into_inner(),
+            language: language.into_inner(),
+            search,
+        }
+    }
+}
+
+/// * Row of the full text index, it's built from the uncompressed content
+#[derive(Clone, Debug)]
+pub struct SearchIndex {
+    pub(in crate::data) title: String,
+    pub(in crate::data) content: String,
+}
+
+impl SearchIndex {
+    /// * Only the title of hidden clips is indexed, otherwise searching would reveal their content
+    pub fn new(title: &field::Title, content: &field::Content, hidden: bool) -> Self {
+        Self {
+            title: title.clone().into_inner().unwrap_or_default(),
+            content: match hidden {
+                true => String::new(),
+                false => content.as_str().to_owned(),
+            },
+        }
+    }
+}
+
+pub struct ListClips {
+    pub(in crate::data) search: Option<String>,
+    pub(in crate::data) title: Option<String>,
+    pub(in crate::data) posted_after: Option<i64>,
+    pub(in crate::data) order: String,
+    pub(in crate::data) cursor_key: Option<i64>,
+    pub(in crate::data) cursor_id: Option<String>,
+    pub(in crate::data) limit: i64,
+}
+
+impl ListClips {
+    /// * `limit` is passed separately since one more clip than requested is fetched to know if there's a next page
+    pub fn new(req: ask::ListClips, limit: u32) -> Self {
+        let (cursor_key, cursor_id) = match req.cursor {
+            Some(cursor) => (Some(cursor.sort_key), Some(cursor.clip_id)),
+            None => (None, None),
+        };
+        Self {
+            search: req.q.as_deref().and_then(listing::search_terms),
+            title: req.title.filter(|title| !title.trim().is_empty()),
+            posted_after: req.posted_after.map(|time| time.timestamp()),
+            order: req.order.to_string(),
+            cursor_key,
+            cursor_id,
+            limit: i64::from(limit),
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ApiKey {
+    pub(in crate::data) name: String,
+    pub(in crate::data) created: NaiveDateTime,
+    pub(in crate::data) expires: Option<NaiveDateTime>,
+    pub(in crate::data) last_used: Option<NaiveDateTime>,
+    pub(in crate::data) scopes: String,
+    pub(in crate::data) owner: Option<String>,
+    pub(in crate::data) owner_name: Option<String>,
+}
+
+impl TryFrom<ApiKey> for crate::domain::ApiKeyInfo {
+    type Error = ApiKeyInfoErr;
+    fn try_from(row: ApiKey) -> Result<Self, Self::Error> {
+        Ok(Self {
+            name: row.name,
+            created: Time::from_naive_utc(row.created),
+            expires: row.expires.map(Time::from_naive_utc),
+            last_used: row.last_used.map(Time::from_naive_utc),
+            scopes: ApiScopes::from_str(&row.scopes)?,
+            owner: row.owner.as_deref().map(UserId::from_str).transpose()?,
+            owner_name: row.owner_name.as_deref().map(Username::new).transpose()?,
+        })
+    }
+}
+
+pub struct NewApiKey {
+    pub(in crate::data) api_key: Vec<u8>,
+    pub(in crate::data) name: String,
+    pub(in crate::data) created: i64,
+    pub(in crate::data) expires: Option<i64>,
+    pub(in crate::data) scopes: String,
+    pub(in crate::data) owner: Option<String>,
+}
+
+impl NewApiKey {
+    /// * The owner is looked up by the service, the request only names them
+    pub fn new(api_key: web::api::ApiKey, req: ask::NewApiKey, owner: Option<UserId>) -> Self {
+        Self {
+            api_key: api_key.into_inner(),
+            name: req.name,
+            created: Utc::now().timestamp(),
+            expires: req.expires.into_inner().map(|time| time.timestamp()),
+            scopes: req.scopes.to_string(),
+            owner: owner.map(String::from),
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct User {
+    pub(in crate::data) user_id: String,
+    pub(in crate::data) username: String,
+    pub(in crate::data) password: String,
+    pub(in crate::data) created: NaiveDateTime,
+}
+
+impl TryFrom<User> for crate::domain::User {
+    type Error = UserErr;
+    fn try_from(row: User) -> Result<Self, Self::Error> {
+        Ok(Self {
+            user_id: UserId::from_str(&row.user_id)?,
+            username: Username::new(&row.username)?,
+            created: Time::from_naive_utc(row.created),
+            password: field::Password::new(row.password)?,
+        })
+    }
+}
+
+pub struct NewUser {
+    pub(in crate::data) user_id: String,
+    pub(in crate::data) username: String,
+    pub(in crate::data) password: String,
+    pub(in crate::data) created: i64,
+}
+
+impl NewUser {
+    /// * `password` must already be hashed
+    pub fn new(username: Username, password: field::Password) -> Self {
+        Self {
+            user_id: UserId::new().into(),
+            username: username.into_inner(),
+            password: password.into_inner().unwrap_or_default(),
+            created: Utc::now().timestamp(),
+        }
+    }
+}
+
+pub struct NewSession {
+    pub(in crate::data) session_hash: Vec<u8>,
+    pub(in crate::data) user_id: String,
+    pub(in crate::data) created: i64,
+    pub(in crate::data) expires: i64,
+}
+
+impl NewSession {
+    pub fn new(session: &SessionToken, user: &User) -> Self {
+        let created = Utc::now();
+        Self {
+            session_hash: session.hash(),
+            user_id: user.user_id.clone(),
+            created: created.timestamp(),
+            expires: (created + Duration::days(user::SESSION_TTL_DAYS)).timestamp(),
         }
     }
 }
@@ -0,0 +1,454 @@
+// NOTE Every query is written once per backend, the functions of this module run the one of the pool's backend
+// ? SQLite's queries are checked against `data.db` at compile time, Postgres' are covered by running the tests against it
+// ? Each function runs in its own span, without its arguments since they may hold clip content and keys
+mod postgres;
+mod sqlite;
+
+use super::model;
+use crate::{
+    data::{compression, DataErr, DatabasePool, Transaction},
+    Shortcode,
+};
+
+// NOTE ModResult is a type alias for this module's Result type
+type ModResult<T> = std::result::Result<T, DataErr>;
+
+const COMPRESSION_BATCH_SIZE: i64 = 100;
+/// * Each clip binds two values, this keeps the statements well under SQLite's limit of 32766
+const HIT_COUNT_BATCH_SIZE: usize = 1000;
+
+/// * Shortcode and row of the full text index of a stored clip, used to rebuild the index
+fn search_index(row: model::Clip) -> (String, model::SearchIndex) {
+    let hidden = row.password.is_some() || row.encrypted || row.max_views.is_some();
+    // ? Content that can't be decoded is left out of the index instead of failing the whole batch
+    let content = match hidden {
+        true => String::new(),
+        false => compression::decompress(
+            row.content,
+            row.content_encoding.as_deref(),
+            row.content_compressed,
+        )
+        .unwrap_or_default(),
+    };
+    let search = model::SearchIndex {
+        title: row.title.unwrap_or_default(),
+        content,
+    };
+    (row.shortcode, search)
+}
+
+// NOTE Calls the query of the pool's backend, the pool is passed as the last argument like the queries expect
+macro_rules! dispatch {
+    ($pool:expr, $query:ident($($arg:expr),*)) => {
+        match $pool {
+            DatabasePool::Sqlite(pool) => sqlite::$query($($arg,)* pool).await,
+            DatabasePool::Postgres(pool) => postgres::$query($($arg,)* pool).await,
+        }
+    };
+}
+
+/// * Adds the hits of many clips within the transaction, a statement per batch of clips
+/// * Clips that were deleted since their hits were counted are skipped, the number of clips updated is returned
+#[tracing::instrument(skip_all, fields(clips = hits.len()))]
+pub async fn increase_hit_counts(
+    hits: &[(Shortcode, u32)],
+    transaction: &mut Transaction<'_>,
+) -> ModResult<u64> {
+    let mut updated = 0;
+    for batch in hits.chunks(HIT_COUNT_BATCH_SIZE) {
+        updated += match transaction {
+            Transaction::Sqlite(transaction) => {
+                sqlite::increase_hit_counts(batch, transaction).await?
+            }
+            Transaction::Postgres(transaction) => {
+                postgres::increase_hit_counts(batch, transaction).await?
+            }
+        };
+    }
+    Ok(updated)
+}
+
+// NOTE M accepts any type that implements the Into trait for the GetClip struct
+#[tracing::instrument(skip_all)]
+pub async fn get_clip<M: Into<model::GetClip>>(
+    model: M,
+    pool: &DatabasePool,
+) -> ModResult<model::Clip> {
+    let model: model::GetClip = model.into();
+    dispatch!(pool, get_clip(model))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn new_clip<M: Into<model::NewClip>>(
+    model: M,
+    pool: &DatabasePool,
+) -> ModResult<model::Clip> {
+    let model: model::NewClip = model.into();
+    dispatch!(pool, new_clip(model))
+}
+
+/// * The previous version of the clip is archived as a revision before it's overwritten
+#[tracing::instrument(skip_all)]
+pub async fn update_clip(model: model::UpdateClip, pool: &DatabasePool) -> ModResult<model::Clip> {
+    dispatch!(pool, update_clip(model))
+}
+
+/// * Clips that are still available, newest first by default and paginated with a cursor
+/// * The cursor is the sort key and ID of the last clip of the previous page, so pages don't shift when clips are added
+#[tracing::instrument(skip_all)]
+pub async fn list_clips(
+    model: model::ListClips,
+    pool: &DatabasePool,
+) -> ModResult<Vec<model::Clip>> {
+    dispatch!(pool, list_clips(model))
+}
+
+/// * Every clip of a user that's still available, newest first
+#[tracing::instrument(skip_all)]
+pub async fn list_user_clips(user_id: &str, pool: &DatabasePool) -> ModResult<Vec<model::Clip>> {
+    dispatch!(pool, list_user_clips(user_id))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_revisions(
+    shortcode: &Shortcode,
+    pool: &DatabasePool,
+) -> ModResult<Vec<model::Revision>> {
+    dispatch!(pool, get_revisions(shortcode))
+}
+
+/// * Counts a view of a clip with limited views, the clip is deleted along with its last allowed view
+/// * Returns `false` when no views are left, the conditional UPDATE guarantees that concurrent readers can't consume the same view
+#[tracing::instrument(skip_all)]
+pub async fn consume_view(shortcode: &Shortcode, pool: &DatabasePool) -> ModResult<bool> {
+    dispatch!(pool, consume_view(shortcode))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn delete_clip(shortcode: &Shortcode, pool: &DatabasePool) -> ModResult<u64> {
+    dispatch!(pool, delete_clip(shortcode))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_file(shortcode: &Shortcode, pool: &DatabasePool) -> ModResult<Vec<u8>> {
+    dispatch!(pool, get_file(shortcode))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn update_password(
+    shortcode: &Shortcode,
+    password: Option<String>,
+    pool: &DatabasePool,
+) -> ModResult<()> {
+    dispatch!(pool, update_password(shortcode, password))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn save_api_key(
+    model: model::NewApiKey,
+    pool: &DatabasePool,
+) -> ModResult<model::ApiKey> {
+    dispatch!(pool, save_api_key(model))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_api_key(api_key: Vec<u8>, pool: &DatabasePool) -> ModResult<model::ApiKey> {
+    dispatch!(pool, get_api_key(api_key))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn list_api_keys(pool: &DatabasePool) -> ModResult<Vec<model::ApiKey>> {
+    dispatch!(pool, list_api_keys())
+}
+
+/// * Records that the key was used, the UPDATE skips keys that were used in the last minute in case of concurrent requests
+#[tracing::instrument(skip_all)]
+pub async fn touch_api_key(api_key: Vec<u8>, pool: &DatabasePool) -> ModResult<()> {
+    dispatch!(pool, touch_api_key(api_key))
+}
+
+pub enum RevocationStatus {
+    Revoked,
+    NotFound,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn revoke_api_key(name: &str, pool: &DatabasePool) -> ModResult<RevocationStatus> {
+    dispatch!(pool, revoke_api_key(name))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn new_user(model: model::NewUser, pool: &DatabasePool) -> ModResult<model::User> {
+    dispatch!(pool, new_user(model))
+}
+
+// NOTE Usernames are compared without case
+#[tracing::instrument(skip_all)]
+pub async fn get_user(username: &str, pool: &DatabasePool) -> ModResult<model::User> {
+    dispatch!(pool, get_user(username))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn new_session(model: model::NewSession, pool: &DatabasePool) -> ModResult<()> {
+    dispatch!(pool, new_session(model))
+}
+
+/// * User of a session that hasn't expired yet
+#[tracing::instrument(skip_all)]
+pub async fn get_session_user(
+    session_hash: Vec<u8>,
+    pool: &DatabasePool,
+) -> ModResult<model::User> {
+    dispatch!(pool, get_session_user(session_hash))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn delete_session(session_hash: Vec<u8>, pool: &DatabasePool) -> ModResult<()> {
+    dispatch!(pool, delete_session(session_hash))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn delete_expired(pool: &DatabasePool) -> ModResult<u64> {
+    dispatch!(pool, delete_expired())
+}
+
+/// * Compresses the content of clips and revisions that were stored before compression was enabled
+/// * Rows are visited in batches ordered by key, each batch is rewritten in a single transaction
+/// * Returns the amount of rows that were compressed
+#[tracing::instrument(skip_all)]
+pub async fn compress_stored_content(pool: &DatabasePool) -> ModResult<u64> {
+    dispatch!(pool, compress_stored_content())
+}
+
+/// * Rebuilds the full text index of every clip, needed for clips that were compressed before search existed
+/// * Returns the amount of clips that were indexed
+#[tracing::instrument(skip_all)]
+pub async fn reindex_clips(pool: &DatabasePool) -> ModResult<u64> {
+    dispatch!(pool, reindex_clips())
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::data::test::*;
+    use crate::data::*;
+    use crate::test::async_runtime;
+
+    fn model_get_clip(shortcode: &str) -> model::GetClip {
+        model::GetClip {
+            shortcode: shortcode.to_string(),
+        }
+    }
+
+    fn model_new_clip(shortcode: &str) -> model::NewClip {
+        use chrono::Utc;
+        model::NewClip {
+            clip_id: DbId::new().into(),
+            shortcode: shortcode.into(),
+            content: format!("content for clip '{}'", shortcode),
+            content_encoding: None,
+            content_compressed: None,
+            title: None,
+            posted: Utc::now().timestamp(),
+            expires: None,
+            password: None,
+            edit_secret: None,
+            max_views: None,
+            language: None,
+            file_name: None,
+            mime_type: None,
+            file_data: None,
+            encrypted: false,
+            owner: None,
+            search: model::SearchIndex {
+                title: String::new(),
+                content: format!("content for clip '{}'", shortcode),
+            },
+        }
+    }
+
+    #[test]
+    fn clip_new_and_get() {
+        let rt = async_runtime();
+        for db in test_dbs(rt.handle()) {
+            let pool = db.get_pool();
+
+            let clip = rt.block_on(async move { super::new_clip(model_new_clip("1"), pool).await });
+
+            assert!(clip.is_ok());
+            let clip = clip.unwrap();
+            assert!(clip.shortcode == "1");
+            assert!(clip.content == *"content for clip '1'");
+        }
+    }
+
+    #[test]
+    fn hit_counts_are_increased_in_one_batch() {
+        use crate::Shortcode;
+
+        let rt = async_runtime();
+        for db in test_dbs(rt.handle()) {
+            let pool = db.get_pool();
+
+            let updated = rt.block_on(async move {
+                for shortcode in ["1", "2"] {
+                    super::new_clip(model_new_clip(shortcode), pool).await?;
+                }
+                let hits = [
+                    (Shortcode::from("1"), 3),
+                    (Shortcode::from("2"), 1),
+                    (Shortcode::from("deleted"), 5),
+                ];
+                let mut transaction = pool.begin().await?;
+                let updated = super::increase_hit_counts(&hits, &mut transaction).await?;
+                transaction.commit().await?;
+                super::increase_hit_counts(&hits[..1], &mut pool.begin().await?).await?;
+                Ok::<_, DataErr>(updated)
+            });
+            // * The clip that doesn't exist is skipped and the uncommitted batch is rolled back
+            assert_eq!(updated.unwrap(), 2);
+            for (shortcode, hits) in [("1", 3), ("2", 1)] {
+                let clip = rt
+                    .block_on(async move { super::get_clip(model_get_clip(shortcode), pool).await })
+                    .unwrap();
+                assert_eq!(clip.hits, hits);
+            }
+        }
+    }
+
+    #[test]
+    fn concurrent_updates_get_their_own_revision() {
+        let rt = async_runtime();
+        for db in test_dbs(rt.handle()) {
+            let pool = db.get_pool().clone();
+            rt.block_on(super::new_clip(model_new_clip("1"), &pool))
+                .unwrap();
+
+            let updates = (0..4)
+                .map(|update| {
+                    let pool = pool.clone();
+                    let model = model::UpdateClip {
+                        shortcode: "1".to_owned(),
+                        content: format!("update {update}"),
+                        content_encoding: None,
+                        content_compressed: None,
+                        title: None,
+                        expires: None,
+                        password: None,
+                        language: None,
+                        search: model::SearchIndex {
+                            title: String::new(),
+                            content: format!("update {update}"),
+                        },
+                    };
+                    rt.spawn(async move { super::update_clip(model, &pool).await })
+                })
+                .collect::<Vec<_>>();
+            for update in updates {
+                rt.block_on(update).unwrap().unwrap();
+            }
+
+            let revisions = rt
+                .block_on(super::get_revisions(&"1".into(), &pool))
+                .unwrap()
+                .iter()
+                .map(|revision| revision.revision)
+                .collect::<Vec<_>>();
+            assert_eq!(revisions, [1, 2, 3, 4]);
+        }
+    }
+
+    #[test]
+    fn plaintext_password_is_rehashed_on_read() {
+        use crate::domain::clip::field::Password;
+        use crate::service::{action, ask, token::AccessTokenSigner};
+
+        let rt = async_runtime();
+        for db in test_dbs(rt.handle()) {
+            let pool = db.get_pool();
+            let signer = AccessTokenSigner::random(chrono::Duration::hours(1));
+            let signer = &signer;
+
+            // ? Simulates a clip stored before passwords were hashed
+            let legacy = model::NewClip {
+                password: Some("123".to_owned()),
+                ..model_new_clip("1")
+            };
+            rt.block_on(async move { super::new_clip(legacy, pool).await })
+                .unwrap();
+
+            let req = ask::GetClip {
+                shortcode: "1".into(),
+                password: Password::new("123".to_owned()).unwrap(),
+                token: None,
+            };
+            let clip = rt.block_on(async move { action::get_clip(req, signer, pool).await });
+            assert!(clip.is_ok());
+
+            let row = rt
+                .block_on(async move { super::get_clip(model_get_clip("1"), pool).await })
+                .unwrap();
+            let stored = row.password.unwrap();
+            assert_ne!(stored, "123");
+            assert!(stored.starts_with("$argon2"));
+
+            let req = ask::GetClip {
+                shortcode: "1".into(),
+                password: Password::new("abc".to_owned()).unwrap(),
+                token: None,
+            };
+            let clip = rt.block_on(async move { action::get_clip(req, signer, pool).await });
+            assert!(clip.is_err());
+        }
+    }
+
+    #[test]
+    fn large_content_is_compressed_transparently() {
+        use crate::domain::Clip;
+
+        let rt = async_runtime();
+        for db in test_dbs(rt.handle()) {
+            let pool = db.get_pool();
+            let content = "GET /clip/abc 200 OK\n".repeat(1000);
+
+            let stored = compression::compress(content.clone());
+            let new = model::NewClip {
+                content: stored.content,
+                content_encoding: stored.encoding,
+                content_compressed: stored.compressed,
+                ..model_new_clip("new")
+            };
+            // ? Simulates a clip stored before compression was enabled
+            let legacy = model::NewClip {
+                content: content.clone(),
+                ..model_new_clip("legacy")
+            };
+            let small = model_new_clip("small");
+            rt.block_on(async move {
+                super::new_clip(new, pool).await.unwrap();
+                super::new_clip(legacy, pool).await.unwrap();
+                super::new_clip(small, pool).await.unwrap();
+            });
+
+            let compressed = rt.block_on(async move { super::compress_stored_content(pool).await });
+            assert_eq!(compressed.unwrap(), 1);
+
+            for shortcode in ["new", "legacy"] {
+                let row = rt
+                    .block_on(async move { super::get_clip(model_get_clip(shortcode), pool).await })
+                    .unwrap();
+                assert_eq!(row.content_encoding.as_deref(), Some(compression::DEFLATE));
+                assert!(row.content.is_empty());
+                assert!(row.content_compressed.as_ref().unwrap().len() < content.len());
+
+                let clip = Clip::try_from(row).unwrap();
+                assert_eq!(clip.content.as_str(), content);
+            }
+
+            let row = rt
+                .block_on(async move { super::get_clip(model_get_clip("small"), pool).await })
+                .unwrap();
+            assert!(row.content_encoding.is_none());
+            assert_eq!(row.content, "content for clip 'small'");
+        }
+    }
+}
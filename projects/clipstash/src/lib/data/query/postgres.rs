@@ -0,0 +1,632 @@
+use super::{search_index, RevocationStatus, COMPRESSION_BATCH_SIZE};
+use crate::{
+    data::{
+        compression,
+        model::{self, GetClip, NewClip, UpdateClip},
+        DataErr,
+    },
+    domain::api_key::LAST_USED_PRECISION_SECS,
+    Shortcode,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::PgPool;
+
+type ModResult<T> = std::result::Result<T, DataErr>;
+type Transaction<'t> = sqlx::Transaction<'t, sqlx::Postgres>;
+
+// NOTE The models carry Unix timestamps like SQLite stores them, Postgres stores them as TIMESTAMP in UTC
+fn time(timestamp: i64) -> NaiveDateTime {
+    DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .naive_utc()
+}
+
+// ? Expiration is compared with the application's clock, like SQLite's queries do with `unixepoch()`
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+pub async fn increase_hit_counts(
+    hits: &[(Shortcode, u32)],
+    transaction: &mut Transaction<'_>,
+) -> ModResult<u64> {
+    let values = (0..hits.len())
+        .map(|row| format!("(${}, ${})", row * 2 + 1, row * 2 + 2))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let sql = format!(
+        r#"UPDATE clips SET hits = clips.hits + batch.hits
+        FROM (SELECT column1 AS shortcode, column2 AS hits FROM (VALUES {values}) AS v) AS batch
+        WHERE clips.shortcode = batch.shortcode"#
+    );
+    let query = hits
+        .iter()
+        .fold(sqlx::query(&sql), |query, (shortcode, hits)| {
+            query.bind(shortcode.as_str()).bind(i64::from(*hits))
+        });
+    Ok(query.execute(&mut *transaction).await?.rows_affected())
+}
+
+pub async fn get_clip<M: Into<model::GetClip>>(model: M, pool: &PgPool) -> ModResult<model::Clip> {
+    let model: GetClip = model.into();
+    Ok(
+        sqlx::query_as::<_, model::Clip>("SELECT * FROM clips WHERE shortcode = $1")
+            .bind(model.shortcode)
+            .fetch_one(pool)
+            .await?,
+    )
+}
+
+pub async fn new_clip<M: Into<model::NewClip>>(model: M, pool: &PgPool) -> ModResult<model::Clip> {
+    let model: NewClip = model.into();
+    let file_size = model.file_data.as_ref().map(|data| data.len() as i64);
+    let mut transaction = pool.begin().await?;
+    sqlx::query(
+        r#"INSERT INTO clips (
+            clip_id,
+            shortcode,
+            content,
+            content_encoding,
+            content_compressed,
+            title,
+            posted,
+            expires,
+            password,
+            hits,
+            edit_secret,
+            max_views,
+            language,
+            file_name,
+            mime_type,
+            file_size,
+            encrypted,
+            owner
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, 0, $10, $11, $12, $13, $14, $15, $16, $17)"#,
+    )
+    .bind(&model.clip_id)
+    .bind(&model.shortcode)
+    .bind(&model.content)
+    .bind(&model.content_encoding)
+    .bind(&model.content_compressed)
+    .bind(&model.title)
+    .bind(time(model.posted))
+    .bind(model.expires.map(time))
+    .bind(&model.password)
+    .bind(&model.edit_secret)
+    .bind(model.max_views)
+    .bind(&model.language)
+    .bind(&model.file_name)
+    .bind(&model.mime_type)
+    .bind(file_size)
+    .bind(model.encrypted)
+    .bind(&model.owner)
+    .execute(&mut transaction)
+    .await?;
+    if let Some(data) = &model.file_data {
+        sqlx::query("INSERT INTO clip_files (clip_id, data) VALUES ($1, $2)")
+            .bind(&model.clip_id)
+            .bind(data)
+            .execute(&mut transaction)
+            .await?;
+    }
+    index_clip(&model.shortcode, &model.search, &mut transaction).await?;
+    transaction.commit().await?;
+
+    get_clip(model.shortcode, pool).await
+}
+
+pub async fn update_clip<M: Into<model::UpdateClip>>(
+    model: M,
+    pool: &PgPool,
+) -> ModResult<model::Clip> {
+    let model: UpdateClip = model.into();
+    let mut transaction = pool.begin().await?;
+
+    // NOTE Concurrent updates would number their revisions the same, the row lock makes them wait for each other
+    // ? The primary key of clip_revisions still rejects a duplicate revision if it ever happens
+    sqlx::query("SELECT clip_id FROM clips WHERE shortcode = $1 FOR UPDATE")
+        .bind(&model.shortcode)
+        .execute(&mut transaction)
+        .await?;
+    sqlx::query(
+        r#"INSERT INTO clip_revisions (
+            clip_id,
+            revision,
+            content,
+            content_encoding,
+            content_compressed,
+            title,
+            expires,
+            replaced
+        )
+        SELECT
+            clip_id,
+            (SELECT COALESCE(MAX(r.revision), 0) + 1 FROM clip_revisions r WHERE r.clip_id = clips.clip_id),
+            content,
+            content_encoding,
+            content_compressed,
+            title,
+            expires,
+            $1
+        FROM clips WHERE shortcode = $2"#,
+    )
+    .bind(now())
+    .bind(&model.shortcode)
+    .execute(&mut transaction)
+    .await?;
+
+    sqlx::query(
+        r#"UPDATE clips SET
+        content = $1,
+        content_encoding = $2,
+        content_compressed = $3,
+        expires = $4,
+        password = $5,
+        title = $6,
+        language = $7
+        WHERE shortcode = $8"#,
+    )
+    .bind(&model.content)
+    .bind(&model.content_encoding)
+    .bind(&model.content_compressed)
+    .bind(model.expires.map(time))
+    .bind(&model.password)
+    .bind(&model.title)
+    .bind(&model.language)
+    .bind(&model.shortcode)
+    .execute(&mut transaction)
+    .await?;
+    index_clip(&model.shortcode, &model.search, &mut transaction).await?;
+
+    transaction.commit().await?;
+    get_clip(model.shortcode, pool).await
+}
+
+/// * Replaces the clip's row in the full text index, the `simple` configuration doesn't stem words, like SQLite's tokenizer
+async fn index_clip(
+    shortcode: &str,
+    search: &model::SearchIndex,
+    transaction: &mut Transaction<'_>,
+) -> ModResult<()> {
+    sqlx::query(
+        r#"INSERT INTO clips_fts (clip_id, document)
+        SELECT clip_id, to_tsvector('simple', $1 || ' ' || $2) FROM clips WHERE shortcode = $3
+        ON CONFLICT (clip_id) DO UPDATE SET document = excluded.document"#,
+    )
+    .bind(&search.title)
+    .bind(&search.content)
+    .bind(shortcode)
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+// NOTE The sort key is negated for ascending orders so every order is read in descending order
+// ? The search terms are quoted for FTS5, plainto_tsquery() drops the quotes and requires every term
+pub async fn list_clips(model: model::ListClips, pool: &PgPool) -> ModResult<Vec<model::Clip>> {
+    Ok(sqlx::query_as::<_, model::Clip>(
+        r#"SELECT * FROM clips
+        WHERE ($1::text IS NULL OR clip_id IN (
+            SELECT clip_id FROM clips_fts WHERE document @@ plainto_tsquery('simple', $1)
+        ))
+        AND ($2::text IS NULL OR strpos(lower(coalesce(title, '')), lower($2)) > 0)
+        AND ($3::timestamp IS NULL OR posted > $3)
+        AND (expires IS NULL OR expires > $8)
+        AND (max_views IS NULL OR views < max_views)
+        AND ($5::bigint IS NULL OR (
+            CASE $4::text
+                WHEN 'oldest' THEN -extract(epoch FROM posted)::bigint
+                WHEN 'hits' THEN hits
+                ELSE extract(epoch FROM posted)::bigint
+            END,
+            clip_id
+        ) < ($5, $6::text))
+        ORDER BY CASE $4::text
+                WHEN 'oldest' THEN -extract(epoch FROM posted)::bigint
+                WHEN 'hits' THEN hits
+                ELSE extract(epoch FROM posted)::bigint
+            END DESC,
+            clip_id DESC
+        LIMIT $7"#,
+    )
+    .bind(model.search)
+    .bind(model.title)
+    .bind(model.posted_after.map(time))
+    .bind(model.order)
+    .bind(model.cursor_key)
+    .bind(model.cursor_id)
+    .bind(model.limit)
+    .bind(now())
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn list_user_clips(user_id: &str, pool: &PgPool) -> ModResult<Vec<model::Clip>> {
+    Ok(sqlx::query_as::<_, model::Clip>(
+        r#"SELECT * FROM clips
+        WHERE owner = $1
+        AND (expires IS NULL OR expires > $2)
+        AND (max_views IS NULL OR views < max_views)
+        ORDER BY posted DESC, clip_id DESC"#,
+    )
+    .bind(user_id)
+    .bind(now())
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn get_revisions(
+    shortcode: &Shortcode,
+    pool: &PgPool,
+) -> ModResult<Vec<model::Revision>> {
+    Ok(sqlx::query_as::<_, model::Revision>(
+        r#"SELECT
+            r.revision,
+            r.content,
+            r.title,
+            r.expires,
+            r.replaced,
+            r.content_encoding,
+            r.content_compressed
+        FROM clip_revisions r
+        JOIN clips c ON c.clip_id = r.clip_id
+        WHERE c.shortcode = $1
+        ORDER BY r.revision"#,
+    )
+    .bind(shortcode.as_str())
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn consume_view(shortcode: &Shortcode, pool: &PgPool) -> ModResult<bool> {
+    let shortcode = shortcode.as_str();
+    let mut transaction = pool.begin().await?;
+
+    let consumed = sqlx::query(
+        r#"UPDATE clips SET views = views + 1
+        WHERE shortcode = $1 AND max_views IS NOT NULL AND views < max_views"#,
+    )
+    .bind(shortcode)
+    .execute(&mut transaction)
+    .await?
+    .rows_affected()
+        > 0;
+
+    if consumed {
+        for query in [
+            r#"DELETE FROM clip_revisions WHERE clip_id IN (
+                SELECT clip_id FROM clips WHERE shortcode = $1 AND views >= max_views
+            )"#,
+            r#"DELETE FROM clip_files WHERE clip_id IN (
+                SELECT clip_id FROM clips WHERE shortcode = $1 AND views >= max_views
+            )"#,
+            "DELETE FROM clips WHERE shortcode = $1 AND views >= max_views",
+        ] {
+            sqlx::query(query)
+                .bind(shortcode)
+                .execute(&mut transaction)
+                .await?;
+        }
+    }
+
+    transaction.commit().await?;
+    Ok(consumed)
+}
+
+pub async fn delete_clip(shortcode: &Shortcode, pool: &PgPool) -> ModResult<u64> {
+    let shortcode = shortcode.as_str();
+    let mut transaction = pool.begin().await?;
+
+    for query in [
+        "DELETE FROM clip_revisions WHERE clip_id IN (SELECT clip_id FROM clips WHERE shortcode = $1)",
+        "DELETE FROM clip_files WHERE clip_id IN (SELECT clip_id FROM clips WHERE shortcode = $1)",
+    ] {
+        sqlx::query(query)
+            .bind(shortcode)
+            .execute(&mut transaction)
+            .await?;
+    }
+    let deleted = sqlx::query("DELETE FROM clips WHERE shortcode = $1")
+        .bind(shortcode)
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+
+    transaction.commit().await?;
+    Ok(deleted)
+}
+
+pub async fn get_file(shortcode: &Shortcode, pool: &PgPool) -> ModResult<Vec<u8>> {
+    Ok(sqlx::query_scalar::<_, Vec<u8>>(
+        r#"SELECT f.data FROM clip_files f
+        JOIN clips c ON c.clip_id = f.clip_id
+        WHERE c.shortcode = $1"#,
+    )
+    .bind(shortcode.as_str())
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn update_password(
+    shortcode: &Shortcode,
+    password: Option<String>,
+    pool: &PgPool,
+) -> ModResult<()> {
+    sqlx::query("UPDATE clips SET password = $1 WHERE shortcode = $2")
+        .bind(password)
+        .bind(shortcode.as_str())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn save_api_key(model: model::NewApiKey, pool: &PgPool) -> ModResult<model::ApiKey> {
+    sqlx::query(
+        r#"INSERT INTO api_keys (api_key, name, created, expires, scopes, owner)
+        VALUES ($1, $2, $3, $4, $5, $6)"#,
+    )
+    .bind(&model.api_key)
+    .bind(&model.name)
+    .bind(time(model.created))
+    .bind(model.expires.map(time))
+    .bind(&model.scopes)
+    .bind(&model.owner)
+    .execute(pool)
+    .await?;
+    get_api_key(model.api_key, pool).await
+}
+
+const API_KEY_COLUMNS: &str = r#"
+    name,
+    created,
+    expires,
+    last_used,
+    scopes,
+    owner,
+    (SELECT username FROM users WHERE user_id = owner) AS owner_name"#;
+
+pub async fn get_api_key(api_key: Vec<u8>, pool: &PgPool) -> ModResult<model::ApiKey> {
+    Ok(sqlx::query_as::<_, model::ApiKey>(&format!(
+        "SELECT {API_KEY_COLUMNS} FROM api_keys WHERE api_key = $1"
+    ))
+    .bind(api_key)
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn list_api_keys(pool: &PgPool) -> ModResult<Vec<model::ApiKey>> {
+    Ok(sqlx::query_as::<_, model::ApiKey>(&format!(
+        "SELECT {API_KEY_COLUMNS} FROM api_keys ORDER BY name"
+    ))
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn touch_api_key(api_key: Vec<u8>, pool: &PgPool) -> ModResult<()> {
+    let now = Utc::now().timestamp();
+    sqlx::query(
+        r#"UPDATE api_keys SET last_used = $1
+        WHERE api_key = $2 AND (last_used IS NULL OR last_used < $3)"#,
+    )
+    .bind(time(now))
+    .bind(api_key)
+    .bind(time(now - LAST_USED_PRECISION_SECS))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn revoke_api_key(name: &str, pool: &PgPool) -> ModResult<RevocationStatus> {
+    Ok(sqlx::query("DELETE FROM api_keys WHERE name = $1")
+        .bind(name)
+        .execute(pool)
+        .await
+        .map(|res| match res.rows_affected() {
+            0 => RevocationStatus::NotFound,
+            _ => RevocationStatus::Revoked,
+        })?)
+}
+
+pub async fn new_user(model: model::NewUser, pool: &PgPool) -> ModResult<model::User> {
+    sqlx::query("INSERT INTO users (user_id, username, password, created) VALUES ($1, $2, $3, $4)")
+        .bind(&model.user_id)
+        .bind(&model.username)
+        .bind(&model.password)
+        .bind(time(model.created))
+        .execute(pool)
+        .await?;
+    get_user(&model.username, pool).await
+}
+
+// NOTE The unique index is on lower(username), so it's also used by this query
+pub async fn get_user(username: &str, pool: &PgPool) -> ModResult<model::User> {
+    Ok(sqlx::query_as::<_, model::User>(
+        "SELECT user_id, username, password, created FROM users WHERE lower(username) = lower($1)",
+    )
+    .bind(username)
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn new_session(model: model::NewSession, pool: &PgPool) -> ModResult<()> {
+    sqlx::query(
+        "INSERT INTO sessions (session_hash, user_id, created, expires) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(model.session_hash)
+    .bind(model.user_id)
+    .bind(time(model.created))
+    .bind(time(model.expires))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_session_user(session_hash: Vec<u8>, pool: &PgPool) -> ModResult<model::User> {
+    Ok(sqlx::query_as::<_, model::User>(
+        r#"SELECT u.user_id, u.username, u.password, u.created
+        FROM sessions s JOIN users u ON u.user_id = s.user_id
+        WHERE s.session_hash = $1 AND s.expires > $2"#,
+    )
+    .bind(session_hash)
+    .bind(now())
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn delete_session(session_hash: Vec<u8>, pool: &PgPool) -> ModResult<()> {
+    sqlx::query("DELETE FROM sessions WHERE session_hash = $1")
+        .bind(session_hash)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+// NOTE Rows of the full text index are deleted along with their clip by the foreign key
+pub async fn delete_expired(pool: &PgPool) -> ModResult<u64> {
+    let now = now();
+    let mut transaction = pool.begin().await?;
+
+    let deleted = sqlx::query(
+        r#"DELETE FROM clips
+        WHERE $1 > expires
+        OR (max_views IS NOT NULL AND views >= max_views)"#,
+    )
+    .bind(now)
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    // * Revisions and files expire along with their clip
+    for query in [
+        "DELETE FROM clip_revisions WHERE clip_id NOT IN (SELECT clip_id FROM clips)",
+        "DELETE FROM clip_files WHERE clip_id NOT IN (SELECT clip_id FROM clips)",
+    ] {
+        sqlx::query(query).execute(&mut transaction).await?;
+    }
+    sqlx::query("DELETE FROM sessions WHERE expires <= $1")
+        .bind(now)
+        .execute(&mut transaction)
+        .await?;
+
+    transaction.commit().await?;
+    Ok(deleted)
+}
+
+pub async fn compress_stored_content(pool: &PgPool) -> ModResult<u64> {
+    let threshold = compression::COMPRESSION_THRESHOLD as i64;
+    let mut compressed = 0;
+
+    let mut last_clip_id = String::new();
+    loop {
+        let mut transaction = pool.begin().await?;
+        let rows = sqlx::query_as::<_, (String, String)>(
+            r#"SELECT clip_id, content FROM clips
+            WHERE content_encoding IS NULL
+            AND octet_length(content) >= $1
+            AND clip_id > $2
+            ORDER BY clip_id
+            LIMIT $3"#,
+        )
+        .bind(threshold)
+        .bind(&last_clip_id)
+        .bind(COMPRESSION_BATCH_SIZE)
+        .fetch_all(&mut transaction)
+        .await?;
+        let Some((last, _)) = rows.last() else {
+            break;
+        };
+        last_clip_id = last.clone();
+
+        for (clip_id, content) in rows {
+            let stored = compression::compress(content);
+            if stored.encoding.is_none() {
+                continue;
+            }
+            compressed += sqlx::query(
+                r#"UPDATE clips SET content = $1, content_encoding = $2, content_compressed = $3
+                WHERE clip_id = $4"#,
+            )
+            .bind(stored.content)
+            .bind(stored.encoding)
+            .bind(stored.compressed)
+            .bind(clip_id)
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        }
+        transaction.commit().await?;
+    }
+
+    // ? Postgres has no rowid, revisions are visited by their primary key instead
+    let mut last_revision = (String::new(), 0);
+    loop {
+        let mut transaction = pool.begin().await?;
+        let rows = sqlx::query_as::<_, (String, i64, String)>(
+            r#"SELECT clip_id, revision, content FROM clip_revisions
+            WHERE content_encoding IS NULL
+            AND octet_length(content) >= $1
+            AND (clip_id, revision) > ($2, $3)
+            ORDER BY clip_id, revision
+            LIMIT $4"#,
+        )
+        .bind(threshold)
+        .bind(&last_revision.0)
+        .bind(last_revision.1)
+        .bind(COMPRESSION_BATCH_SIZE)
+        .fetch_all(&mut transaction)
+        .await?;
+        let Some((clip_id, revision, _)) = rows.last() else {
+            break;
+        };
+        last_revision = (clip_id.clone(), *revision);
+
+        for (clip_id, revision, content) in rows {
+            let stored = compression::compress(content);
+            if stored.encoding.is_none() {
+                continue;
+            }
+            compressed += sqlx::query(
+                r#"UPDATE clip_revisions SET content = $1, content_encoding = $2, content_compressed = $3
+                WHERE clip_id = $4 AND revision = $5"#,
+            )
+            .bind(stored.content)
+            .bind(stored.encoding)
+            .bind(stored.compressed)
+            .bind(clip_id)
+            .bind(revision)
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        }
+        transaction.commit().await?;
+    }
+
+    Ok(compressed)
+}
+
+pub async fn reindex_clips(pool: &PgPool) -> ModResult<u64> {
+    let mut indexed = 0;
+    let mut last_clip_id = String::new();
+    loop {
+        let mut transaction = pool.begin().await?;
+        let rows = sqlx::query_as::<_, model::Clip>(
+            "SELECT * FROM clips WHERE clip_id > $1 ORDER BY clip_id LIMIT $2",
+        )
+        .bind(&last_clip_id)
+        .bind(COMPRESSION_BATCH_SIZE)
+        .fetch_all(&mut transaction)
+        .await?;
+        let Some(last) = rows.last() else {
+            break;
+        };
+        last_clip_id = last.clip_id.clone();
+
+        for row in rows {
+            let (shortcode, search) = search_index(row);
+            index_clip(&shortcode, &search, &mut transaction).await?;
+            indexed += 1;
+        }
+        transaction.commit().await?;
+    }
+    Ok(indexed)
+}
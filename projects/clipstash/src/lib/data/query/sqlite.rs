@@ -0,0 +1,661 @@
+use super::{search_index, RevocationStatus, COMPRESSION_BATCH_SIZE};
+use crate::{
+    data::{
+        compression,
+        model::{self, GetClip, NewClip, UpdateClip},
+        DataErr,
+    },
+    domain::api_key::LAST_USED_PRECISION_SECS,
+    Shortcode,
+};
+use chrono::{NaiveDateTime, Utc};
+use sqlx::SqlitePool;
+
+type ModResult<T> = std::result::Result<T, DataErr>;
+type Transaction<'t> = sqlx::Transaction<'t, sqlx::Sqlite>;
+
+// NOTE The statement has a row of values per clip, so unlike the others it can't be checked at compile time
+// ? The columns of a VALUES list are named column1, column2... by both SQLite and Postgres
+pub async fn increase_hit_counts(
+    hits: &[(Shortcode, u32)],
+    transaction: &mut Transaction<'_>,
+) -> ModResult<u64> {
+    let values = vec!["(?, ?)"; hits.len()].join(", ");
+    let sql = format!(
+        r#"UPDATE clips SET hits = clips.hits + batch.hits
+        FROM (SELECT column1 AS shortcode, column2 AS hits FROM (VALUES {values})) AS batch
+        WHERE clips.shortcode = batch.shortcode"#
+    );
+    let query = hits
+        .iter()
+        .fold(sqlx::query(&sql), |query, (shortcode, hits)| {
+            query.bind(shortcode.as_str()).bind(i64::from(*hits))
+        });
+    Ok(query.execute(&mut *transaction).await?.rows_affected())
+}
+
+pub async fn get_clip<M: Into<model::GetClip>>(
+    model: M,
+    pool: &SqlitePool,
+) -> ModResult<model::Clip> {
+    let model: GetClip = model.into();
+    let shortcode = model.shortcode.to_string();
+
+    Ok(sqlx::query_as!(
+        model::Clip,
+        "SELECT * FROM clips WHERE shortcode = ?",
+        shortcode
+    )
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn new_clip<M: Into<model::NewClip>>(
+    model: M,
+    pool: &SqlitePool,
+) -> ModResult<model::Clip> {
+    let model: NewClip = model.into();
+    let file_size = model.file_data.as_ref().map(|data| data.len() as i64);
+    let mut transaction = pool.begin().await?;
+    // NOTE The query! macro provides a type-safe way to configure SQL queries at compile time
+    let _ = sqlx::query!(
+        r#"INSERT INTO clips (
+            clip_id,
+            shortcode,
+            content,
+            content_encoding,
+            content_compressed,
+            title,
+            posted,
+            expires,
+            password,
+            hits,
+            edit_secret,
+            max_views,
+            language,
+            file_name,
+            mime_type,
+            file_size,
+            encrypted,
+            owner
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+        model.clip_id,
+        model.shortcode,
+        model.content,
+        model.content_encoding,
+        model.content_compressed,
+        model.title,
+        model.posted,
+        model.expires,
+        model.password,
+        0,
+        model.edit_secret,
+        model.max_views,
+        model.language,
+        model.file_name,
+        model.mime_type,
+        file_size,
+        model.encrypted,
+        model.owner
+    )
+    .execute(&mut transaction)
+    .await?;
+    if let Some(data) = &model.file_data {
+        sqlx::query!(
+            "INSERT INTO clip_files (clip_id, data) VALUES (?, ?)",
+            model.clip_id,
+            data
+        )
+        .execute(&mut transaction)
+        .await?;
+    }
+    sqlx::query!(
+        "INSERT INTO clips_fts (clip_id, title, content) VALUES (?, ?, ?)",
+        model.clip_id,
+        model.search.title,
+        model.search.content
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+
+    // NOTE .await will run the get_clip asynchronously, then this returns a Result<model::Clip>
+    get_clip(model.shortcode, pool).await
+}
+
+pub async fn update_clip<M: Into<model::UpdateClip>>(
+    model: M,
+    pool: &SqlitePool,
+) -> ModResult<model::Clip> {
+    let model: UpdateClip = model.into();
+    let replaced = Utc::now().timestamp();
+    let mut transaction = pool.begin().await?;
+
+    let _ = sqlx::query!(
+        r#"INSERT INTO clip_revisions (
+            clip_id,
+            revision,
+            content,
+            content_encoding,
+            content_compressed,
+            title,
+            expires,
+            replaced
+        )
+        SELECT
+            clip_id,
+            (SELECT COALESCE(MAX(r.revision), 0) + 1 FROM clip_revisions r WHERE r.clip_id = clips.clip_id),
+            content,
+            content_encoding,
+            content_compressed,
+            title,
+            expires,
+            ?
+        FROM clips WHERE shortcode = ?"#,
+        replaced,
+        model.shortcode
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    let _ = sqlx::query!(
+        r#"UPDATE clips SET 
+        content = ?, 
+        content_encoding = ?,
+        content_compressed = ?,
+        expires = ?, 
+        password = ?, 
+        title = ?,
+        language = ?
+        WHERE shortcode = ?"#,
+        model.content,
+        model.content_encoding,
+        model.content_compressed,
+        model.expires,
+        model.password,
+        model.title,
+        model.language,
+        model.shortcode
+    )
+    .execute(&mut transaction)
+    .await?;
+    index_clip(&model.shortcode, &model.search, &mut transaction).await?;
+
+    transaction.commit().await?;
+    get_clip(model.shortcode, pool).await
+}
+
+/// * Replaces the clip's row in the full text index
+async fn index_clip(
+    shortcode: &str,
+    search: &model::SearchIndex,
+    transaction: &mut Transaction<'_>,
+) -> ModResult<()> {
+    sqlx::query!(
+        "DELETE FROM clips_fts WHERE clip_id = (SELECT clip_id FROM clips WHERE shortcode = ?)",
+        shortcode
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"INSERT INTO clips_fts (clip_id, title, content)
+        SELECT clip_id, ?, ? FROM clips WHERE shortcode = ?"#,
+        search.title,
+        search.content,
+        shortcode
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+// NOTE The sort key is negated for ascending orders so every order is read in descending order
+pub async fn list_clips(model: model::ListClips, pool: &SqlitePool) -> ModResult<Vec<model::Clip>> {
+    Ok(sqlx::query_as!(
+        model::Clip,
+        r#"SELECT * FROM clips
+        WHERE (?1 IS NULL OR clip_id IN (SELECT clip_id FROM clips_fts WHERE clips_fts MATCH ?1))
+        AND (?2 IS NULL OR instr(lower(coalesce(title, '')), lower(?2)) > 0)
+        AND (?3 IS NULL OR posted > ?3)
+        AND (expires IS NULL OR expires > unixepoch())
+        AND (max_views IS NULL OR views < max_views)
+        AND (?5 IS NULL OR (
+            CASE ?4 WHEN 'oldest' THEN -posted WHEN 'hits' THEN hits ELSE posted END,
+            clip_id
+        ) < (?5, ?6))
+        ORDER BY CASE ?4 WHEN 'oldest' THEN -posted WHEN 'hits' THEN hits ELSE posted END DESC,
+            clip_id DESC
+        LIMIT ?7"#,
+        model.search,
+        model.title,
+        model.posted_after,
+        model.order,
+        model.cursor_key,
+        model.cursor_id,
+        model.limit
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn list_user_clips(user_id: &str, pool: &SqlitePool) -> ModResult<Vec<model::Clip>> {
+    Ok(sqlx::query_as!(
+        model::Clip,
+        r#"SELECT * FROM clips
+        WHERE owner = ?
+        AND (expires IS NULL OR expires > unixepoch())
+        AND (max_views IS NULL OR views < max_views)
+        ORDER BY posted DESC, clip_id DESC"#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn get_revisions(
+    shortcode: &Shortcode,
+    pool: &SqlitePool,
+) -> ModResult<Vec<model::Revision>> {
+    let shortcode = shortcode.as_str();
+    Ok(sqlx::query_as!(
+        model::Revision,
+        r#"SELECT
+            r.revision AS "revision!",
+            r.content AS "content!",
+            r.title,
+            r.expires AS "expires: NaiveDateTime",
+            r.replaced AS "replaced!: NaiveDateTime",
+            r.content_encoding,
+            r.content_compressed
+        FROM clip_revisions r
+        JOIN clips c ON c.clip_id = r.clip_id
+        WHERE c.shortcode = ?
+        ORDER BY r.revision"#,
+        shortcode
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn consume_view(shortcode: &Shortcode, pool: &SqlitePool) -> ModResult<bool> {
+    let shortcode = shortcode.as_str();
+    let mut transaction = pool.begin().await?;
+
+    let consumed = sqlx::query!(
+        r#"UPDATE clips SET views = views + 1
+        WHERE shortcode = ? AND max_views IS NOT NULL AND views < max_views"#,
+        shortcode
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected()
+        > 0;
+
+    if consumed {
+        sqlx::query!(
+            r#"DELETE FROM clip_revisions WHERE clip_id IN (
+                SELECT clip_id FROM clips WHERE shortcode = ? AND views >= max_views
+            )"#,
+            shortcode
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            r#"DELETE FROM clip_files WHERE clip_id IN (
+                SELECT clip_id FROM clips WHERE shortcode = ? AND views >= max_views
+            )"#,
+            shortcode
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM clips WHERE shortcode = ? AND views >= max_views",
+            shortcode
+        )
+        .execute(&mut transaction)
+        .await?;
+    }
+
+    transaction.commit().await?;
+    Ok(consumed)
+}
+
+pub async fn delete_clip(shortcode: &Shortcode, pool: &SqlitePool) -> ModResult<u64> {
+    let shortcode = shortcode.as_str();
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!(
+        r#"DELETE FROM clip_revisions WHERE clip_id IN (
+            SELECT clip_id FROM clips WHERE shortcode = ?
+        )"#,
+        shortcode
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM clip_files WHERE clip_id IN (
+            SELECT clip_id FROM clips WHERE shortcode = ?
+        )"#,
+        shortcode
+    )
+    .execute(&mut transaction)
+    .await?;
+    let deleted = sqlx::query!("DELETE FROM clips WHERE shortcode = ?", shortcode)
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+
+    transaction.commit().await?;
+    Ok(deleted)
+}
+
+pub async fn get_file(shortcode: &Shortcode, pool: &SqlitePool) -> ModResult<Vec<u8>> {
+    let shortcode = shortcode.as_str();
+    Ok(sqlx::query!(
+        r#"SELECT f.data FROM clip_files f
+        JOIN clips c ON c.clip_id = f.clip_id
+        WHERE c.shortcode = ?"#,
+        shortcode
+    )
+    .fetch_one(pool)
+    .await?
+    .data)
+}
+
+pub async fn update_password(
+    shortcode: &Shortcode,
+    password: Option<String>,
+    pool: &SqlitePool,
+) -> ModResult<()> {
+    let shortcode = shortcode.as_str();
+    Ok(sqlx::query!(
+        "UPDATE clips SET password = ? WHERE shortcode = ?",
+        password,
+        shortcode
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?)
+}
+
+pub async fn save_api_key(model: model::NewApiKey, pool: &SqlitePool) -> ModResult<model::ApiKey> {
+    let _ = sqlx::query!(
+        r#"INSERT INTO api_keys (api_key, name, created, expires, scopes, owner)
+        VALUES (?, ?, ?, ?, ?, ?)"#,
+        model.api_key,
+        model.name,
+        model.created,
+        model.expires,
+        model.scopes,
+        model.owner
+    )
+    .execute(pool)
+    .await?;
+    get_api_key(model.api_key, pool).await
+}
+
+pub async fn get_api_key(api_key: Vec<u8>, pool: &SqlitePool) -> ModResult<model::ApiKey> {
+    Ok(sqlx::query_as!(
+        model::ApiKey,
+        r#"SELECT
+            name,
+            created AS "created: NaiveDateTime",
+            expires AS "expires: NaiveDateTime",
+            last_used AS "last_used: NaiveDateTime",
+            scopes,
+            owner,
+            (SELECT username FROM users WHERE user_id = owner) AS "owner_name?: String"
+        FROM api_keys WHERE api_key = ?"#,
+        api_key
+    )
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn list_api_keys(pool: &SqlitePool) -> ModResult<Vec<model::ApiKey>> {
+    Ok(sqlx::query_as!(
+        model::ApiKey,
+        r#"SELECT
+            name,
+            created AS "created: NaiveDateTime",
+            expires AS "expires: NaiveDateTime",
+            last_used AS "last_used: NaiveDateTime",
+            scopes,
+            owner,
+            (SELECT username FROM users WHERE user_id = owner) AS "owner_name?: String"
+        FROM api_keys ORDER BY name"#
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn touch_api_key(api_key: Vec<u8>, pool: &SqlitePool) -> ModResult<()> {
+    let now = Utc::now().timestamp();
+    let stale = now - LAST_USED_PRECISION_SECS;
+    Ok(sqlx::query!(
+        r#"UPDATE api_keys SET last_used = ?
+        WHERE api_key = ? AND (last_used IS NULL OR last_used < ?)"#,
+        now,
+        api_key,
+        stale
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?)
+}
+
+pub async fn revoke_api_key(name: &str, pool: &SqlitePool) -> ModResult<RevocationStatus> {
+    Ok(sqlx::query!("DELETE FROM api_keys WHERE name = ?", name)
+        .execute(pool)
+        .await
+        .map(|res| match res.rows_affected() {
+            0 => RevocationStatus::NotFound,
+            _ => RevocationStatus::Revoked,
+        })?)
+}
+
+pub async fn new_user(model: model::NewUser, pool: &SqlitePool) -> ModResult<model::User> {
+    let _ = sqlx::query!(
+        "INSERT INTO users (user_id, username, password, created) VALUES (?, ?, ?, ?)",
+        model.user_id,
+        model.username,
+        model.password,
+        model.created
+    )
+    .execute(pool)
+    .await?;
+    get_user(&model.username, pool).await
+}
+
+// NOTE Usernames are compared without case, the column is declared with COLLATE NOCASE
+pub async fn get_user(username: &str, pool: &SqlitePool) -> ModResult<model::User> {
+    Ok(sqlx::query_as!(
+        model::User,
+        r#"SELECT user_id, username, password, created AS "created: NaiveDateTime"
+        FROM users WHERE username = ?"#,
+        username
+    )
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn new_session(model: model::NewSession, pool: &SqlitePool) -> ModResult<()> {
+    Ok(sqlx::query!(
+        "INSERT INTO sessions (session_hash, user_id, created, expires) VALUES (?, ?, ?, ?)",
+        model.session_hash,
+        model.user_id,
+        model.created,
+        model.expires
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())?)
+}
+
+pub async fn get_session_user(session_hash: Vec<u8>, pool: &SqlitePool) -> ModResult<model::User> {
+    Ok(sqlx::query_as!(
+        model::User,
+        r#"SELECT u.user_id, u.username, u.password, u.created AS "created: NaiveDateTime"
+        FROM sessions s JOIN users u ON u.user_id = s.user_id
+        WHERE s.session_hash = ? AND s.expires > unixepoch()"#,
+        session_hash
+    )
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn delete_session(session_hash: Vec<u8>, pool: &SqlitePool) -> ModResult<()> {
+    Ok(
+        sqlx::query!("DELETE FROM sessions WHERE session_hash = ?", session_hash)
+            .execute(pool)
+            .await
+            .map(|_| ())?,
+    )
+}
+
+pub async fn delete_expired(pool: &SqlitePool) -> ModResult<u64> {
+    let mut transaction = pool.begin().await?;
+
+    let deleted = sqlx::query!(
+        r#"DELETE FROM clips
+        WHERE strftime('%s', 'now') > expires
+        OR (max_views IS NOT NULL AND views >= max_views)"#
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    // * Revisions and files expire along with their clip
+    sqlx::query!("DELETE FROM clip_revisions WHERE clip_id NOT IN (SELECT clip_id FROM clips)")
+        .execute(&mut transaction)
+        .await?;
+    sqlx::query!("DELETE FROM clip_files WHERE clip_id NOT IN (SELECT clip_id FROM clips)")
+        .execute(&mut transaction)
+        .await?;
+    // ? Expired sessions can't be used anymore, they're cleaned up with the clips
+    sqlx::query!("DELETE FROM sessions WHERE expires <= unixepoch()")
+        .execute(&mut transaction)
+        .await?;
+
+    transaction.commit().await?;
+    Ok(deleted)
+}
+
+pub async fn compress_stored_content(pool: &SqlitePool) -> ModResult<u64> {
+    let threshold = compression::COMPRESSION_THRESHOLD as i64;
+    let mut compressed = 0;
+
+    let mut last_clip_id = String::new();
+    loop {
+        let mut transaction = pool.begin().await?;
+        // ? length() counts characters on TEXT, casting to BLOB counts bytes like the threshold does
+        let rows = sqlx::query!(
+            r#"SELECT clip_id, content FROM clips
+            WHERE content_encoding IS NULL
+            AND length(CAST(content AS BLOB)) >= ?
+            AND clip_id > ?
+            ORDER BY clip_id
+            LIMIT ?"#,
+            threshold,
+            last_clip_id,
+            COMPRESSION_BATCH_SIZE
+        )
+        .fetch_all(&mut transaction)
+        .await?;
+        let Some(last) = rows.last() else {
+            break;
+        };
+        last_clip_id = last.clip_id.clone();
+
+        for row in rows {
+            let stored = compression::compress(row.content);
+            if stored.encoding.is_none() {
+                continue;
+            }
+            compressed += sqlx::query!(
+                r#"UPDATE clips SET content = ?, content_encoding = ?, content_compressed = ?
+                WHERE clip_id = ?"#,
+                stored.content,
+                stored.encoding,
+                stored.compressed,
+                row.clip_id
+            )
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        }
+        transaction.commit().await?;
+    }
+
+    let mut last_rowid = 0;
+    loop {
+        let mut transaction = pool.begin().await?;
+        let rows = sqlx::query!(
+            r#"SELECT rowid AS "rowid!", content FROM clip_revisions
+            WHERE content_encoding IS NULL
+            AND length(CAST(content AS BLOB)) >= ?
+            AND rowid > ?
+            ORDER BY rowid
+            LIMIT ?"#,
+            threshold,
+            last_rowid,
+            COMPRESSION_BATCH_SIZE
+        )
+        .fetch_all(&mut transaction)
+        .await?;
+        let Some(last) = rows.last() else {
+            break;
+        };
+        last_rowid = last.rowid;
+
+        for row in rows {
+            let stored = compression::compress(row.content);
+            if stored.encoding.is_none() {
+                continue;
+            }
+            compressed += sqlx::query!(
+                r#"UPDATE clip_revisions SET content = ?, content_encoding = ?, content_compressed = ?
+                WHERE rowid = ?"#,
+                stored.content,
+                stored.encoding,
+                stored.compressed,
+                row.rowid
+            )
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        }
+        transaction.commit().await?;
+    }
+
+    Ok(compressed)
+}
+
+pub async fn reindex_clips(pool: &SqlitePool) -> ModResult<u64> {
+    let mut indexed = 0;
+    let mut last_clip_id = String::new();
+    loop {
+        let mut transaction = pool.begin().await?;
+        let rows = sqlx::query_as!(
+            model::Clip,
+            "SELECT * FROM clips WHERE clip_id > ? ORDER BY clip_id LIMIT ?",
+            last_clip_id,
+            COMPRESSION_BATCH_SIZE
+        )
+        .fetch_all(&mut transaction)
+        .await?;
+        let Some(last) = rows.last() else {
+            break;
+        };
+        last_clip_id = last.clip_id.clone();
+
+        for row in rows {
+            let (shortcode, search) = search_index(row);
+            index_clip(&shortcode, &search, &mut transaction).await?;
+            indexed += 1;
+        }
+        transaction.commit().await?;
+    }
+    Ok(indexed)
+}
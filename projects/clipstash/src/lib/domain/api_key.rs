@@ -0,0 +1,159 @@
+use crate::domain::{user::UserErr, UserId, Username};
+use crate::Time;
+use rocket::http::Method;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use strum::{Display, EnumString};
+
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum ApiKeyInfoErr {
+    #[error("invalid api key scope: {0}")]
+    InvalidScope(String),
+    #[error("invalid api key name: {0}")]
+    InvalidName(String),
+    #[error("invalid api key owner: {0}")]
+    Owner(#[from] UserErr),
+}
+
+impl ApiKeyInfoErr {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidScope(_) => "invalid_api_key_scope",
+            Self::InvalidName(_) => "invalid_api_key_name",
+            Self::Owner(e) => e.code(),
+        }
+    }
+}
+
+/// * What an API key is allowed to do, `admin` grants every other scope
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Deserialize,
+    Serialize,
+    Display,
+    EnumString,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ApiScope {
+    Read,
+    Create,
+    Update,
+    Delete,
+    Admin,
+}
+
+impl ApiScope {
+    /// * Scope a request needs, the API maps every action on clips to its own method
+    pub fn for_method(method: Method) -> Self {
+        match method {
+            Method::Get | Method::Head => Self::Read,
+            Method::Post => Self::Create,
+            Method::Put | Method::Patch => Self::Update,
+            Method::Delete => Self::Delete,
+            _ => Self::Admin,
+        }
+    }
+}
+
+/// * Stored as a comma separated list, e.g. `read,create`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ApiScopes(Vec<ApiScope>);
+
+impl ApiScopes {
+    pub fn new(mut scopes: Vec<ApiScope>) -> Self {
+        scopes.sort_unstable();
+        scopes.dedup();
+        Self(scopes)
+    }
+
+    pub fn allows(&self, scope: ApiScope) -> bool {
+        self.0
+            .iter()
+            .any(|granted| *granted == scope || *granted == ApiScope::Admin)
+    }
+
+    pub fn into_inner(self) -> Vec<ApiScope> {
+        self.0
+    }
+}
+
+impl FromStr for ApiScopes {
+    type Err = ApiKeyInfoErr;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let scopes = s
+            .split(',')
+            .map(str::trim)
+            .filter(|scope| !scope.is_empty())
+            .map(|scope| {
+                ApiScope::from_str(&scope.to_lowercase())
+                    .map_err(|_| ApiKeyInfoErr::InvalidScope(scope.to_owned()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if scopes.is_empty() {
+            return Err(ApiKeyInfoErr::InvalidScope(
+                "at least one scope is required".to_owned(),
+            ));
+        }
+        Ok(Self::new(scopes))
+    }
+}
+
+impl fmt::Display for ApiScopes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scopes = self.0.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        write!(f, "{}", scopes.join(","))
+    }
+}
+
+/// * How long the recorded usage of a key may lag behind, so every API request doesn't cost a write
+pub const LAST_USED_PRECISION_SECS: i64 = 60;
+
+/// * Everything that's known about an API key except the key itself, which is only shown once when it's created
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ApiKeyInfo {
+    pub name: String,
+    pub created: Time,
+    pub expires: Option<Time>,
+    pub last_used: Option<Time>,
+    pub scopes: ApiScopes,
+    // ? Clips created with a key that belongs to a user are owned by that user
+    #[serde(default)]
+    pub owner: Option<UserId>,
+    #[serde(default)]
+    pub owner_name: Option<Username>,
+}
+
+impl ApiKeyInfo {
+    pub fn validate_name(name: &str) -> Result<(), ApiKeyInfoErr> {
+        match name.len() {
+            0 => Err(ApiKeyInfoErr::InvalidName(
+                "the name can't be empty".to_owned(),
+            )),
+            1..=64 => Ok(()),
+            _ => Err(ApiKeyInfoErr::InvalidName(
+                "the name can't be longer than 64 characters".to_owned(),
+            )),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires
+            .as_ref()
+            .is_some_and(|expires| expires.timestamp() <= chrono::Utc::now().timestamp())
+    }
+
+    /// * Whether `last_used` is too old to keep, see `LAST_USED_PRECISION_SECS`
+    pub fn last_used_is_stale(&self) -> bool {
+        self.last_used.as_ref().is_none_or(|last_used| {
+            last_used.timestamp() < chrono::Utc::now().timestamp() - LAST_USED_PRECISION_SECS
+        })
+    }
+}
@@ -0,0 +1,53 @@
+use super::ClipErr;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// * Metadata of a file uploaded as a clip, the contents are only loaded when the file is downloaded
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+pub struct Attachment {
+    name: String,
+    mime_type: String,
+    #[schema(minimum = 0)]
+    size: u64,
+}
+
+impl Attachment {
+    pub fn new(name: &str, mime_type: &str, size: u64) -> Result<Self, ClipErr> {
+        // ? Only the last path segment is kept, clients may send the full path of the file
+        let name = name
+            .rsplit(['/', '\\'])
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| !c.is_control() && *c != '"')
+            .collect::<String>();
+        let name = name.trim();
+        if name.is_empty() || name.len() > 255 {
+            return Err(ClipErr::InvalidFile(
+                "file names must have between 1 and 255 characters".to_owned(),
+            ));
+        }
+        if mime_type.parse::<rocket::http::ContentType>().is_err() {
+            return Err(ClipErr::InvalidFile(format!(
+                "invalid mime type {mime_type}"
+            )));
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            mime_type: mime_type.to_owned(),
+            size,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn mime_type(&self) -> &str {
+        &self.mime_type
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
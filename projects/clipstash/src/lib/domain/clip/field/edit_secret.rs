@@ -0,0 +1,48 @@
+use super::{ClipErr, Password};
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// * Secret given once to the creator of a clip, required to update or delete it
+/// * Only its hash is stored, so it's hashed and verified just like a `Password`
+#[derive(Clone, Default, Debug, Deserialize, Serialize)]
+pub struct EditSecret(Password);
+
+impl EditSecret {
+    pub fn new<T: Into<Option<String>>>(value: T) -> Self {
+        // ? Password::new() never fails, it only discards blank values
+        Self(Password::new(value).unwrap_or_default())
+    }
+
+    pub fn generate() -> Self {
+        let bytes: Vec<u8> = (0..24).map(|_| rand::random::<u8>()).collect();
+        Self::new(base64::encode_config(bytes, base64::URL_SAFE_NO_PAD))
+    }
+
+    pub fn into_inner(self) -> Option<String> {
+        self.0.into_inner()
+    }
+
+    pub fn hash(self) -> Result<Self, ClipErr> {
+        Ok(Self(self.0.hash()?))
+    }
+
+    /// * `self` is the stored hash, clips without one can't be edited by anyone
+    pub fn is_valid(&self, other: &Self) -> bool {
+        self.0.has_password() && self.0.is_valid(&other.0)
+    }
+}
+
+impl FromStr for EditSecret {
+    type Err = ClipErr;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(s.to_owned()))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for EditSecret {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        Ok(Self::new(field.value.to_owned()))
+    }
+}
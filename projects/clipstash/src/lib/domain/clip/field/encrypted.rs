@@ -0,0 +1,137 @@
+use super::{ClipErr, Content};
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use rand::RngCore;
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+
+/// * End-to-end encrypted clips are encrypted by the client, the server only ever stores the ciphertext
+/// * The ciphertext is the base64url encoded AES-256-GCM nonce followed by the encrypted content
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Encrypted(bool);
+
+impl Encrypted {
+    pub fn new(value: bool) -> Self {
+        Self(value)
+    }
+
+    pub fn into_inner(self) -> bool {
+        self.0
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0
+    }
+
+    /// * The server can't decrypt the content, but it can reject content that was never encrypted
+    pub fn ensure_ciphertext(&self, content: &Content) -> Result<(), ClipErr> {
+        if !self.0 {
+            return Ok(());
+        }
+        match decode(content.as_str()) {
+            Some(bytes) if bytes.len() > NONCE_LENGTH + TAG_LENGTH => Ok(()),
+            _ => Err(ClipErr::InvalidCiphertext(
+                "encrypted content must be base64url encoded AES-GCM ciphertext".to_owned(),
+            )),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for Encrypted {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        Ok(Self(bool::from_value(field)?))
+    }
+
+    // ? Unchecked checkboxes aren't sent at all
+    fn default() -> Option<Self> {
+        Some(Self(false))
+    }
+}
+
+fn encode(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode(value: &str) -> Option<Vec<u8>> {
+    base64::decode_config(value.trim(), base64::URL_SAFE_NO_PAD).ok()
+}
+
+/// * Key of an end-to-end encrypted clip, it's only ever shared in the fragment of the clip's URL
+/// * Browsers don't send the fragment to the server, so the key never leaves the clients
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClipKey([u8; KEY_LENGTH]);
+
+impl ClipKey {
+    pub fn generate() -> Self {
+        let mut key = [0; KEY_LENGTH];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self(key)
+    }
+
+    pub fn to_base64(&self) -> String {
+        encode(&self.0)
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<Content, ClipErr> {
+        let mut nonce = [0; NONCE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = Aes256Gcm::new(&self.0.into())
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
+            .map_err(|e| ClipErr::InvalidCiphertext(e.to_string()))?;
+
+        Content::new(&encode(&[nonce.as_slice(), &ciphertext].concat()))
+    }
+
+    pub fn decrypt(&self, content: &Content) -> Result<Content, ClipErr> {
+        let invalid = || ClipErr::InvalidCiphertext("the clip can't be decrypted".to_owned());
+        let bytes = decode(content.as_str()).ok_or_else(invalid)?;
+        if bytes.len() <= NONCE_LENGTH {
+            return Err(invalid());
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LENGTH);
+        let plaintext = Aes256Gcm::new(&self.0.into())
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| invalid())?;
+        Content::new(&String::from_utf8(plaintext).map_err(|_| invalid())?)
+    }
+}
+
+impl FromStr for ClipKey {
+    type Err = ClipErr;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        decode(s)
+            .and_then(|key| <[u8; KEY_LENGTH]>::try_from(key).ok())
+            .map(Self)
+            .ok_or_else(|| ClipErr::InvalidCiphertext("invalid clip key".to_owned()))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn encrypted_content_only_decrypts_with_its_key() {
+        let key = ClipKey::generate();
+        let ciphertext = key.encrypt("a secret").unwrap();
+
+        assert_ne!(ciphertext.as_str(), "a secret");
+        assert!(Encrypted::new(true).ensure_ciphertext(&ciphertext).is_ok());
+        let key = ClipKey::from_str(&key.to_base64()).unwrap();
+        assert_eq!(key.decrypt(&ciphertext).unwrap().as_str(), "a secret");
+        assert!(ClipKey::generate().decrypt(&ciphertext).is_err());
+
+        let plaintext = Content::new("a secret").unwrap();
+        assert!(Encrypted::new(true).ensure_ciphertext(&plaintext).is_err());
+        assert!(Encrypted::new(false).ensure_ciphertext(&plaintext).is_ok());
+    }
+}
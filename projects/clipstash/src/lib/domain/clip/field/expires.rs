@@ -2,10 +2,11 @@ use std::str::FromStr;
 
 use super::ClipErr;
 use crate::domain::time::Time;
+use chrono::{Duration, Utc};
 use rocket::form::{self, FromFormField, ValueField};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Expires(Option<Time>);
 impl Expires {
     pub fn new<T: Into<Option<Time>>>(expires: T) -> Self {
@@ -15,6 +16,47 @@ impl Expires {
     pub fn into_inner(self) -> Option<Time> {
         self.0
     }
+
+    /// * Rejects expiration times that already passed, new and updated clips must be checked with this
+    pub fn ensure_future(&self) -> Result<(), ClipErr> {
+        match &self.0 {
+            Some(time) if time.timestamp() <= Utc::now().timestamp() => Err(ClipErr::InvalidDate(
+                format!("{} is in the past", time.clone().into_inner().to_rfc3339()),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn parse(s: &str) -> Result<Self, ClipErr> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Ok(Self(None));
+        }
+
+        let time = match Self::parse_relative(s) {
+            Some(duration) => Utc::now()
+                .checked_add_signed(duration)
+                .ok_or_else(|| ClipErr::InvalidDate(format!("{s} is too far in the future")))?
+                .into(),
+            None => Time::from_str(s)?,
+        };
+        Ok(Self(Some(time)))
+    }
+
+    /// * Parses relative durations such as `30s`, `10m`, `2h`, `7d` or `1w`
+    pub(crate) fn parse_relative(s: &str) -> Option<Duration> {
+        let unit_at = s.len().checked_sub(1).filter(|&i| s.is_char_boundary(i))?;
+        let (amount, unit) = s.split_at(unit_at);
+        let amount = amount.parse::<i64>().ok()?;
+        match unit {
+            "s" => Duration::try_seconds(amount),
+            "m" => Duration::try_minutes(amount),
+            "h" => Duration::try_hours(amount),
+            "d" => Duration::try_days(amount),
+            "w" => Duration::try_weeks(amount),
+            _ => None,
+        }
+    }
 }
 
 impl Default for Expires {
@@ -26,18 +68,22 @@ impl Default for Expires {
 impl FromStr for Expires {
     type Err = ClipErr;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // NOTE transpose() maps Ok(Some(x)) to Some(Ok(x))
-        // NOTE .then() is lazily evaluated so it's preferrable to .then_some() as Time::from_str() is a function call
-        let time = (!s.is_empty()).then(|| Time::from_str(s)).transpose()?;
-        Ok(Self(time))
-    }
-    // ? from_str() is equivalent to this:
-    // * let time = if !s.is_empty() {
-    // *     Some(Time::from_str(s)?)
-    // * } else {
-    // *     None
-    // * };
-    // * Ok(Self(time))
+        let expires = Self::parse(s)?;
+        expires.ensure_future()?;
+        Ok(expires)
+    }
+}
+
+// ? Deserializing through parse() lets JSON clients send the same formats as the form and the CLI
+// ? Past dates are still accepted here since existing clips and revisions are deserialized as well
+impl<'de> Deserialize<'de> for Expires {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let expires = Option::<String>::deserialize(deserializer)?;
+        expires
+            .as_deref()
+            .map_or(Ok(Self(None)), Self::parse)
+            .map_err(serde::de::Error::custom)
+    }
 }
 
 #[rocket::async_trait]
@@ -51,3 +97,28 @@ impl<'r> FromFormField<'r> for Expires {
         Ok(res.map_err(|e| form::Error::validation(format!("{}", e)))?)
     }
 }
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn parses_relative_and_absolute_expiry() {
+        let in_ten_minutes = Expires::from_str("10m").unwrap().into_inner().unwrap();
+        let delta = in_ten_minutes.timestamp() - Utc::now().timestamp();
+        assert!((595..=600).contains(&delta));
+        assert!(Expires::from_str("2999-01-01T12:30:00+02:00").is_ok());
+        assert!(Expires::from_str("2999-01-01").is_ok());
+        assert!(matches!(
+            Expires::from_str("-5m"),
+            Err(ClipErr::InvalidDate(_))
+        ));
+        assert!(matches!(
+            Expires::from_str("2000-01-01T00:00:00Z"),
+            Err(ClipErr::InvalidDate(_))
+        ));
+        // ? Past dates are only rejected once they're checked with ensure_future()
+        let past: Expires = serde_json::from_str(r#""2000-01-01""#).unwrap();
+        assert!(past.ensure_future().is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use super::ClipErr;
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, str::FromStr};
+
+/// * Content larger than this is neither detected nor highlighted, highlighting it on every view would be too slow
+pub const MAX_HIGHLIGHTED_SIZE: usize = 256 * 1024;
+
+/// * Languages that can be picked for a clip, as `(token, display name)` pairs
+/// * Tokens are the names or file extensions the highlighter looks syntaxes up with
+pub const LANGUAGES: &[(&str, &str)] = &[
+    ("c", "C"),
+    ("c++", "C++"),
+    ("cs", "C#"),
+    ("css", "CSS"),
+    ("diff", "Diff"),
+    ("go", "Go"),
+    ("html", "HTML"),
+    ("java", "Java"),
+    ("javascript", "JavaScript"),
+    ("json", "JSON"),
+    ("markdown", "Markdown"),
+    ("php", "PHP"),
+    ("python", "Python"),
+    ("ruby", "Ruby"),
+    ("rust", "Rust"),
+    ("sh", "Shell"),
+    ("sql", "SQL"),
+    ("xml", "XML"),
+    ("yaml", "YAML"),
+];
+
+/// * Snippets that are typical of a language, but that can show up in other languages as well
+const MARKERS: &[(&str, &[&str])] = &[
+    (
+        "rust",
+        &[
+            "fn main(",
+            "let mut ",
+            "impl ",
+            "pub fn ",
+            "use std::",
+            "println!(",
+            "&self",
+        ],
+    ),
+    (
+        "go",
+        &[
+            "package main",
+            "func main()",
+            "fmt.Println",
+            "func (",
+            ":= ",
+            "import (",
+        ],
+    ),
+    (
+        "java",
+        &[
+            "public static void",
+            "public class ",
+            "System.out.println",
+            "@Override",
+        ],
+    ),
+    (
+        "c++",
+        &[
+            "#include <iostream>",
+            "std::cout",
+            "std::vector",
+            "namespace ",
+            "template <",
+        ],
+    ),
+    (
+        "c",
+        &[
+            "#include <stdio.h>",
+            "#include <stdlib.h>",
+            "#include <string.h>",
+            "printf(",
+            "malloc(",
+        ],
+    ),
+    (
+        "python",
+        &["def ", "elif ", "self.", "__init__", "__name__", "):\n"],
+    ),
+    (
+        "javascript",
+        &[
+            "function ",
+            "const ",
+            "=> {",
+            "console.log",
+            "document.",
+            "require(",
+        ],
+    ),
+    (
+        "sql",
+        &[
+            "SELECT ",
+            "INSERT INTO ",
+            "CREATE TABLE ",
+            " FROM ",
+            " WHERE ",
+        ],
+    ),
+];
+
+/// * Language of the clip's content, used for syntax highlighting
+/// * `None` is plain text
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Language(Option<String>);
+
+impl Language {
+    pub fn new<T: Into<Option<String>>>(value: T) -> Result<Self, ClipErr> {
+        let Some(value) = value.into() else {
+            return Ok(Self(None));
+        };
+
+        let value = value.trim().to_lowercase();
+        if value.is_empty() {
+            return Ok(Self(None));
+        }
+        match LANGUAGES.iter().find(|(token, _)| *token == value) {
+            Some((token, _)) => Ok(Self(Some((*token).to_owned()))),
+            None => Err(ClipErr::InvalidLanguage(value)),
+        }
+    }
+
+    pub fn into_inner(self) -> Option<String> {
+        self.0
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// * Guesses the language of the most common languages, content that's ambiguous or too large is left as plain text
+    pub fn detect(content: &str) -> Self {
+        if content.len() > MAX_HIGHLIGHTED_SIZE {
+            return Self(None);
+        }
+        let trimmed = content.trim_start();
+        let first_line = trimmed.lines().next().unwrap_or_default();
+        let starts_with = |prefix: &str| {
+            trimmed
+                .get(..prefix.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+        };
+
+        // * Shebangs, declarations and valid JSON are unambiguous on their own
+        let token = if first_line.starts_with("#!") {
+            if first_line.contains("python") {
+                "python"
+            } else if first_line.contains("node") {
+                "javascript"
+            } else if first_line.contains("ruby") {
+                "ruby"
+            } else {
+                "sh"
+            }
+        } else if trimmed.starts_with("<?php") {
+            "php"
+        } else if trimmed.starts_with("<?xml") {
+            "xml"
+        } else if starts_with("<!doctype html") || starts_with("<html") {
+            "html"
+        } else if (trimmed.starts_with('{') || trimmed.starts_with('['))
+            && serde_json::from_str::<serde_json::Value>(content).is_ok()
+        {
+            "json"
+        } else if first_line.starts_with("diff --git") || first_line.starts_with("--- ") {
+            "diff"
+        } else {
+            return Self::from_markers(content);
+        };
+        Self(Some(token.to_owned()))
+    }
+
+    // ? A single marker such as `const ` or `:= ` is too weak, so at least two are needed and the other languages must have clearly fewer
+    fn from_markers(content: &str) -> Self {
+        let mut scores = MARKERS
+            .iter()
+            .map(|(token, markers)| {
+                let found = markers
+                    .iter()
+                    .filter(|marker| content.contains(*marker))
+                    .count();
+                (*token, found)
+            })
+            .collect::<Vec<_>>();
+        scores.sort_by_key(|(_, found)| Reverse(*found));
+        match scores.as_slice() {
+            [(token, best), (_, next), ..] if *best >= 2 && *best > next * 2 => {
+                Self(Some((*token).to_owned()))
+            }
+            _ => Self(None),
+        }
+    }
+}
+
+impl FromStr for Language {
+    type Err = ClipErr;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s.to_owned())
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for Language {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        let res = Self::from_str(field.value);
+        Ok(res.map_err(|e| form::Error::validation(format!("{e}")))?)
+    }
+
+    // ? Leaving the field out of the form lets the language be detected
+    fn default() -> Option<Self> {
+        Some(Self(None))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn detects_only_unambiguous_languages() {
+        let detect = |content: &str| Language::detect(content).into_inner();
+
+        let rust = "use std::io;\n\nfn main() {\n    let mut line = String::new();\n}\n";
+        assert_eq!(detect(rust).as_deref(), Some("rust"));
+        assert_eq!(
+            detect("#!/usr/bin/env python3\nprint(1)").as_deref(),
+            Some("python")
+        );
+        assert_eq!(detect(r#"{"a": [1, 2]}"#).as_deref(), Some("json"));
+        assert_eq!(
+            detect("<!DOCTYPE html>\n<p>hi</p>").as_deref(),
+            Some("html")
+        );
+
+        // * Prose that happens to contain a marker stays plain text
+        assert_eq!(detect("wrap it in a <div> and x := 1"), None);
+        assert_eq!(detect("the const keyword, as in `const x = 1`"), None);
+        // * Markers of two languages in the same amount are ambiguous
+        assert_eq!(
+            detect("fn main() {}\nfunc main() {}\nlet mut x\npackage main"),
+            None
+        );
+
+        let large = rust.repeat(MAX_HIGHLIGHTED_SIZE / rust.len() + 1);
+        assert_eq!(detect(&large), None);
+    }
+}
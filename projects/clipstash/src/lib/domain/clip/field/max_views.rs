@@ -0,0 +1,59 @@
+use super::ClipErr;
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// * Amount of times a clip can be viewed before it's deleted, `None` means unlimited
+/// * `1` makes it a "burn after reading" clip
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct MaxViews(Option<u32>);
+
+impl MaxViews {
+    pub fn new<T: Into<Option<u32>>>(value: T) -> Result<Self, ClipErr> {
+        match value.into() {
+            Some(0) => Err(ClipErr::InvalidMaxViews(
+                "a clip must be viewable at least once".to_owned(),
+            )),
+            value => Ok(Self(value)),
+        }
+    }
+
+    pub fn burn_after_reading() -> Self {
+        Self(Some(1))
+    }
+
+    pub fn into_inner(self) -> Option<u32> {
+        self.0
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+impl FromStr for MaxViews {
+    type Err = ClipErr;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "" => Ok(Self(None)),
+            "burn" => Ok(Self::burn_after_reading()),
+            s => Self::new(
+                s.parse::<u32>()
+                    .map_err(|e| ClipErr::InvalidMaxViews(e.to_string()))?,
+            ),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for MaxViews {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        let res = Self::from_str(field.value);
+        Ok(res.map_err(|e| form::Error::validation(format!("{e}")))?)
+    }
+
+    // ? The field is optional in the form, a missing value means unlimited views
+    fn default() -> Option<Self> {
+        Some(Self(None))
+    }
+}
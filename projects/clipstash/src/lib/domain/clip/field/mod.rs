@@ -3,13 +3,16 @@ mod clip_id;
 pub use clip_id::ClipId;
 
 mod shortcode;
-pub use shortcode::Shortcode;
+pub use shortcode::{Shortcode, ShortcodeGenerator};
+
+mod vanity_shortcode;
+pub use vanity_shortcode::VanityShortcode;
 
 mod content;
 pub use content::Content;
 
 mod title;
-pub use title::Title;
+pub use title::{Title, TitleLimit};
 
 mod posted;
 pub use posted::Posted;
@@ -22,3 +25,21 @@ pub use password::Password;
 
 mod hits;
 pub use hits::Hits;
+
+mod edit_secret;
+pub use edit_secret::EditSecret;
+
+mod max_views;
+pub use max_views::MaxViews;
+
+mod attachment;
+pub use attachment::Attachment;
+
+mod upload;
+pub use upload::Upload;
+
+mod encrypted;
+pub use encrypted::{ClipKey, Encrypted};
+
+mod language;
+pub use language::{Language, LANGUAGES, MAX_HIGHLIGHTED_SIZE};
@@ -1,8 +1,16 @@
 use super::ClipErr;
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
 use rocket::form::{self, FromFormField, ValueField};
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
+// NOTE Hash of a random password that was thrown away, made with the same parameters as `Password::hash()`
+const DUMMY_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$5VY7Kmb4KkAlTxEojILfFQ$h/nOxc7hSJ/d8cFh1mZF44sDs1qmog0ia9egc80iaiA";
+
 #[derive(Clone, Default, Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
 pub struct Password(Option<String>);
 
@@ -26,8 +34,50 @@ impl Password {
         self.0.is_some()
     }
 
+    /// * Hashes the password with Argon2 and a random salt, the result is a PHC string that can be stored
+    /// * An empty password stays empty
+    pub fn hash(self) -> Result<Self, ClipErr> {
+        let Some(password) = self.0 else {
+            return Ok(Self(None));
+        };
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|e| ClipErr::PasswordHash(e.to_string()))?;
+        Ok(Self(Some(hash.to_string())))
+    }
+
+    /// * Stored password that no password is valid for, checking it takes as long as checking a real one
+    /// * Used when there's nothing to check against, so the time taken doesn't reveal it
+    pub fn dummy() -> Self {
+        Self(Some(DUMMY_HASH.to_owned()))
+    }
+
+    /// * Passwords stored before hashing was introduced are plaintext and must be hashed again
+    pub fn needs_rehash(&self) -> bool {
+        matches!(&self.0, Some(password) if PasswordHash::new(password).is_err())
+    }
+
+    /// * `self` is the stored password, `other` is the password provided by the user
     pub fn is_valid(&self, other: &Self) -> bool {
-        !self.has_password() || self == other
+        let Some(stored) = &self.0 else {
+            return true;
+        };
+        let Some(provided) = &other.0 else {
+            return false;
+        };
+
+        match PasswordHash::new(stored) {
+            // NOTE verify_password() compares the hashes in constant time
+            Ok(hash) => Argon2::default()
+                .verify_password(provided.as_bytes(), &hash)
+                .is_ok(),
+            // ? Legacy plaintext password, compared in constant time to avoid leaking it through timing
+            Err(_) => {
+                use subtle::ConstantTimeEq;
+                stored.as_bytes().ct_eq(provided.as_bytes()).into()
+            }
+        }
     }
 }
 
@@ -45,3 +95,28 @@ impl<'r> FromFormField<'r> for Password {
         Ok(res.map_err(|e| form::Error::validation(format!("{e}")))?)
     }
 }
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use argon2::Params;
+
+    #[test]
+    fn dummy_hash_is_as_slow_as_a_real_one() {
+        let dummy = PasswordHash::new(DUMMY_HASH).unwrap();
+        let hashed = Password::new("hunter22".to_owned())
+            .unwrap()
+            .hash()
+            .unwrap();
+        let hashed = hashed.into_inner().unwrap();
+        let hashed = PasswordHash::new(&hashed).unwrap();
+
+        assert_eq!(dummy.algorithm, hashed.algorithm);
+        assert_eq!(dummy.version, hashed.version);
+        assert_eq!(
+            Params::try_from(&dummy).unwrap(),
+            Params::try_from(&hashed).unwrap()
+        );
+        assert!(!Password::dummy().is_valid(&Password::new("hunter22".to_owned()).unwrap()));
+    }
+}
@@ -1,29 +1,62 @@
 use super::ClipErr;
 use derive_more::From;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::str::FromStr;
 
-#[derive(
-    Debug, Clone, Hash, PartialEq, Eq, Deserialize, Serialize, From, UriDisplayPath, UriDisplayQuery,
-)]
+/// * Characters allowed in any shortcode, they don't need to be escaped in URLs
+const ALLOWED_CHARS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-_";
+const MAX_LENGTH: usize = 64;
+/// * Shortcodes that would be confused with the app's own routes
+const RESERVED: &[&str] = &[
+    "api",
+    "raw",
+    "static",
+    "clip",
+    "clips",
+    "key",
+    "keys",
+    "new",
+    "delete",
+    "diff",
+    "revisions",
+    "admin",
+    "login",
+    "logout",
+    "metrics",
+    "health",
+];
+
+// NOTE From<String> and From<&str> don't validate, they're meant for shortcodes that were already stored
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, From, UriDisplayPath, UriDisplayQuery)]
 pub struct Shortcode(String);
 
 impl Shortcode {
+    /// * Generates a random shortcode with the default generator
     pub fn new() -> Self {
-        use rand::prelude::*;
-        let allowed_chars = ['a', 'b', 'c', 'd', '1', '2', '3', '4'];
+        ShortcodeGenerator::default().generate()
+    }
 
-        let mut rng = thread_rng();
-        // NOTE with_capacity generates a string with a maximum capacity of 10, more efficient than String::new()
-        let mut shortcode = String::with_capacity(10);
-        for _ in 0..10 {
-            shortcode.push(
-                *allowed_chars
-                    .choose(&mut rng)
-                    .expect("sampling array should have values"),
-            );
+    /// * Shortcodes chosen by users follow stricter rules than generated ones
+    pub fn vanity(shortcode: &str) -> Result<Self, ClipErr> {
+        let shortcode = Self::from_str(shortcode)?;
+        if !(4..=32).contains(&shortcode.0.len()) {
+            return Err(ClipErr::InvalidShortcode(
+                "custom shortcodes must have between 4 and 32 characters".to_owned(),
+            ));
+        }
+        if shortcode.is_reserved() {
+            return Err(ClipErr::InvalidShortcode(format!(
+                "'{}' is reserved",
+                shortcode.0
+            )));
         }
-        Self(shortcode)
+        Ok(shortcode)
+    }
+
+    pub fn is_reserved(&self) -> bool {
+        RESERVED
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(&self.0))
     }
 
     pub fn as_str(&self) -> &str {
@@ -56,17 +89,123 @@ impl From<&str> for Shortcode {
 impl FromStr for Shortcode {
     type Err = ClipErr;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s.len() > MAX_LENGTH {
+            return Err(ClipErr::InvalidShortcode(format!(
+                "shortcodes must have between 1 and {MAX_LENGTH} characters"
+            )));
+        }
+        if let Some(c) = s.chars().find(|c| !ALLOWED_CHARS.contains(*c)) {
+            return Err(ClipErr::InvalidShortcode(format!(
+                "'{c}' is not allowed in shortcodes"
+            )));
+        }
         Ok(Self(s.to_owned()))
     }
 }
 
+impl<'de> Deserialize<'de> for Shortcode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let shortcode = String::deserialize(deserializer)?;
+        Self::from_str(&shortcode).map_err(serde::de::Error::custom)
+    }
+}
+
 use rocket::{request::FromParam, UriDisplayPath, UriDisplayQuery};
 
 impl<'r> FromParam<'r> for Shortcode {
     type Error = &'r str;
 
+    // ? Rejected params are forwarded by rocket, so malformed shortcodes end up as a 422 instead of reaching the database
     fn from_param(param: &'r str) -> Result<Self, Self::Error> {
-        // ? Equivalent to: Ok(Shortcode::from(param))
-        Ok(param.into())
+        Self::from_str(param).map_err(|_| param)
+    }
+}
+
+/// * Generates random shortcodes, the alphabet and length are configurable
+/// * The default of 10 alphanumeric characters gives close to 60 bits of entropy
+#[derive(Clone, Debug)]
+pub struct ShortcodeGenerator {
+    alphabet: Vec<char>,
+    length: usize,
+}
+
+impl ShortcodeGenerator {
+    pub fn new(alphabet: &str, length: usize) -> Result<Self, ClipErr> {
+        let mut chars = alphabet.chars().collect::<Vec<_>>();
+        chars.sort_unstable();
+        chars.dedup();
+
+        if let Some(c) = chars.iter().find(|c| !ALLOWED_CHARS.contains(**c)) {
+            return Err(ClipErr::InvalidShortcode(format!(
+                "'{c}' is not allowed in shortcodes"
+            )));
+        }
+        if chars.len() < 2 {
+            return Err(ClipErr::InvalidShortcode(
+                "the alphabet needs at least 2 distinct characters".to_owned(),
+            ));
+        }
+        if !(4..=MAX_LENGTH).contains(&length) {
+            return Err(ClipErr::InvalidShortcode(format!(
+                "generated shortcodes must have between 4 and {MAX_LENGTH} characters"
+            )));
+        }
+        Ok(Self {
+            alphabet: chars,
+            length,
+        })
+    }
+
+    pub fn generate(&self) -> Shortcode {
+        use rand::prelude::*;
+
+        let mut rng = thread_rng();
+        loop {
+            let shortcode = (0..self.length)
+                .map(|_| {
+                    *self
+                        .alphabet
+                        .choose(&mut rng)
+                        .expect("alphabet should have values")
+                })
+                .collect::<String>();
+            let shortcode = Shortcode(shortcode);
+            if !shortcode.is_reserved() {
+                return shortcode;
+            }
+        }
+    }
+}
+
+impl Default for ShortcodeGenerator {
+    fn default() -> Self {
+        Self::new(&ALLOWED_CHARS.replace(['-', '_'], ""), 10)
+            .expect("default shortcode alphabet is valid")
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn shortcodes_are_validated() {
+        assert!(Shortcode::from_str("abc-DEF_123").is_ok());
+        assert!(Shortcode::from_str("").is_err());
+        assert!(Shortcode::from_str("a/b").is_err());
+        assert!(serde_json::from_str::<Shortcode>(r#""not valid""#).is_err());
+    }
+
+    #[test]
+    fn generator_uses_configured_alphabet_and_length() {
+        assert!(ShortcodeGenerator::new("a", 10).is_err());
+        assert!(ShortcodeGenerator::new("ab/", 10).is_err());
+        assert!(ShortcodeGenerator::new("ab", 2).is_err());
+
+        let generator = ShortcodeGenerator::new("xyz", 16).unwrap();
+        let shortcode = generator.generate();
+        assert_eq!(shortcode.as_str().len(), 16);
+        assert!(shortcode.as_str().chars().all(|c| "xyz".contains(c)));
+        assert_eq!(ShortcodeGenerator::default().generate().as_str().len(), 10);
     }
 }
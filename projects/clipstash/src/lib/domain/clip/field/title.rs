@@ -1,13 +1,17 @@
 use std::str::FromStr;
 
 use super::ClipErr;
+use derive_more::From;
 use rocket::form::{self, FromFormField, ValueField};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+// NOTE From<Option<String>> doesn't validate, it's meant for titles that were already stored
+// ? Stored titles stay readable when the limit is lowered
+#[derive(Debug, Default, Clone, Deserialize, Serialize, From)]
 pub struct Title(Option<String>);
 
 impl Title {
+    // NOTE The length is checked by the service against `TitleLimit`, the limit is part of the app's state
     pub fn new<T: Into<Option<String>>>(value: T) -> Result<Self, ClipErr> {
         let value: Option<String> = value.into();
 
@@ -17,8 +21,7 @@ impl Title {
 
         match value.trim().len() {
             0 => Ok(Self(None)),
-            1..=200 => Ok(Self(Some(value.to_owned()))),
-            _ => Err(ClipErr::InvalidTitle("title too large".to_owned())),
+            _ => Ok(Self(Some(value.to_owned()))),
         }
     }
 
@@ -49,3 +52,36 @@ impl<'r> FromFormField<'r> for Title {
         Ok(res.map_err(|e| form::Error::validation(format!("{}", e)))?)
     }
 }
+
+/// * Longest title a clip can be given, set by the `max_title_length` setting
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TitleLimit(usize);
+
+impl TitleLimit {
+    pub const DEFAULT: usize = 200;
+
+    pub fn new(length: usize) -> Result<Self, ClipErr> {
+        match length {
+            0 => Err(ClipErr::InvalidTitle(
+                "the limit must be at least 1".to_owned(),
+            )),
+            _ => Ok(Self(length)),
+        }
+    }
+
+    pub fn check(&self, title: &Title) -> Result<(), ClipErr> {
+        match &title.0 {
+            Some(title) if title.trim().len() > self.0 => Err(ClipErr::InvalidTitle(format!(
+                "title too large, titles have at most {} characters",
+                self.0
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for TitleLimit {
+    fn default() -> Self {
+        Self(Self::DEFAULT)
+    }
+}
@@ -0,0 +1,81 @@
+use super::{Attachment, ClipErr};
+use rocket::{
+    data::ToByteUnit,
+    form::{self, DataField, FromFormField, ValueField},
+    http::ContentType,
+};
+
+/// * File sent in a multipart form when creating a clip, `None` when no file was picked
+#[derive(Clone, Debug, Default)]
+pub struct Upload(Option<(Attachment, Vec<u8>)>);
+
+impl Upload {
+    pub fn new(attachment: Attachment, data: Vec<u8>) -> Self {
+        Self(Some((attachment, data)))
+    }
+
+    pub fn from_bytes(name: &str, mime_type: Option<&str>, data: Vec<u8>) -> Result<Self, ClipErr> {
+        let mime_type = match mime_type {
+            Some(mime_type) if mime_type != "application/octet-stream" => mime_type.to_owned(),
+            // ? Generic types are refined with the file's extension when possible
+            _ => name
+                .rsplit_once('.')
+                .and_then(|(_, extension)| ContentType::from_extension(extension))
+                .unwrap_or(ContentType::Binary)
+                .to_string(),
+        };
+        let attachment = Attachment::new(name, &mime_type, data.len() as u64)?;
+        Ok(Self::new(attachment, data))
+    }
+
+    pub fn attachment(&self) -> Option<&Attachment> {
+        self.0.as_ref().map(|(attachment, _)| attachment)
+    }
+
+    pub fn into_inner(self) -> Option<(Attachment, Vec<u8>)> {
+        self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for Upload {
+    // ? A file input without a file is sent as an empty text value by some clients
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        match field.value.is_empty() {
+            true => Ok(Self(None)),
+            false => Err(form::Error::validation(
+                "files must be uploaded as multipart form data",
+            ))?,
+        }
+    }
+
+    async fn from_data(field: DataField<'r, '_>) -> form::Result<'r, Self> {
+        let limit = field
+            .request
+            .limits()
+            .get("file")
+            .unwrap_or_else(|| 1.mebibytes());
+        let bytes = field.data.open(limit).into_bytes().await?;
+        if !bytes.is_complete() {
+            Err(form::Error::validation(format!(
+                "files can't be larger than {limit}"
+            )))?;
+        }
+        // NOTE The raw name is sanitized by Attachment::new(), rocket's sanitized name drops the extension
+        let name = field
+            .file_name
+            .map(|name| name.dangerous_unsafe_unsanitized_raw().as_str())
+            .unwrap_or_default();
+        if bytes.is_empty() && name.is_empty() {
+            return Ok(Self(None));
+        }
+
+        let mime_type = field.content_type.to_string();
+        let res = Self::from_bytes(name, Some(&mime_type), bytes.into_inner());
+        Ok(res.map_err(|e| form::Error::validation(format!("{e}")))?)
+    }
+
+    fn default() -> Option<Self> {
+        Some(Self(None))
+    }
+}
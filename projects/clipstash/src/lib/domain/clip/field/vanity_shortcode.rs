@@ -0,0 +1,68 @@
+use super::{ClipErr, Shortcode};
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// * Shortcode picked by the user when creating a clip, `None` generates a random one
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(try_from = "Option<String>")]
+pub struct VanityShortcode(Option<Shortcode>);
+
+impl VanityShortcode {
+    pub fn new<T: Into<Option<String>>>(value: T) -> Result<Self, ClipErr> {
+        match value.into() {
+            Some(value) if !value.trim().is_empty() => {
+                Ok(Self(Some(Shortcode::vanity(value.trim())?)))
+            }
+            _ => Ok(Self(None)),
+        }
+    }
+
+    pub fn into_inner(self) -> Option<Shortcode> {
+        self.0
+    }
+}
+
+impl TryFrom<Option<String>> for VanityShortcode {
+    type Error = ClipErr;
+    fn try_from(value: Option<String>) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl FromStr for VanityShortcode {
+    type Err = ClipErr;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s.to_owned())
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for VanityShortcode {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        let res = Self::from_str(field.value);
+        Ok(res.map_err(|e| form::Error::validation(format!("{e}")))?)
+    }
+
+    fn default() -> Option<Self> {
+        Some(Self(None))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn vanity_shortcodes_are_validated() {
+        assert!(matches!(
+            VanityShortcode::from_str("RAW"),
+            Err(ClipErr::InvalidShortcode(_))
+        ));
+        assert!(VanityShortcode::from_str("abc").is_err());
+        assert!(VanityShortcode::from_str("")
+            .unwrap()
+            .into_inner()
+            .is_none());
+    }
+}
@@ -1,5 +1,6 @@
 pub mod field;
 
+use crate::domain::UserId;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -7,12 +8,28 @@ use thiserror::Error;
 pub enum ClipErr {
     #[error("invalid password: {0}")]
     InvalidPassword(String),
+    #[error("password hash error: {0}")]
+    PasswordHash(String),
     #[error("invalid title: {0}")]
     InvalidTitle(String),
     #[error("empty content")]
     EmptyContent,
+    #[error("invalid max views: {0}")]
+    InvalidMaxViews(String),
+    #[error("unsupported language: {0}")]
+    InvalidLanguage(String),
+    #[error("invalid file: {0}")]
+    InvalidFile(String),
+    #[error("invalid ciphertext: {0}")]
+    InvalidCiphertext(String),
+    #[error("invalid shortcode: {0}")]
+    InvalidShortcode(String),
+    #[error("content decoding error: {0}")]
+    ContentEncoding(String),
     #[error("invalid date: {0}")]
     InvalidDate(String),
+    #[error("invalid query: {0}")]
+    InvalidQuery(String),
     #[error("date parse error: {0}")]
     DateParse(#[from] chrono::ParseError),
     #[error("id parse error: {0}")]
@@ -21,6 +38,29 @@ pub enum ClipErr {
     Hits(#[from] std::num::TryFromIntError),
 }
 
+impl ClipErr {
+    /// * Machine readable code of the error, API clients can rely on it not changing
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidPassword(_) => "invalid_password",
+            Self::PasswordHash(_) => "password_hash",
+            Self::InvalidTitle(_) => "invalid_title",
+            Self::EmptyContent => "empty_content",
+            Self::InvalidMaxViews(_) => "invalid_max_views",
+            Self::InvalidLanguage(_) => "invalid_language",
+            Self::InvalidFile(_) => "invalid_file",
+            Self::InvalidCiphertext(_) => "invalid_ciphertext",
+            Self::InvalidShortcode(_) => "invalid_shortcode",
+            Self::ContentEncoding(_) => "content_encoding",
+            Self::InvalidDate(_) => "invalid_date",
+            Self::InvalidQuery(_) => "invalid_query",
+            Self::DateParse(_) => "invalid_date_format",
+            Self::Id(_) => "invalid_id",
+            Self::Hits(_) => "invalid_hits",
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Clip {
     // NOTE serde(skip) is used to prevent the field from being serialized on the Json() call
@@ -28,9 +68,29 @@ pub struct Clip {
     pub clip_id: field::ClipId,
     pub shortcode: field::Shortcode,
     pub content: field::Content,
+    // ? Encrypted clips hold ciphertext in `content`, only clients with the key can read them
+    #[serde(default)]
+    pub encrypted: field::Encrypted,
     pub title: field::Title,
     pub posted: field::Posted,
     pub expires: field::Expires,
+    #[serde(default)]
+    pub language: field::Language,
+    // ? Set for clips created from a file, the content holds the file name in that case
+    #[serde(default)]
+    pub attachment: Option<field::Attachment>,
+    // ? The stored password is a hash, it's only used to validate the user's password
+    #[serde(skip)]
     pub password: field::Password,
     pub hits: field::Hits,
+    #[serde(default)]
+    pub max_views: field::MaxViews,
+    // ? Only counted for clips with a view limit, unlike `hits` it's written on every read
+    #[serde(default)]
+    pub views: field::Hits,
+    #[serde(skip)]
+    pub edit_secret: field::EditSecret,
+    // ? Clips created by a logged in user, or with one of their API keys, can be managed without the edit secret
+    #[serde(skip)]
+    pub owner: Option<UserId>,
 }
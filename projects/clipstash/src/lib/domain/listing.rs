@@ -0,0 +1,193 @@
+use crate::domain::{clip::field, Clip};
+use crate::ClipErr;
+use rocket::FromFormField;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use strum::{Display, EnumString};
+
+pub const DEFAULT_PAGE_SIZE: u32 = 20;
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+/// * Order of listed clips, ties are broken by the clip's ID so every order is stable
+/// * `hits` changes as clips are read, so paging through it may skip or repeat a clip
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+    Display,
+    EnumString,
+    FromFormField,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ClipOrder {
+    #[default]
+    Newest,
+    Oldest,
+    Hits,
+}
+
+impl ClipOrder {
+    /// * Clips are always listed by descending sort key, oldest first is the negated timestamp
+    /// * Must match the sort key computed by the listing query
+    pub fn sort_key(&self, clip: &Clip) -> i64 {
+        let posted = clip.posted.clone().into_inner().timestamp();
+        match self {
+            Self::Newest => posted,
+            Self::Oldest => -posted,
+            Self::Hits => i64::try_from(clip.hits.clone().into_inner()).unwrap_or(i64::MAX),
+        }
+    }
+}
+
+/// * Position right after the last clip of a page, clients should treat it as opaque
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct Cursor {
+    pub order: ClipOrder,
+    pub sort_key: i64,
+    pub clip_id: String,
+}
+
+impl Cursor {
+    pub fn after(order: ClipOrder, clip: &Clip) -> Self {
+        Self {
+            order,
+            sort_key: order.sort_key(clip),
+            clip_id: clip.clip_id.clone().into_inner().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cursor = format!("{}:{}:{}", self.order, self.sort_key, self.clip_id);
+        f.write_str(&base64::encode_config(cursor, base64::URL_SAFE_NO_PAD))
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = ClipErr;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ClipErr::InvalidQuery("invalid cursor".to_owned());
+        let decoded = base64::decode_config(s, base64::URL_SAFE_NO_PAD).map_err(|_| invalid())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+        let mut parts = decoded.splitn(3, ':');
+        let (Some(order), Some(sort_key), Some(clip_id)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        Ok(Self {
+            order: order.parse().map_err(|_| invalid())?,
+            sort_key: sort_key.parse().map_err(|_| invalid())?,
+            clip_id: clip_id.to_owned(),
+        })
+    }
+}
+
+impl From<Cursor> for String {
+    fn from(cursor: Cursor) -> Self {
+        cursor.to_string()
+    }
+}
+
+impl TryFrom<String> for Cursor {
+    type Error = ClipErr;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// * What's shown of a clip when it's listed
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClipSummary {
+    pub shortcode: field::Shortcode,
+    pub title: field::Title,
+    // ? Left out for password protected clips and clips with limited views, listing them only reveals that they exist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<field::Content>,
+    pub protected: bool,
+    #[serde(default)]
+    pub encrypted: field::Encrypted,
+    pub posted: field::Posted,
+    pub expires: field::Expires,
+    #[serde(default)]
+    pub language: field::Language,
+    #[serde(default)]
+    pub attachment: Option<field::Attachment>,
+    pub hits: field::Hits,
+}
+
+impl From<Clip> for ClipSummary {
+    fn from(clip: Clip) -> Self {
+        let protected = clip.password.has_password();
+        // ? The listing would show the content of a burn after reading clip without using up its views
+        let hidden = protected || clip.max_views.is_limited();
+        Self {
+            shortcode: clip.shortcode,
+            title: clip.title,
+            content: (!hidden).then_some(clip.content),
+            protected,
+            encrypted: clip.encrypted,
+            posted: clip.posted,
+            expires: clip.expires,
+            language: clip.language,
+            attachment: clip.attachment,
+            hits: clip.hits,
+        }
+    }
+}
+
+/// * `next` is only set when there are more clips after this page
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClipPage {
+    pub clips: Vec<ClipSummary>,
+    pub next: Option<Cursor>,
+}
+
+/// * Turns a search into an FTS5 query where every word must match
+/// * Words are quoted so the search can't use, or break on, FTS5 query syntax
+pub fn search_terms(search: &str) -> Option<String> {
+    let terms = search
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect::<Vec<_>>();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+pub fn page_size(limit: Option<u32>) -> Result<u32, ClipErr> {
+    match limit.unwrap_or(DEFAULT_PAGE_SIZE) {
+        limit @ 1..=MAX_PAGE_SIZE => Ok(limit),
+        _ => Err(ClipErr::InvalidQuery(format!(
+            "limit must be between 1 and {MAX_PAGE_SIZE}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn cursor_round_trips() {
+        let cursor = Cursor {
+            order: ClipOrder::Oldest,
+            sort_key: -1_700_000_000,
+            clip_id: "a:b".to_owned(),
+        };
+        assert_eq!(cursor.to_string().parse::<Cursor>().unwrap(), cursor);
+        assert!("not a cursor".parse::<Cursor>().is_err());
+
+        assert_eq!(
+            search_terms(r#" rust "quoted  "#).as_deref(),
+            Some(r#""rust" """quoted""#)
+        );
+        assert!(search_terms("   ").is_none());
+    }
+}
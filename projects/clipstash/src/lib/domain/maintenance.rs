@@ -1,24 +1,55 @@
 use crate::data::DatabasePool;
+use crate::metrics::METRICS;
 use crate::service;
+use parking_lot::Mutex;
 use std::time::Duration;
-use tokio::runtime::Handle;
+use tokio::{runtime::Handle, sync::watch, task::JoinHandle};
+use tracing::Instrument;
 
-pub struct Maintenance;
+pub struct Maintenance {
+    handle: Handle,
+    stop: watch::Sender<bool>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
 
 impl Maintenance {
-    /// Deletes expired clips every 10 seconds
-    pub fn spawn(pool: DatabasePool, handle: Handle) -> Self {
+    /// Deletes expired clips every `interval`
+    pub fn spawn(pool: DatabasePool, handle: Handle, interval: Duration) -> Self {
+        let (stop, mut stopped) = watch::channel(false);
         // NOTE spawn will immediately spawn this async task
-        handle.spawn(async move {
-            let mut interval = tokio::time::interval(Duration::from_secs(10));
+        let task = handle.spawn(
+            async move {
+                let mut interval = tokio::time::interval(interval);
 
-            loop {
-                interval.tick().await;
-                if let Err(e) = service::action::delete_expired(&pool).await {
-                    eprintln!("failed to delete expired clips: {e}");
+                loop {
+                    // ? A sweep that already started is finished before the task stops
+                    tokio::select! {
+                        _ = interval.tick() => {}
+                        _ = stopped.changed() => break,
+                    }
+                    match service::action::delete_expired(&pool).await {
+                        Ok(deleted) => METRICS.sweep(deleted),
+                        Err(e) => tracing::error!(error = %e, "failed to delete expired clips"),
+                    }
                 }
             }
-        });
-        Self
+            .instrument(tracing::info_span!("maintenance")),
+        );
+        Self {
+            handle,
+            stop,
+            task: Mutex::new(Some(task)),
+        }
+    }
+
+    /// * Stops the sweeps and waits for the task to end, it must not be called from the runtime's tasks
+    pub fn shutdown(&self) {
+        let Some(task) = self.task.lock().take() else {
+            return;
+        };
+        let _ = self.stop.send(true);
+        if let Err(e) = self.handle.block_on(task) {
+            tracing::error!(error = %e, "maintenance task failed");
+        }
     }
 }
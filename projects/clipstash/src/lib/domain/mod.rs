@@ -1,5 +1,13 @@
+pub mod api_key;
 pub mod clip;
+pub mod listing;
 pub mod maintenance;
+pub mod revision;
 pub mod time;
+pub mod user;
 
+pub use api_key::{ApiKeyInfo, ApiScope, ApiScopes};
 pub use clip::Clip;
+pub use listing::{ClipOrder, ClipPage, ClipSummary, Cursor};
+pub use revision::Revision;
+pub use user::{User, UserId, Username};
@@ -0,0 +1,55 @@
+use crate::domain::{clip::field, time::Time, Clip};
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+
+/// * A version of a clip, revisions are numbered from 1 and the latest one is the clip's current content
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Revision {
+    pub revision: u32,
+    pub content: field::Content,
+    pub title: field::Title,
+    pub expires: field::Expires,
+    // ? When this revision was overwritten by an update, `None` for the current revision
+    pub replaced: Option<Time>,
+}
+
+impl Revision {
+    pub fn current(revision: u32, clip: Clip) -> Self {
+        Self {
+            revision,
+            content: clip.content,
+            title: clip.title,
+            expires: clip.expires,
+            replaced: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DiffLine {
+    pub kind: DiffKind,
+    pub text: String,
+}
+
+/// * Line based diff between the content of two revisions
+pub fn diff(from: &Revision, to: &Revision) -> Vec<DiffLine> {
+    TextDiff::from_lines(from.content.as_str(), to.content.as_str())
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            kind: match change.tag() {
+                ChangeTag::Equal => DiffKind::Equal,
+                ChangeTag::Insert => DiffKind::Insert,
+                ChangeTag::Delete => DiffKind::Delete,
+            },
+            text: change.value().trim_end_matches(['\r', '\n']).to_owned(),
+        })
+        .collect()
+}
@@ -16,13 +16,17 @@ impl Time {
     }
 
     pub fn from_naive_utc(datetime: NaiveDateTime) -> Self {
-        Self(DateTime::from_utc(datetime, Utc))
+        Self(DateTime::from_naive_utc_and_offset(datetime, Utc))
     }
 }
 
 impl FromStr for Time {
     type Err = chrono::ParseError;
+    /// * Accepts RFC 3339 timestamps, or `YYYY-MM-DD` dates pinned to midnight UTC
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(datetime) = DateTime::parse_from_rfc3339(s) {
+            return Ok(datetime.with_timezone(&Utc).into());
+        }
         let datetime = format!("{}T00:00:00Z", s).parse::<DateTime<Utc>>()?;
         Ok(datetime.into())
     }
@@ -0,0 +1,140 @@
+use crate::data::DbId;
+use crate::domain::clip::field::Password;
+use crate::{ClipErr, Time};
+use derive_more::Display;
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+/// * Sessions last this long after logging in, they're not extended while they're used
+pub const SESSION_TTL_DAYS: i64 = 30;
+
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum UserErr {
+    #[error("invalid username: {0}")]
+    InvalidUsername(String),
+    #[error("invalid password: {0}")]
+    InvalidPassword(String),
+    #[error("id parse error: {0}")]
+    Id(#[from] uuid::Error),
+    #[error("password error: {0}")]
+    Password(#[from] ClipErr),
+}
+
+impl UserErr {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidUsername(_) => "invalid_username",
+            Self::InvalidPassword(_) => "invalid_user_password",
+            Self::Id(_) => "invalid_id",
+            Self::Password(e) => e.code(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Display)]
+pub struct UserId(DbId);
+
+impl UserId {
+    pub fn new() -> Self {
+        Self(DbId::new())
+    }
+
+    pub fn into_inner(self) -> DbId {
+        self.0
+    }
+}
+
+impl Default for UserId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<DbId> for UserId {
+    fn from(id: DbId) -> Self {
+        Self(id)
+    }
+}
+
+impl FromStr for UserId {
+    type Err = UserErr;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(DbId::from_str(s)?))
+    }
+}
+
+impl From<UserId> for String {
+    fn from(id: UserId) -> Self {
+        id.0.into()
+    }
+}
+
+/// * Usernames have between 3 and 32 letters, digits, `-` or `_`, they're unique regardless of case
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Display)]
+pub struct Username(String);
+
+impl Username {
+    pub fn new(username: &str) -> Result<Self, UserErr> {
+        let username = username.trim();
+        if !(3..=32).contains(&username.chars().count()) {
+            return Err(UserErr::InvalidUsername(
+                "usernames must have between 3 and 32 characters".to_owned(),
+            ));
+        }
+        if let Some(c) = username
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || *c == '-' || *c == '_'))
+        {
+            return Err(UserErr::InvalidUsername(format!(
+                "'{c}' is not allowed in usernames"
+            )));
+        }
+        Ok(Self(username.to_owned()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl FromStr for Username {
+    type Err = UserErr;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for Username {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        Ok(Self::new(field.value).map_err(|e| form::Error::validation(format!("{e}")))?)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct User {
+    pub user_id: UserId,
+    pub username: Username,
+    pub created: Time,
+    // ? The stored password is a hash, it's only used to log in
+    #[serde(skip)]
+    pub password: Password,
+}
+
+impl User {
+    /// * Only checked when registering, so accounts can't be created with trivially guessable passwords
+    pub fn validate_password(password: &Password) -> Result<(), UserErr> {
+        match password.clone().into_inner() {
+            Some(password) if password.chars().count() >= MIN_PASSWORD_LENGTH => Ok(()),
+            _ => Err(UserErr::InvalidPassword(format!(
+                "passwords must have at least {MIN_PASSWORD_LENGTH} characters"
+            ))),
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use parking_lot::Mutex;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        LazyLock,
+    },
+    time::Duration,
+};
+
+// NOTE The metrics are recorded from the request fairing and from background tasks, so they're kept in a global
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+/// * Bucket bounds of latency histograms, in seconds
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+/// * Bucket bounds of the clips deleted by a single sweep
+const SWEEP_BUCKETS: &[f64] = &[0.0, 1.0, 5.0, 10.0, 50.0, 100.0, 500.0, 1000.0];
+
+#[derive(Clone, Debug)]
+struct Histogram {
+    bounds: &'static [f64],
+    // ? Counts of each bucket on their own, they're made cumulative when rendered
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(bucket) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{{}}} {cumulative}",
+                join(labels, &format!(r#"le="{bound}""#))
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{}}} {}",
+            join(labels, r#"le="+Inf""#),
+            self.count
+        );
+        let _ = writeln!(out, "{name}_sum{} {}", braces(labels), self.sum);
+        let _ = writeln!(out, "{name}_count{} {}", braces(labels), self.count);
+    }
+}
+
+fn join(labels: &str, label: &str) -> String {
+    match labels.is_empty() {
+        true => label.to_owned(),
+        false => format!("{labels},{label}"),
+    }
+}
+
+fn braces(labels: &str) -> String {
+    match labels.is_empty() {
+        true => String::new(),
+        false => format!("{{{labels}}}"),
+    }
+}
+
+// NOTE Label values may contain any character, backslashes, quotes and newlines are escaped
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// * Method, route and status of a request, the route is the pattern it matched so paths don't each get their own series
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct RequestLabels {
+    method: String,
+    route: String,
+    status: u16,
+}
+
+impl RequestLabels {
+    fn render(&self) -> String {
+        format!(
+            r#"method="{}",route="{}",status="{}""#,
+            self.method,
+            escape(&self.route),
+            self.status
+        )
+    }
+}
+
+pub struct Metrics {
+    requests: Mutex<BTreeMap<RequestLabels, Histogram>>,
+    clips_created: AtomicU64,
+    clips_updated: AtomicU64,
+    clips_expired: AtomicU64,
+    hits_dropped: AtomicU64,
+    hit_commits_failed: AtomicU64,
+    hit_commits: Mutex<Histogram>,
+    sweeps: Mutex<Histogram>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            requests: Default::default(),
+            clips_created: Default::default(),
+            clips_updated: Default::default(),
+            clips_expired: Default::default(),
+            hits_dropped: Default::default(),
+            hit_commits_failed: Default::default(),
+            hit_commits: Mutex::new(Histogram::new(LATENCY_BUCKETS)),
+            sweeps: Mutex::new(Histogram::new(SWEEP_BUCKETS)),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let labels = RequestLabels {
+            method: method.to_owned(),
+            route: route.to_owned(),
+            status,
+        };
+        self.requests
+            .lock()
+            .entry(labels)
+            .or_insert_with(|| Histogram::new(LATENCY_BUCKETS))
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn clip_created(&self) {
+        self.clips_created.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn clip_updated(&self) {
+        self.clips_updated.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// * Records a run of `HitCounter`'s commit, which writes the hits collected since the previous one
+    pub fn hit_commit(&self, elapsed: Duration) {
+        self.hit_commits.lock().observe(elapsed.as_secs_f64());
+    }
+
+    /// * Records a commit of `HitCounter` that failed, its hits are lost
+    pub fn hit_commit_failed(&self) {
+        self.hit_commits_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// * Records a hit that was dropped because `HitCounter`'s queue was full
+    pub fn hit_dropped(&self) {
+        self.hits_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// * Records a sweep of the expired clips and how many it deleted
+    pub fn sweep(&self, deleted: u64) {
+        self.clips_expired.fetch_add(deleted, Ordering::Relaxed);
+        self.sweeps.lock().observe(deleted as f64);
+    }
+
+    /// * Renders the metrics in Prometheus' text format, gauges are sampled from the app's state when they're rendered
+    pub fn render(&self, gauges: &Gauges) -> String {
+        let mut out = String::new();
+
+        let requests = self.requests.lock().clone();
+        header(
+            &mut out,
+            "clipstash_http_requests_total",
+            "counter",
+            "Requests handled, by route and status",
+        );
+        for (labels, histogram) in &requests {
+            let _ = writeln!(
+                out,
+                "clipstash_http_requests_total{{{}}} {}",
+                labels.render(),
+                histogram.count
+            );
+        }
+        header(
+            &mut out,
+            "clipstash_http_request_duration_seconds",
+            "histogram",
+            "Time taken to answer requests, by route and status",
+        );
+        for (labels, histogram) in &requests {
+            histogram.render(
+                &mut out,
+                "clipstash_http_request_duration_seconds",
+                &labels.render(),
+            );
+        }
+
+        for (name, help, counter) in [
+            (
+                "clipstash_clips_created_total",
+                "Clips created",
+                &self.clips_created,
+            ),
+            (
+                "clipstash_clips_updated_total",
+                "Clips updated",
+                &self.clips_updated,
+            ),
+            (
+                "clipstash_clips_expired_total",
+                "Expired clips deleted by the maintenance sweeps",
+                &self.clips_expired,
+            ),
+            (
+                "clipstash_hits_dropped_total",
+                "Hits dropped because the hit counter's queue was full",
+                &self.hits_dropped,
+            ),
+            (
+                "clipstash_hit_commits_failed_total",
+                "Commits of the hit counter that failed, along with their hits",
+                &self.hit_commits_failed,
+            ),
+        ] {
+            header(&mut out, name, "counter", help);
+            let _ = writeln!(out, "{name} {}", counter.load(Ordering::Relaxed));
+        }
+
+        header(
+            &mut out,
+            "clipstash_hit_commit_duration_seconds",
+            "histogram",
+            "Time taken by the hit counter's successful commits",
+        );
+        self.hit_commits
+            .lock()
+            .render(&mut out, "clipstash_hit_commit_duration_seconds", "");
+        header(
+            &mut out,
+            "clipstash_expired_sweep_deleted_clips",
+            "histogram",
+            "Expired clips deleted by each maintenance sweep",
+        );
+        self.sweeps
+            .lock()
+            .render(&mut out, "clipstash_expired_sweep_deleted_clips", "");
+
+        for (name, help, value) in [
+            (
+                "clipstash_hit_counter_queue_depth",
+                "Messages waiting to be processed by the hit counter",
+                gauges.hit_queue,
+            ),
+            (
+                "clipstash_db_pool_connections",
+                "Connections open in the database pool",
+                gauges.pool_size,
+            ),
+            (
+                "clipstash_db_pool_idle_connections",
+                "Open connections of the database pool that aren't in use",
+                gauges.pool_idle,
+            ),
+        ] {
+            header(&mut out, name, "gauge", help);
+            let _ = writeln!(out, "{name} {value}");
+        }
+        out
+    }
+}
+
+/// * Values that are read from the app's state when the metrics are scraped, see `web::metrics`
+pub struct Gauges {
+    pub hit_queue: usize,
+    pub pool_size: usize,
+    pub pool_idle: usize,
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn histograms_are_cumulative() {
+        let mut histogram = Histogram::new(&[1.0, 5.0]);
+        for value in [0.5, 2.0, 3.0, 10.0] {
+            histogram.observe(value);
+        }
+        let mut out = String::new();
+        histogram.render(&mut out, "test", r#"route="/a""#);
+        assert_eq!(
+            out,
+            concat!(
+                "test_bucket{route=\"/a\",le=\"1\"} 1\n",
+                "test_bucket{route=\"/a\",le=\"5\"} 3\n",
+                "test_bucket{route=\"/a\",le=\"+Inf\"} 4\n",
+                "test_sum{route=\"/a\"} 15.5\n",
+                "test_count{route=\"/a\"} 4\n",
+            )
+        );
+    }
+}
@@ -1,9 +1,12 @@
+pub mod config;
 pub mod data;
 pub mod domain;
+pub mod metrics;
 pub mod service;
 pub mod web;
 
 // NOTE re-exporting common modules
+pub use config::Config;
 pub use data::DataErr;
 pub use domain::clip::field::Shortcode;
 pub use domain::clip::ClipErr;
@@ -13,20 +16,69 @@ pub use domain::Clip;
 pub use service::ServiceErr;
 
 use data::AppDatabase;
-use rocket::{fs::FileServer, Build, Rocket};
-use web::{hitcounter::HitCounter, renderer::Renderer};
+use domain::clip::field::{ShortcodeGenerator, TitleLimit};
+use rocket::{data::ToByteUnit, fs::FileServer, Build, Rocket};
+use service::token::AccessTokenSigner;
+use web::{hitcounter::HitCounter, logging::traced, ratelimit::RateLimiter, renderer::Renderer};
 
 pub fn rocket(config: RocketConfig) -> Rocket<Build> {
-    rocket::build()
+    // ? Multipart forms also carry the other fields of the clip, so they're allowed to be slightly larger than the file
+    let settings = &config.config;
+    let figment = rocket::Config::figment()
+        .merge(("address", settings.address))
+        .merge(("port", settings.port))
+        // ? SIGINT and SIGTERM stop the server, it stops accepting connections and lets the running requests finish
+        .merge(("shutdown.ctrlc", true))
+        .merge(("shutdown.signals", ["term"]))
+        .merge(("shutdown.grace", settings.shutdown_grace.as_secs()))
+        .merge(("limits.file", settings.max_upload_size.as_u64()))
+        .merge((
+            "limits.data-form",
+            (settings.max_upload_size + 64.kibibytes()).as_u64(),
+        ));
+    // ? Rocket trusts `X-Real-IP` by default, which would let clients pick the IP they're rate limited by
+    let figment = match &settings.ip_header {
+        Some(header) => figment.merge(("ip_header", header)),
+        None => figment.merge(("ip_header", false)),
+    };
+    let static_directory = settings.static_directory.clone();
+
+    // NOTE Every route is wrapped so its handler runs in the span of the request, see `web::logging`
+    rocket::custom(figment)
         .manage::<AppDatabase>(config.database)
         .manage::<Renderer>(config.renderer)
         .manage::<HitCounter>(config.hit_counter)
         .manage::<Maintenance>(config.maintenance)
-        .mount("/", web::http::routes())
-        .mount("/api/clip", web::api::routes())
-        .mount("/static", FileServer::from("static")) // ? "static" refers to the /static folder in the root of our crate
+        .manage::<AccessTokenSigner>(config.token_signer)
+        .manage::<ShortcodeGenerator>(config.shortcodes)
+        .manage::<TitleLimit>(config.title_limit)
+        .manage::<RateLimiter>(config.rate_limiter)
+        .mount("/", traced(web::ratelimit::routes()))
+        .mount("/", traced(web::http::routes()))
+        .mount("/", traced(web::account::routes()))
+        .mount(web::api::v1::BASE, traced(web::api::v1::routes()))
+        .mount(web::api::LEGACY_BASE, traced(web::api::v1::routes()))
+        .mount("/api", traced(web::openapi::routes()))
+        .mount("/", traced(web::metrics::routes()))
+        .mount("/static", traced(FileServer::from(static_directory)))
         .register("/", web::http::catcher::catchers())
-        .register("/api/clip", web::api::catcher::catchers())
+        .register(web::api::v1::BASE, web::api::catcher::catchers())
+        .register(web::api::LEGACY_BASE, web::api::catcher::catchers())
+        .attach(web::request_id::RequestIdHeader)
+        .attach(web::api::LegacyHeaders)
+        .attach(web::metrics::RequestMetrics)
+}
+
+/// * Stops the background tasks once the server has stopped, the hits collected so far are committed
+/// * Shutdown fairings run while the last requests are still being answered, their hits would be lost
+/// * It waits for the tasks, so it must be called outside of the runtime they run on
+pub fn shutdown<P: rocket::Phase>(rocket: &Rocket<P>) {
+    if let Some(maintenance) = rocket.state::<Maintenance>() {
+        maintenance.shutdown();
+    }
+    if let Some(hit_counter) = rocket.state::<HitCounter>() {
+        hit_counter.shutdown();
+    }
 }
 
 pub struct RocketConfig {
@@ -34,6 +86,12 @@ pub struct RocketConfig {
     pub database: AppDatabase,
     pub hit_counter: HitCounter,
     pub maintenance: Maintenance,
+    pub token_signer: AccessTokenSigner,
+    pub shortcodes: ShortcodeGenerator,
+    pub title_limit: TitleLimit,
+    pub rate_limiter: RateLimiter,
+    /// * Settings the server reads on its own, such as the address and upload limits
+    pub config: Config,
 }
 
 #[cfg(test)]
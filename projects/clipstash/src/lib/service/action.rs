@@ -1,70 +1,499 @@
-use crate::web::api::ApiKey;
 use crate::{
-    data::{query, DatabasePool, Transaction},
-    domain::Clip,
-    service::ask,
-    Shortcode,
+    data::{model, query, DatabasePool, Transaction},
+    domain::{
+        clip::field::{
+            Attachment, Content, EditSecret, Language, Password, ShortcodeGenerator, TitleLimit,
+        },
+        listing,
+        user::UserErr,
+        ApiKeyInfo, Clip, ClipPage, ClipSummary, Cursor, Revision, User, UserId,
+    },
+    service::{
+        ask,
+        token::{AccessTokenSigner, SessionToken},
+    },
+    ClipErr, Shortcode,
 };
+use crate::{metrics::METRICS, web::api::ApiKey};
 use std::convert::TryInto;
 
 use super::ServiceErr;
 
 type ModResult<T> = std::result::Result<T, ServiceErr>;
 
+// NOTE Each action runs in its own span inside the request's, the arguments are left out since they carry passwords and keys
+
 // NOTE The transactions will be used to defer database writes and batch them together
 // ? This will lead to increased performance for increasing the hit count
+#[tracing::instrument(skip_all)]
 pub async fn begin_transaction(pool: &DatabasePool) -> ModResult<Transaction<'_>> {
     Ok(pool.begin().await?)
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn end_transaction(transaction: Transaction<'_>) -> ModResult<()> {
     Ok(transaction.commit().await?)
 }
 
-pub async fn increase_hit_count(
-    shortcode: &Shortcode,
-    hits: u32,
-    pool: &DatabasePool,
+/// * Adds the hits collected for many clips in a single transaction
+#[tracing::instrument(skip_all)]
+pub async fn increase_hit_counts(hits: &[(Shortcode, u32)], pool: &DatabasePool) -> ModResult<u64> {
+    let mut transaction = begin_transaction(pool).await?;
+    let updated = query::increase_hit_counts(hits, &mut transaction).await?;
+    end_transaction(transaction).await?;
+    Ok(updated)
+}
+
+// NOTE Argon2 is CPU-bound by design, so it runs on the blocking thread pool instead of stalling the async workers
+async fn hash_password(password: Password) -> ModResult<Password> {
+    Ok(tokio::task::spawn_blocking(move || password.hash())
+        .await
+        .map_err(|e| ClipErr::PasswordHash(e.to_string()))??)
+}
+
+async fn verify_password(stored: Password, provided: Password) -> ModResult<bool> {
+    Ok(
+        tokio::task::spawn_blocking(move || stored.is_valid(&provided))
+            .await
+            .map_err(|e| ClipErr::PasswordHash(e.to_string()))?,
+    )
+}
+
+async fn verify_edit_secret(stored: EditSecret, provided: EditSecret) -> ModResult<()> {
+    let valid = tokio::task::spawn_blocking(move || stored.is_valid(&provided))
+        .await
+        .map_err(|e| ClipErr::PasswordHash(e.to_string()))?;
+    match valid {
+        true => Ok(()),
+        false => Err(ServiceErr::PermissionErr("Invalid edit secret".to_owned())),
+    }
+}
+
+/// * The owner of a clip can edit it without its edit secret
+async fn authorize_edit(
+    clip: &Clip,
+    edit_secret: EditSecret,
+    user: Option<&UserId>,
 ) -> ModResult<()> {
-    Ok(query::increase_hit_count(shortcode, hits, pool).await?)
+    match &clip.owner {
+        Some(owner) if Some(owner) == user => Ok(()),
+        _ => verify_edit_secret(clip.edit_secret.clone(), edit_secret).await,
+    }
 }
 
-pub async fn get_clip(req: ask::GetClip, pool: &DatabasePool) -> ModResult<Clip> {
+/// * Access is granted by either a valid access token for this clip or the clip's password
+async fn authorize(
+    req: ask::GetClip,
+    signer: &AccessTokenSigner,
+    pool: &DatabasePool,
+) -> ModResult<Clip> {
     let user_password = req.password.clone();
-    let clip: Clip = query::get_clip(req, pool).await?.try_into()?;
-    let Clip { password, .. } = &clip;
+    let token = req.token.clone();
+    let mut clip: Clip = query::get_clip(req, pool).await?.try_into()?;
+
+    // * Clips stored with a plaintext password are migrated the first time they're read
+    if clip.password.needs_rehash() {
+        let hashed = hash_password(clip.password.clone()).await?;
+        query::update_password(&clip.shortcode, hashed.clone().into_inner(), pool).await?;
+        clip.password = hashed;
+    }
+
+    let authorized = !clip.password.has_password()
+        || token.is_some_and(|token| signer.verify(&token, &clip))
+        || verify_password(clip.password.clone(), user_password).await?;
+    if !authorized {
+        return Err(ServiceErr::PermissionErr("Invalid password".to_owned()));
+    }
+    Ok(clip)
+}
+
+async fn consume_view(clip: &Clip, pool: &DatabasePool) -> ModResult<()> {
+    // NOTE Views are counted right away instead of through the hit counter, otherwise a clip could be read past its limit
+    if clip.max_views.is_limited() && !query::consume_view(&clip.shortcode, pool).await? {
+        return Err(ServiceErr::NotFound);
+    }
+    Ok(())
+}
 
-    if password.is_valid(&user_password) {
-        Ok(clip)
+#[tracing::instrument(skip_all)]
+pub async fn get_clip(
+    req: ask::GetClip,
+    signer: &AccessTokenSigner,
+    pool: &DatabasePool,
+) -> ModResult<Clip> {
+    let clip = authorize(req, signer, pool).await?;
+    // ? The views of a clip with a file are counted by its downloads, the page that links to the file doesn't count
+    if clip.attachment.is_none() {
+        consume_view(&clip, pool).await?;
+    }
+    Ok(clip)
+}
+
+/// * Same as `get_clip()`, for the page its creator is sent to after creating it
+/// * Only the creator has the edit secret, so a valid one means the clip is shown without counting a view
+#[tracing::instrument(skip_all)]
+pub async fn get_new_clip(
+    req: ask::GetClip,
+    edit_secret: EditSecret,
+    signer: &AccessTokenSigner,
+    pool: &DatabasePool,
+) -> ModResult<Clip> {
+    let clip = authorize(req, signer, pool).await?;
+    if clip.attachment.is_none()
+        && verify_edit_secret(clip.edit_secret.clone(), edit_secret)
+            .await
+            .is_err()
+    {
+        consume_view(&clip, pool).await?;
+    }
+    Ok(clip)
+}
+
+/// * Downloading the file of a clip counts as a view, viewing the clip itself doesn't
+/// * The file is loaded before the view is consumed, since the last allowed view deletes it
+#[tracing::instrument(skip_all)]
+pub async fn get_file(
+    req: ask::GetClip,
+    signer: &AccessTokenSigner,
+    pool: &DatabasePool,
+) -> ModResult<(Attachment, Vec<u8>)> {
+    let clip = authorize(req, signer, pool).await?;
+    let attachment = clip.attachment.clone().ok_or(ServiceErr::NotFound)?;
+    let data = query::get_file(&clip.shortcode, pool).await?;
+    consume_view(&clip, pool).await?;
+    Ok((attachment, data))
+}
+
+/// * Every revision of a clip, from the oldest to the current one
+/// * Requires the same access as reading the clip, and counts a view like reading it since the current content is included
+#[tracing::instrument(skip_all)]
+pub async fn get_revisions(
+    req: ask::GetClip,
+    signer: &AccessTokenSigner,
+    pool: &DatabasePool,
+) -> ModResult<Vec<Revision>> {
+    let clip = authorize(req, signer, pool).await?;
+    // ? Otherwise a clip with limited views could be read as many times as wanted through its history
+    if clip.attachment.is_none() {
+        consume_view(&clip, pool).await?;
+    }
+    let mut revisions = query::get_revisions(&clip.shortcode, pool)
+        .await?
+        .into_iter()
+        .map(Revision::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let current = revisions.last().map_or(1, |revision| revision.revision + 1);
+    revisions.push(Revision::current(current, clip));
+    Ok(revisions)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_revision(
+    req: ask::GetClip,
+    revision: u32,
+    signer: &AccessTokenSigner,
+    pool: &DatabasePool,
+) -> ModResult<Revision> {
+    get_revisions(req, signer, pool)
+        .await?
+        .into_iter()
+        .find(|r| r.revision == revision)
+        .ok_or(ServiceErr::NotFound)
+}
+
+const SHORTCODE_ATTEMPTS: usize = 5;
+
+fn language_or_detected(language: Language, content: &Content) -> Language {
+    if language.is_set() {
+        language
     } else {
-        Err(ServiceErr::PermissionErr("Invalid password".to_owned()))
+        Language::detect(content.as_str())
     }
 }
 
-pub async fn new_clip(req: ask::NewClip, pool: &DatabasePool) -> ModResult<Clip> {
-    Ok(query::new_clip(req, pool).await?.try_into()?)
+/// * Returns the clip along with its edit secret, which is only stored hashed so this is the only time it's available
+/// * Generated shortcodes are retried a few times if they're already taken, custom ones fail right away
+#[tracing::instrument(skip_all)]
+pub async fn new_clip(
+    req: ask::NewClip,
+    shortcodes: &ShortcodeGenerator,
+    title_limit: &TitleLimit,
+    pool: &DatabasePool,
+) -> ModResult<(Clip, EditSecret)> {
+    title_limit.check(&req.title)?;
+    req.expires.ensure_future()?;
+    req.encrypted.ensure_ciphertext(&req.content)?;
+    if req.encrypted.is_set() && req.file.attachment().is_some() {
+        return Err(
+            ClipErr::InvalidFile("files can't be attached to encrypted clips".to_owned()).into(),
+        );
+    }
+    let edit_secret = EditSecret::generate();
+    let hashed_secret = edit_secret.clone();
+    let hashed_secret = tokio::task::spawn_blocking(move || hashed_secret.hash())
+        .await
+        .map_err(|e| ClipErr::PasswordHash(e.to_string()))??;
+
+    let req = ask::NewClip {
+        password: hash_password(req.password).await?,
+        edit_secret: hashed_secret,
+        // ? Ciphertext can't be highlighted, so encrypted clips are always plain text to the server
+        language: match req.encrypted.is_set() {
+            true => Language::default(),
+            false => language_or_detected(req.language, &req.content),
+        },
+        ..req
+    };
+    let vanity = req.shortcode.clone().into_inner();
+    let model = model::NewClip::from(req);
+
+    for _ in 0..SHORTCODE_ATTEMPTS {
+        let shortcode = vanity.clone().unwrap_or_else(|| shortcodes.generate());
+        match query::new_clip(model.clone().with_shortcode(shortcode.clone()), pool).await {
+            Ok(clip) => {
+                METRICS.clip_created();
+                return Ok((clip.try_into()?, edit_secret));
+            }
+            Err(e) if e.is_unique_violation() && vanity.is_some() => {
+                return Err(ServiceErr::Conflict(format!(
+                    "shortcode '{}' is already taken",
+                    shortcode.as_str()
+                )))
+            }
+            Err(e) if e.is_unique_violation() => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Err(ServiceErr::Conflict(
+        "could not generate an unused shortcode".to_owned(),
+    ))
 }
 
-pub async fn update_clip(req: ask::UpdateClip, pool: &DatabasePool) -> ModResult<Clip> {
-    Ok(query::update_clip(req, pool).await?.try_into()?)
+#[tracing::instrument(skip_all)]
+pub async fn update_clip(
+    req: ask::UpdateClip,
+    title_limit: &TitleLimit,
+    pool: &DatabasePool,
+) -> ModResult<Clip> {
+    let clip: Clip = query::get_clip(req.shortcode.clone(), pool)
+        .await?
+        .try_into()?;
+    authorize_edit(&clip, req.edit_secret.clone(), req.user.as_ref()).await?;
+    // NOTE The server can't tell whether new content was encrypted with the clip's key, so encrypted clips are immutable
+    if clip.encrypted.is_set() {
+        return Err(ServiceErr::PermissionErr(
+            "encrypted clips can't be edited".to_owned(),
+        ));
+    }
+    title_limit.check(&req.title)?;
+    req.expires.ensure_future()?;
+
+    let password = match req.keep_password {
+        true => clip.password,
+        false => hash_password(req.password).await?,
+    };
+    let req = ask::UpdateClip {
+        password,
+        language: language_or_detected(req.language, &req.content),
+        ..req
+    };
+    let model = model::UpdateClip::new(req, &clip.max_views);
+    let clip = query::update_clip(model, pool).await?.try_into()?;
+    METRICS.clip_updated();
+    Ok(clip)
 }
 
-pub async fn generate_api_key(pool: &DatabasePool) -> ModResult<ApiKey> {
+#[tracing::instrument(skip_all)]
+pub async fn delete_clip(req: ask::DeleteClip, pool: &DatabasePool) -> ModResult<Clip> {
+    let clip: Clip = query::get_clip(req.shortcode.clone(), pool)
+        .await?
+        .try_into()?;
+
+    authorize_edit(&clip, req.edit_secret, req.user.as_ref()).await?;
+
+    match query::delete_clip(&req.shortcode, pool).await? {
+        0 => Err(ServiceErr::NotFound),
+        _ => Ok(clip),
+    }
+}
+
+/// * Returns the new key along with its info, the key is only shown this once
+#[tracing::instrument(skip_all)]
+pub async fn generate_api_key(
+    req: ask::NewApiKey,
+    pool: &DatabasePool,
+) -> ModResult<(ApiKey, ApiKeyInfo)> {
+    req.expires.ensure_future()?;
+    ApiKeyInfo::validate_name(&req.name)?;
+    let owner = match &req.owner {
+        Some(username) => match query::get_user(username.as_str(), pool).await {
+            Ok(user) => Some(User::try_from(user)?.user_id),
+            Err(e) if e.is_not_found() => {
+                return Err(UserErr::InvalidUsername(format!("no user named '{username}'")).into())
+            }
+            Err(e) => return Err(e.into()),
+        },
+        None => None,
+    };
+    let name = req.name.clone();
     let api_key = ApiKey::new();
-    Ok(query::save_api_key(api_key, pool).await?)
+    let model = model::NewApiKey::new(api_key.clone(), req, owner);
+    match query::save_api_key(model, pool).await {
+        Ok(info) => Ok((api_key, info.try_into()?)),
+        Err(e) if e.is_unique_violation() => Err(ServiceErr::Conflict(format!(
+            "an api key named '{name}' already exists"
+        ))),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn list_api_keys(pool: &DatabasePool) -> ModResult<Vec<ApiKeyInfo>> {
+    Ok(query::list_api_keys(pool)
+        .await?
+        .into_iter()
+        .map(ApiKeyInfo::try_from)
+        .collect::<Result<Vec<_>, _>>()?)
 }
 
-pub async fn revoke_api_key(
-    api_key: ApiKey,
+#[tracing::instrument(skip_all)]
+pub async fn get_api_key(api_key: &ApiKey, pool: &DatabasePool) -> ModResult<ApiKeyInfo> {
+    Ok(query::get_api_key(api_key.clone().into_inner(), pool)
+        .await?
+        .try_into()?)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn touch_api_key(api_key: &ApiKey, pool: &DatabasePool) -> ModResult<()> {
+    Ok(query::touch_api_key(api_key.clone().into_inner(), pool).await?)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn revoke_api_key(name: &str, pool: &DatabasePool) -> ModResult<query::RevocationStatus> {
+    Ok(query::revoke_api_key(name, pool).await?)
+}
+
+/// * Password protected clips and clips with limited views are listed without their content
+#[tracing::instrument(skip_all)]
+pub async fn list_clips(req: ask::ListClips, pool: &DatabasePool) -> ModResult<ClipPage> {
+    let order = req.order;
+    if req
+        .cursor
+        .as_ref()
+        .is_some_and(|cursor| cursor.order != order)
+    {
+        return Err(ClipErr::InvalidQuery("the cursor belongs to another order".to_owned()).into());
+    }
+    let limit = listing::page_size(req.limit)?;
+
+    let mut clips = query::list_clips(model::ListClips::new(req, limit + 1), pool)
+        .await?
+        .into_iter()
+        .map(Clip::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    let next = match clips.len() > limit as usize {
+        true => {
+            clips.truncate(limit as usize);
+            clips.last().map(|clip| Cursor::after(order, clip))
+        }
+        false => None,
+    };
+    Ok(ClipPage {
+        clips: clips.into_iter().map(ClipSummary::from).collect(),
+        next,
+    })
+}
+
+/// * Creates an account, the username must not be taken regardless of case
+#[tracing::instrument(skip_all)]
+pub async fn register(req: ask::Credentials, pool: &DatabasePool) -> ModResult<User> {
+    User::validate_password(&req.password)?;
+    let password = hash_password(req.password).await?;
+    let username = req.username.clone();
+    match query::new_user(model::NewUser::new(req.username, password), pool).await {
+        Ok(user) => Ok(user.try_into()?),
+        Err(e) if e.is_unique_violation() => Err(ServiceErr::Conflict(format!(
+            "the username '{username}' is already taken"
+        ))),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// * Returns the user along with a new session, the token is only kept by the client
+/// * Unknown users and wrong passwords get the same error, so it doesn't reveal which usernames exist
+#[tracing::instrument(skip_all)]
+pub async fn login(req: ask::Credentials, pool: &DatabasePool) -> ModResult<(User, SessionToken)> {
+    let invalid = || ServiceErr::PermissionErr("Invalid username or password".to_owned());
+    let row = match query::get_user(req.username.as_str(), pool).await {
+        Ok(row) => row,
+        // ? A password is still verified, otherwise unknown usernames would be answered faster than wrong passwords
+        Err(e) if e.is_not_found() => {
+            verify_password(Password::dummy(), req.password).await?;
+            return Err(invalid());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let session = SessionToken::generate();
+    let new_session = model::NewSession::new(&session, &row);
+    let user: User = row.try_into()?;
+    if !verify_password(user.password.clone(), req.password).await? {
+        return Err(invalid());
+    }
+    query::new_session(new_session, pool).await?;
+    Ok((user, session))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_session_user(session: &SessionToken, pool: &DatabasePool) -> ModResult<User> {
+    Ok(query::get_session_user(session.hash(), pool)
+        .await?
+        .try_into()?)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn logout(session: &SessionToken, pool: &DatabasePool) -> ModResult<()> {
+    Ok(query::delete_session(session.hash(), pool).await?)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn list_user_clips(user: &User, pool: &DatabasePool) -> ModResult<Vec<ClipSummary>> {
+    Ok(
+        query::list_user_clips(&String::from(user.user_id.clone()), pool)
+            .await?
+            .into_iter()
+            .map(|clip| Clip::try_from(clip).map(ClipSummary::from))
+            .collect::<Result<Vec<_>, _>>()?,
+    )
+}
+
+/// * Clips are loaded for editing without their password or views, only their owner can do it
+#[tracing::instrument(skip_all)]
+pub async fn get_owned_clip(
+    shortcode: Shortcode,
+    user: &User,
     pool: &DatabasePool,
-) -> ModResult<query::RevocationStatus> {
-    Ok(query::revoke_api_key(api_key, pool).await?)
+) -> ModResult<Clip> {
+    let clip: Clip = query::get_clip(shortcode, pool).await?.try_into()?;
+    match &clip.owner {
+        Some(owner) if *owner == user.user_id => Ok(clip),
+        _ => Err(ServiceErr::PermissionErr(
+            "Only the owner of a clip can edit it here".to_owned(),
+        )),
+    }
 }
 
-pub async fn api_key_is_valid(api_key: ApiKey, pool: &DatabasePool) -> ModResult<bool> {
-    Ok(query::api_key_is_valid(api_key, pool).await?)
+#[tracing::instrument(skip_all)]
+pub async fn reindex_clips(pool: &DatabasePool) -> ModResult<u64> {
+    Ok(query::reindex_clips(pool).await?)
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn delete_expired(pool: &DatabasePool) -> ModResult<u64> {
     Ok(query::delete_expired(pool).await?)
 }
+
+#[tracing::instrument(skip_all)]
+pub async fn compress_stored_content(pool: &DatabasePool) -> ModResult<u64> {
+    Ok(query::compress_stored_content(pool).await?)
+}
@@ -1,14 +1,23 @@
-use crate::domain::clip::field::Password;
-use crate::Shortcode;
-use crate::{domain::clip::field, web::PASSWORD_COOKIE};
+use crate::service::token::AccessToken;
+use crate::{
+    domain::clip::field,
+    web::{access_token_cookie, openapi},
+};
+use crate::{
+    domain::{ApiScopes, ClipOrder, Cursor, UserId, Username},
+    ClipErr, Shortcode, Time,
+};
 
 use rocket::http::CookieJar;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct GetClip {
     pub shortcode: Shortcode,
     pub password: field::Password,
+    #[serde(default)]
+    pub token: Option<AccessToken>,
 }
 
 impl GetClip {
@@ -17,18 +26,21 @@ impl GetClip {
             // ? Equivalent to -> Shortcode::from(shortcode),
             shortcode: shortcode.into(),
             password: field::Password::default(),
+            token: None,
         }
     }
 
+    /// * Reads the access token issued for this specific clip, tokens of other clips are ignored
     pub fn from_cookies<T: Into<Shortcode>>(shortcode: T, cookies: &CookieJar<'_>) -> Self {
+        let shortcode: Shortcode = shortcode.into();
+        let token = cookies
+            .get(&access_token_cookie(&shortcode))
+            .map(|cookie| AccessToken::from(cookie.value()));
+
         Self {
-            shortcode: shortcode.into(),
-            password: cookies
-                .get(PASSWORD_COOKIE)
-                .map(|cookie| cookie.value())
-                // NOTE `and_then` is more concise then `map(expr).flatten()`
-                .and_then(|raw_password| Password::new(raw_password.to_string()).ok())
-                .unwrap_or_default(),
+            shortcode,
+            password: field::Password::default(),
+            token,
         }
     }
 }
@@ -38,6 +50,7 @@ impl From<Shortcode> for GetClip {
         Self {
             shortcode,
             password: field::Password::default(),
+            token: None,
         }
     }
 }
@@ -48,32 +61,172 @@ impl From<&str> for GetClip {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// * A new clip, encrypted clips must already be encrypted by the client
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct NewClip {
+    #[schema(value_type = String)]
     pub content: field::Content,
+    #[schema(value_type = Option<String>, required = true)]
     pub title: field::Title,
+    #[serde(default)]
+    #[schema(schema_with = openapi::expires)]
     pub expires: field::Expires,
+    #[schema(value_type = Option<String>, required = true)]
     pub password: field::Password,
+    #[serde(default)]
+    #[schema(value_type = Option<u32>, minimum = 1)]
+    pub max_views: field::MaxViews,
+    // ? Detected from the content when it's not provided
+    #[serde(default)]
+    #[schema(schema_with = openapi::language)]
+    pub language: field::Language,
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub shortcode: field::VanityShortcode,
+    // ? The content must already be encrypted by the client
+    #[serde(default)]
+    #[schema(value_type = bool)]
+    pub encrypted: field::Encrypted,
+    // ? Files are only accepted from multipart forms
+    #[serde(skip)]
+    pub file: field::Upload,
+    // ? Generated by the service, never provided by the client
+    #[serde(skip)]
+    pub edit_secret: field::EditSecret,
+    // ? Set from the session or the API key, clients can't give clips to other users
+    #[serde(skip)]
+    pub owner: Option<UserId>,
+}
+
+impl NewClip {
+    /// * A clip with only its content, the other fields keep their defaults
+    /// * Other fields are set with struct update syntax, e.g. `NewClip { password, ..NewClip::new(content) }`
+    pub fn new(content: field::Content) -> Self {
+        Self {
+            content,
+            title: Default::default(),
+            expires: Default::default(),
+            password: Default::default(),
+            max_views: Default::default(),
+            language: Default::default(),
+            shortcode: Default::default(),
+            encrypted: Default::default(),
+            file: Default::default(),
+            edit_secret: Default::default(),
+            owner: None,
+        }
+    }
 }
 
 use crate::web::form;
 
-impl From<form::NewClip> for NewClip {
-    fn from(value: form::NewClip) -> Self {
-        Self {
-            content: value.content,
+// ? Uploads don't need any content, the file name is used as the content instead
+impl TryFrom<form::NewClip> for NewClip {
+    type Error = ClipErr;
+    fn try_from(value: form::NewClip) -> Result<Self, Self::Error> {
+        let content = match (value.content, value.file.attachment()) {
+            (Some(content), _) => content,
+            (None, Some(attachment)) => field::Content::new(attachment.name())?,
+            (None, None) => return Err(ClipErr::EmptyContent),
+        };
+        Ok(Self {
+            content,
             title: value.title,
             expires: value.expires,
             password: value.password,
-        }
+            max_views: value.max_views,
+            language: value.language,
+            shortcode: value.shortcode,
+            encrypted: value.encrypted,
+            file: value.file,
+            edit_secret: field::EditSecret::default(),
+            owner: None,
+        })
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// * New version of a clip, `expires` also accepts durations such as `2h` or `7d`
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct UpdateClip {
+    #[schema(value_type = String)]
     pub shortcode: field::Shortcode,
+    #[schema(value_type = String)]
     pub content: field::Content,
+    #[schema(value_type = Option<String>, required = true)]
     pub title: field::Title,
+    #[serde(default)]
+    #[schema(schema_with = openapi::expires)]
+    pub expires: field::Expires,
+    #[schema(value_type = Option<String>, required = true)]
+    pub password: field::Password,
+    #[serde(default)]
+    #[schema(schema_with = openapi::language)]
+    pub language: field::Language,
+    // ? Not needed when the clip's owner makes the request
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub edit_secret: field::EditSecret,
+    #[serde(skip)]
+    pub user: Option<UserId>,
+    // ? Keeps the stored password instead of replacing it with `password`, the dashboard can't show the current one
+    #[serde(skip)]
+    pub keep_password: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteClip {
+    pub shortcode: field::Shortcode,
+    #[serde(default)]
+    pub edit_secret: field::EditSecret,
+    #[serde(skip)]
+    pub user: Option<UserId>,
+}
+
+/// * Every filter is optional, `q` searches the title and content while `title` only matches the title
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ListClips {
+    pub q: Option<String>,
+    pub title: Option<String>,
+    pub posted_after: Option<Time>,
+    #[serde(default)]
+    pub order: ClipOrder,
+    pub cursor: Option<Cursor>,
+    pub limit: Option<u32>,
+}
+
+impl TryFrom<form::ListClips> for ListClips {
+    type Error = ClipErr;
+    fn try_from(value: form::ListClips) -> Result<Self, Self::Error> {
+        // ? Empty query values are the same as leaving them out
+        let non_empty = |value: Option<String>| value.filter(|value| !value.trim().is_empty());
+        Ok(Self {
+            q: non_empty(value.q),
+            title: non_empty(value.title),
+            posted_after: non_empty(value.posted_after)
+                .map(|date| date.parse::<Time>())
+                .transpose()?,
+            order: value.order.unwrap_or_default(),
+            cursor: non_empty(value.cursor)
+                .map(|cursor| cursor.parse::<Cursor>())
+                .transpose()?,
+            limit: value.limit,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NewApiKey {
+    pub name: String,
+    pub scopes: ApiScopes,
     pub expires: field::Expires,
+    // ? Username of the key's owner, keys without one create anonymous clips
+    #[serde(default)]
+    pub owner: Option<Username>,
+}
+
+/// * Used both to register and to log in
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Credentials {
+    pub username: Username,
     pub password: field::Password,
 }
@@ -1,7 +1,11 @@
 pub mod action;
 pub mod ask;
+pub mod token;
 
-use crate::{ClipErr, DataErr};
+use crate::{
+    domain::{api_key::ApiKeyInfoErr, user::UserErr},
+    ClipErr, DataErr,
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ServiceErr {
@@ -13,6 +17,12 @@ pub enum ServiceErr {
     NotFound,
     #[error("permissions not met: {0}")]
     PermissionErr(String),
+    #[error("conflict: {0}")]
+    Conflict(String),
+    #[error("api key error: {0}")]
+    ApiKey(#[from] ApiKeyInfoErr),
+    #[error("user error: {0}")]
+    User(#[from] UserErr),
 }
 
 impl From<DataErr> for ServiceErr {
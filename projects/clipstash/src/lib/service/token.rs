@@ -0,0 +1,121 @@
+use crate::domain::Clip;
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// * Proof that the password of a single clip was verified, issued so the password doesn't have to be stored by the client
+/// * Format: `<expiration timestamp>.<base64 HMAC signature>`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AccessToken(String);
+
+impl AccessToken {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl From<&str> for AccessToken {
+    fn from(token: &str) -> Self {
+        Self(token.to_owned())
+    }
+}
+
+/// * Identifies a logged in user, it's kept in the session cookie
+/// * Only its SHA-256 hash is stored, so the sessions table can't be used to log in
+#[derive(Clone, Debug)]
+pub struct SessionToken(String);
+
+impl SessionToken {
+    pub fn generate() -> Self {
+        let token = (0..32).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
+        Self(base64::encode_config(token, base64::URL_SAFE_NO_PAD))
+    }
+
+    // NOTE The token is random and long enough that a fast unsalted hash is safe
+    pub fn hash(&self) -> Vec<u8> {
+        Sha256::digest(self.0.as_bytes()).to_vec()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl From<&str> for SessionToken {
+    fn from(token: &str) -> Self {
+        Self(token.to_owned())
+    }
+}
+
+pub struct AccessTokenSigner {
+    key: Vec<u8>,
+    ttl: Duration,
+}
+
+impl AccessTokenSigner {
+    pub fn new(key: Vec<u8>, ttl: Duration) -> Self {
+        Self { key, ttl }
+    }
+
+    /// * Tokens signed with a random key are invalidated when the server restarts
+    pub fn random(ttl: Duration) -> Self {
+        let key = (0..32).map(|_| rand::random::<u8>()).collect();
+        Self::new(key, ttl)
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn issue(&self, clip: &Clip) -> AccessToken {
+        let expires = (Utc::now() + self.ttl).timestamp();
+        let signature = self.mac(clip, expires).finalize().into_bytes();
+        AccessToken(format!(
+            "{expires}.{}",
+            base64::encode_config(signature, base64::URL_SAFE_NO_PAD)
+        ))
+    }
+
+    pub fn verify(&self, token: &AccessToken, clip: &Clip) -> bool {
+        let Some((expires, signature)) = token.as_str().split_once('.') else {
+            return false;
+        };
+        let Ok(expires) = expires.parse::<i64>() else {
+            return false;
+        };
+        let Ok(signature) = base64::decode_config(signature, base64::URL_SAFE_NO_PAD) else {
+            return false;
+        };
+
+        // NOTE verify_slice() compares the signatures in constant time
+        expires > Utc::now().timestamp() && self.mac(clip, expires).verify_slice(&signature).is_ok()
+    }
+
+    // ? The stored password hash is part of the signed message, so changing the password invalidates every issued token
+    fn mac(&self, clip: &Clip, expires: i64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("hmac accepts keys of any size");
+        mac.update(clip.shortcode.as_str().as_bytes());
+        mac.update(b"\n");
+        mac.update(expires.to_string().as_bytes());
+        mac.update(b"\n");
+        mac.update(
+            clip.password
+                .clone()
+                .into_inner()
+                .unwrap_or_default()
+                .as_bytes(),
+        );
+        mac
+    }
+}
@@ -0,0 +1,460 @@
+use crate::{
+    data::AppDatabase,
+    domain::{clip::field::TitleLimit, user::SESSION_TTL_DAYS, User},
+    service::{self, action, token::SessionToken},
+    web::{ctx, form, hitcounter::HitCounter, renderer::Renderer, PageErr},
+    ServiceErr, Shortcode,
+};
+use rocket::{
+    form::{Contextual, Form},
+    http::{Cookie, CookieJar, SameSite, Status},
+    request::{FlashMessage, FromRequest, Outcome, Request},
+    response::{content::RawHtml, Flash, Redirect},
+    time, uri, State,
+};
+
+pub const SESSION_COOKIE: &str = "clipstash_session";
+
+/// * The user of the session cookie, routes that require it are forwarded when nobody is logged in
+pub struct CurrentUser(pub User);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CurrentUser {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(cookie) = req.cookies().get(SESSION_COOKIE) else {
+            return Outcome::Forward(Status::Unauthorized);
+        };
+        let session = SessionToken::from(cookie.value());
+        let db = match req.guard::<&State<AppDatabase>>().await {
+            Outcome::Success(db) => db,
+            _ => return Outcome::Error((Status::InternalServerError, ())),
+        };
+        match action::get_session_user(&session, db.get_pool()).await {
+            Ok(user) => Outcome::Success(Self(user)),
+            // ? Expired or logged out sessions are treated like a missing cookie
+            Err(ServiceErr::NotFound) => Outcome::Forward(Status::Unauthorized),
+            Err(e) => {
+                tracing::error!(error = %e, "failed to load session");
+                Outcome::Error((Status::InternalServerError, ()))
+            }
+        }
+    }
+}
+
+// NOTE SameSite=Lax keeps other sites from posting forms with the session, so the forms don't need CSRF tokens
+fn add_session_cookie(cookies: &CookieJar<'_>, session: SessionToken) {
+    let cookie = Cookie::build((SESSION_COOKIE, session.into_inner()))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::days(SESSION_TTL_DAYS));
+    cookies.add(cookie);
+}
+
+fn form_errors<'v>(context: &'v rocket::form::Context<'_>) -> Vec<&'v str> {
+    context
+        .errors()
+        .map(|err| {
+            use rocket::form::error::ErrorKind;
+            if let ErrorKind::Validation(msg) = &err.kind {
+                msg.as_ref()
+            } else {
+                tracing::warn!(error = %err, "unhandled form error");
+                "An error ocurred, please try again"
+            }
+        })
+        .collect()
+}
+
+type AccountResult = Result<Redirect, (Status, RawHtml<String>)>;
+
+/// * Logs in with the submitted credentials and renders the errors on the page that was submitted
+async fn start_session(
+    req: service::ask::Credentials,
+    page: ctx::Account,
+    cookies: &CookieJar<'_>,
+    database: &AppDatabase,
+    renderer: &Renderer<'_>,
+) -> AccountResult {
+    match action::login(req, database.get_pool()).await {
+        Ok((_, session)) => {
+            add_session_cookie(cookies, session);
+            Ok(Redirect::to(uri!(dashboard)))
+        }
+        Err(ServiceErr::PermissionErr(msg)) => Err((
+            Status::Unauthorized,
+            RawHtml(renderer.render(page, &[msg.as_str()])),
+        )),
+        Err(e) => {
+            tracing::error!(error = %e, "internal error");
+            Err((
+                Status::InternalServerError,
+                RawHtml(renderer.render(page, &["A server error occured. Please try again"])),
+            ))
+        }
+    }
+}
+
+#[rocket::get("/login")]
+fn login_page(renderer: &State<Renderer<'_>>) -> RawHtml<String> {
+    RawHtml(renderer.render(ctx::Account::login(), &[]))
+}
+
+#[rocket::post("/login", data = "<form>")]
+async fn login(
+    form: Form<Contextual<'_, form::Credentials>>,
+    cookies: &CookieJar<'_>,
+    database: &State<AppDatabase>,
+    renderer: &State<Renderer<'_>>,
+) -> AccountResult {
+    let form = form.into_inner();
+    let Some(value) = form.value else {
+        return Err((
+            Status::BadRequest,
+            RawHtml(renderer.render(ctx::Account::login(), &form_errors(&form.context))),
+        ));
+    };
+    let req = service::ask::Credentials {
+        username: value.username,
+        password: value.password,
+    };
+    start_session(req, ctx::Account::login(), cookies, database, renderer).await
+}
+
+#[rocket::get("/register")]
+fn register_page(renderer: &State<Renderer<'_>>) -> RawHtml<String> {
+    RawHtml(renderer.render(ctx::Account::register(), &[]))
+}
+
+/// * Users are logged in right after registering
+#[rocket::post("/register", data = "<form>")]
+async fn register(
+    form: Form<Contextual<'_, form::Credentials>>,
+    cookies: &CookieJar<'_>,
+    database: &State<AppDatabase>,
+    renderer: &State<Renderer<'_>>,
+) -> AccountResult {
+    let form = form.into_inner();
+    let render_error = |status, msg: &str| {
+        Err((
+            status,
+            RawHtml(renderer.render(ctx::Account::register(), &[msg])),
+        ))
+    };
+    let Some(value) = form.value else {
+        return Err((
+            Status::BadRequest,
+            RawHtml(renderer.render(ctx::Account::register(), &form_errors(&form.context))),
+        ));
+    };
+    let req = service::ask::Credentials {
+        username: value.username.clone(),
+        password: value.password.clone(),
+    };
+    match action::register(req, database.get_pool()).await {
+        Ok(_) => {}
+        Err(ServiceErr::Conflict(msg)) => return render_error(Status::Conflict, &msg),
+        Err(ServiceErr::User(e)) => return render_error(Status::BadRequest, &e.to_string()),
+        Err(e) => {
+            tracing::error!(error = %e, "internal error");
+            return render_error(
+                Status::InternalServerError,
+                "A server error occured. Please try again",
+            );
+        }
+    }
+    let req = service::ask::Credentials {
+        username: value.username,
+        password: value.password,
+    };
+    start_session(req, ctx::Account::register(), cookies, database, renderer).await
+}
+
+#[rocket::post("/logout")]
+async fn logout(cookies: &CookieJar<'_>, database: &State<AppDatabase>) -> Redirect {
+    if let Some(cookie) = cookies.get(SESSION_COOKIE) {
+        let session = SessionToken::from(cookie.value());
+        // ? The cookie is removed either way, the session would only linger until it expires
+        if let Err(e) = action::logout(&session, database.get_pool()).await {
+            tracing::warn!(error = %e, "failed to delete session");
+        }
+    }
+    cookies.remove(Cookie::build(SESSION_COOKIE).path("/"));
+    Redirect::to(uri!(login_page))
+}
+
+#[rocket::get("/dashboard")]
+async fn dashboard(
+    user: CurrentUser,
+    flash: Option<FlashMessage<'_>>,
+    database: &State<AppDatabase>,
+    renderer: &State<Renderer<'_>>,
+) -> Result<RawHtml<String>, PageErr> {
+    let CurrentUser(user) = user;
+    let clips = action::list_user_clips(&user, database.get_pool())
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "internal error");
+            PageErr::Internal("server error".to_owned())
+        })?;
+    // * Results of the edit and delete actions, which redirect back to the dashboard
+    let (errors, message) = match &flash {
+        Some(flash) if flash.kind() == "error" => (vec![flash.message()], None),
+        Some(flash) => (vec![], Some(flash.message().to_owned())),
+        None => (vec![], None),
+    };
+    let context = ctx::Dashboard::new(user.username, clips, message);
+    Ok(RawHtml(renderer.render(context, &errors)))
+}
+
+/// * Every dashboard page sends visitors that aren't logged in to the login page
+#[rocket::get("/dashboard/<_..>", rank = 2)]
+fn dashboard_login() -> Redirect {
+    Redirect::to(uri!(login_page))
+}
+
+fn back_to_dashboard(result: Result<&str, ServiceErr>) -> Flash<Redirect> {
+    let redirect = Redirect::to(uri!(dashboard));
+    match result {
+        Ok(msg) => Flash::success(redirect, msg),
+        Err(ServiceErr::PermissionErr(msg)) => Flash::error(redirect, msg),
+        Err(ServiceErr::NotFound) => Flash::error(redirect, "Clip not found"),
+        Err(e) => {
+            tracing::error!(error = %e, "internal error");
+            Flash::error(redirect, "A server error occured. Please try again")
+        }
+    }
+}
+
+#[rocket::get("/dashboard/<shortcode>/edit")]
+async fn edit_clip_page(
+    shortcode: Shortcode,
+    user: CurrentUser,
+    flash: Option<FlashMessage<'_>>,
+    database: &State<AppDatabase>,
+    renderer: &State<Renderer<'_>>,
+) -> Result<RawHtml<String>, Flash<Redirect>> {
+    let clip = action::get_owned_clip(shortcode, &user.0, database.get_pool())
+        .await
+        .map_err(|e| back_to_dashboard(Err(e)))?;
+    // * Errors of a failed edit, which redirects back to this page
+    let errors = flash
+        .as_ref()
+        .filter(|flash| flash.kind() == "error")
+        .map(|flash| vec![flash.message()])
+        .unwrap_or_default();
+    Ok(RawHtml(renderer.render(ctx::EditClip::new(clip), &errors)))
+}
+
+/// * The owner doesn't need the clip's edit secret
+#[rocket::post("/dashboard/<shortcode>/edit", data = "<form>")]
+async fn edit_clip(
+    shortcode: Shortcode,
+    form: Form<Contextual<'_, form::EditClip>>,
+    user: CurrentUser,
+    database: &State<AppDatabase>,
+    title_limit: &State<TitleLimit>,
+) -> Flash<Redirect> {
+    let form = form.into_inner();
+    let back_to_edit = |msg: &str| {
+        Flash::error(
+            Redirect::to(uri!(edit_clip_page(shortcode = shortcode.clone()))),
+            msg,
+        )
+    };
+    let Some(value) = form.value else {
+        return back_to_edit(&form_errors(&form.context).join(", "));
+    };
+    let req = service::ask::UpdateClip {
+        shortcode: shortcode.clone(),
+        content: value.content,
+        title: value.title,
+        expires: value.expires,
+        keep_password: !value.remove_password && !value.password.has_password(),
+        password: value.password,
+        language: value.language,
+        edit_secret: Default::default(),
+        user: Some(user.0.user_id),
+    };
+    match action::update_clip(req, title_limit, database.get_pool()).await {
+        Ok(_) => back_to_dashboard(Ok("Clip updated")),
+        Err(ServiceErr::Clip(e)) => back_to_edit(&e.to_string()),
+        Err(e) => back_to_dashboard(Err(e)),
+    }
+}
+
+#[rocket::post("/dashboard/<shortcode>/delete")]
+async fn delete_clip(
+    shortcode: Shortcode,
+    user: CurrentUser,
+    hit_counter: &State<HitCounter>,
+    database: &State<AppDatabase>,
+) -> Flash<Redirect> {
+    let req = service::ask::DeleteClip {
+        shortcode: shortcode.clone(),
+        edit_secret: Default::default(),
+        user: Some(user.0.user_id),
+    };
+    match action::delete_clip(req, database.get_pool()).await {
+        Ok(_) => {
+            hit_counter.remove(shortcode);
+            back_to_dashboard(Ok("Clip deleted"))
+        }
+        Err(e) => back_to_dashboard(Err(e)),
+    }
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![
+        login_page,
+        login,
+        register_page,
+        register,
+        logout,
+        dashboard,
+        dashboard_login,
+        edit_clip_page,
+        edit_clip,
+        delete_clip
+    ]
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::data::AppDatabase;
+    use crate::domain::{ApiScopes, Username};
+    use crate::service::{action, ask};
+    use crate::test::async_runtime;
+    use crate::web::{api::API_KEY_HEADER, test::client};
+    use crate::Shortcode;
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::blocking::Client;
+
+    fn post_form(client: &Client, path: &str, body: &str) -> (Status, Option<String>) {
+        let response = client
+            .post(path.to_owned())
+            .header(ContentType::Form)
+            .body(body)
+            .dispatch();
+        let location = response.headers().get_one("Location").map(str::to_owned);
+        (response.status(), location)
+    }
+
+    fn clip_content(client: &Client, shortcode: &str) -> Option<String> {
+        let response = client.get(format!("/clip/raw/{shortcode}")).dispatch();
+        (response.status() == Status::Ok)
+            .then(|| response.into_string())
+            .flatten()
+    }
+
+    #[test]
+    fn users_manage_their_clips_from_the_dashboard() {
+        let client = client();
+
+        let (status, _) = post_form(&client, "/register", "username=alice&password=short");
+        assert_eq!(status, Status::BadRequest);
+        let (status, location) =
+            post_form(&client, "/register", "username=alice&password=password123");
+        assert_eq!(status, Status::SeeOther);
+        assert_eq!(location.as_deref(), Some("/dashboard"));
+        let (status, _) = post_form(&client, "/register", "username=ALICE&password=password123");
+        assert_eq!(status, Status::Conflict);
+
+        // * Clips posted while logged in belong to the user
+        let (_, location) = post_form(
+            &client,
+            "/",
+            "content=first+draft&title=Mine&expires=&password=",
+        );
+        let shortcode = location.unwrap().trim_start_matches("/clip/").to_owned();
+        let dashboard = client.get("/dashboard").dispatch().into_string().unwrap();
+        assert!(dashboard.contains("Mine"));
+        assert!(dashboard.contains(&format!("/dashboard/{shortcode}/edit")));
+
+        // ? The owner doesn't need the edit secret
+        let edit_page = client
+            .get(format!("/dashboard/{shortcode}/edit"))
+            .dispatch();
+        assert_eq!(edit_page.status(), Status::Ok);
+        let (_, location) = post_form(
+            &client,
+            &format!("/dashboard/{shortcode}/edit"),
+            "content=final&title=Mine&expires=&password=&language=",
+        );
+        assert_eq!(location.as_deref(), Some("/dashboard"));
+        assert_eq!(clip_content(&client, &shortcode).as_deref(), Some("final"));
+
+        // * Other users can't edit or delete the clip
+        post_form(&client, "/logout", "");
+        let response = client.get("/dashboard").dispatch();
+        assert_eq!(response.headers().get_one("Location"), Some("/login"));
+        post_form(&client, "/register", "username=bob&password=password456");
+        post_form(&client, &format!("/dashboard/{shortcode}/delete"), "");
+        post_form(
+            &client,
+            &format!("/dashboard/{shortcode}/edit"),
+            "content=hijacked&title=&expires=&password=&language=",
+        );
+        assert_eq!(clip_content(&client, &shortcode).as_deref(), Some("final"));
+        assert!(!client
+            .get("/dashboard")
+            .dispatch()
+            .into_string()
+            .unwrap()
+            .contains("Mine"));
+
+        post_form(&client, "/logout", "");
+        let (status, _) = post_form(&client, "/login", "username=alice&password=password456");
+        assert_eq!(status, Status::Unauthorized);
+        let (status, _) = post_form(&client, "/login", "username=nobody&password=password123");
+        assert_eq!(status, Status::Unauthorized);
+        let (status, _) = post_form(&client, "/login", "username=Alice&password=password123");
+        assert_eq!(status, Status::SeeOther);
+        post_form(&client, &format!("/dashboard/{shortcode}/delete"), "");
+        assert!(clip_content(&client, &shortcode).is_none());
+    }
+
+    #[test]
+    fn clips_created_with_a_users_api_key_belong_to_them() {
+        let rt = async_runtime();
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        post_form(&client, "/register", "username=carol&password=password123");
+        let req = ask::NewApiKey {
+            name: "carol".to_owned(),
+            scopes: "admin".parse::<ApiScopes>().unwrap(),
+            expires: Default::default(),
+            owner: Some(Username::new("carol").unwrap()),
+        };
+        let (key, info) = rt
+            .block_on(async { action::generate_api_key(req, db.get_pool()).await })
+            .unwrap();
+        assert_eq!(info.owner_name.unwrap().as_str(), "carol");
+        let key_header = || Header::new(API_KEY_HEADER, key.to_base64());
+
+        let response = client
+            .post("/api/v1/clip")
+            .header(ContentType::JSON)
+            .header(key_header())
+            .body(r#"{"content": "from the api", "title": "Scripted", "expires": null, "password": null}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let clip = response
+            .into_json::<crate::web::api::v1::NewClipResponse>()
+            .unwrap()
+            .clip;
+        let dashboard = client.get("/dashboard").dispatch().into_string().unwrap();
+        assert!(dashboard.contains("Scripted"));
+
+        let response = client
+            .delete(format!("/api/v1/clip/{}", clip.shortcode.as_str()))
+            .header(key_header())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let shortcode: Shortcode = clip.shortcode;
+        assert!(clip_content(&client, shortcode.as_str()).is_none());
+    }
+}
@@ -0,0 +1,906 @@
+use crate::{
+    data::AppDatabase,
+    domain::{
+        clip::field::{EditSecret, Password},
+        ApiKeyInfo, ApiScope, UserId,
+    },
+    service::{self, action, token::AccessToken},
+    web::request_id::RequestId,
+    ServiceErr, Shortcode,
+};
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::{CookieJar, Header, Status},
+    request::{FromRequest, Outcome, Request},
+    response::{self, Responder, Response},
+    serde::json::Json,
+    State,
+};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+pub mod v1;
+
+/// * Where v1 was mounted before the API was versioned, it's kept as an alias of v1 until [`LEGACY_SUNSET`]
+pub const LEGACY_BASE: &str = "/api/clip";
+/// * When the legacy path was deprecated, as a Unix timestamp
+pub const LEGACY_DEPRECATED: i64 = 1_792_281_600;
+/// * When the legacy path will be removed, as an HTTP date
+pub const LEGACY_SUNSET: &str = "Sun, 18 Apr 2027 00:00:00 GMT";
+pub const API_KEY_HEADER: &str = "x-api-key";
+pub const PASSWORD_HEADER: &str = "x-clip-password";
+pub const ACCESS_TOKEN_HEADER: &str = "x-clip-token";
+pub const EDIT_SECRET_HEADER: &str = "x-edit-secret";
+
+/// * Body of every error returned by the JSON API
+/// * `code` is meant for programs and doesn't change, `message` is meant for people and may change
+#[derive(Clone, Debug, Deserialize, Serialize, thiserror::Error, ToSchema)]
+#[error("{message} ({code}, request {request_id})")]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Object, required = false)]
+    pub details: Option<serde_json::Value>,
+    #[serde(default)]
+    #[schema(value_type = String, required = true)]
+    pub request_id: RequestId,
+}
+
+impl ErrorResponse {
+    pub fn new(code: &str, message: String, request_id: &RequestId) -> Self {
+        Self {
+            code: code.to_owned(),
+            message,
+            details: None,
+            request_id: request_id.clone(),
+        }
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum ApiKeyErr {
+    #[error("API key missing, send it in the {API_KEY_HEADER} header")]
+    Missing,
+    #[error("API key not found")]
+    NotFound,
+    #[error("invalid API key format: {0}")]
+    Decode(String),
+    #[error("API key '{0}' expired")]
+    Expired(String),
+    #[error("API key '{name}' lacks the '{scope}' scope")]
+    MissingScope { name: String, scope: ApiScope },
+}
+
+impl ApiKeyErr {
+    pub fn status(&self) -> Status {
+        match self {
+            Self::Missing | Self::NotFound | Self::Decode(_) => Status::BadRequest,
+            Self::Expired(_) => Status::Unauthorized,
+            Self::MissingScope { .. } => Status::Forbidden,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Missing => "api_key_missing",
+            Self::NotFound => "api_key_not_found",
+            Self::Decode(_) => "api_key_invalid",
+            Self::Expired(_) => "api_key_expired",
+            Self::MissingScope { .. } => "api_key_missing_scope",
+        }
+    }
+
+    fn into_response(self, request_id: &RequestId) -> ErrorResponse {
+        let response = ErrorResponse::new(self.code(), self.to_string(), request_id);
+        match self {
+            Self::MissingScope { scope, .. } => {
+                response.with_details(serde_json::json!({ "required_scope": scope }))
+            }
+            _ => response,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiKey(Vec<u8>);
+
+impl ApiKey {
+    pub fn new() -> Self {
+        let key = (0..16).map(|_| rand::random::<u8>()).collect();
+        Self(key)
+    }
+
+    pub fn to_base64(&self) -> String {
+        base64::encode(self.0.as_slice())
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl Default for ApiKey {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FromStr for ApiKey {
+    type Err = ApiKeyErr;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        base64::decode(s)
+            .map(Self)
+            .map_err(|e| ApiKeyErr::Decode(e.to_string()))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiErr {
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("server error: {0}")]
+    Server(String),
+    #[error("client error: {1}")]
+    User(&'static str, String),
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("invalid api key: {0}")]
+    Key(#[from] ApiKeyErr),
+    #[error("conflict: {0}")]
+    Conflict(String),
+}
+
+impl ApiErr {
+    pub fn status(&self) -> Status {
+        match self {
+            Self::NotFound(_) => Status::NotFound,
+            Self::Server(_) => Status::InternalServerError,
+            Self::User(..) => Status::BadRequest,
+            Self::Unauthorized(_) => Status::Unauthorized,
+            Self::Key(e) => e.status(),
+            Self::Conflict(_) => Status::Conflict,
+        }
+    }
+
+    pub fn into_response(self, request_id: &RequestId) -> ErrorResponse {
+        let (code, message) = match self {
+            Self::NotFound(msg) => ("not_found", msg),
+            Self::Server(msg) => ("internal_error", msg),
+            Self::User(code, msg) => (code, msg),
+            Self::Unauthorized(msg) => ("permission_denied", msg),
+            Self::Key(e) => return e.into_response(request_id),
+            Self::Conflict(msg) => ("conflict", msg),
+        };
+        ErrorResponse::new(code, message, request_id)
+    }
+}
+
+impl From<ServiceErr> for ApiErr {
+    fn from(err: ServiceErr) -> Self {
+        match err {
+            ServiceErr::Clip(c) => Self::User(c.code(), c.to_string()),
+            ServiceErr::NotFound => Self::NotFound("entity not found".to_string()),
+            ServiceErr::Data(_) => Self::Server("internal server error".to_string()),
+            ServiceErr::PermissionErr(msg) => Self::Unauthorized(msg),
+            ServiceErr::Conflict(msg) => Self::Conflict(msg),
+            ServiceErr::ApiKey(e) => Self::User(e.code(), e.to_string()),
+            ServiceErr::User(e) => Self::User(e.code(), e.to_string()),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiErr {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let status = self.status();
+        let body = self.into_response(RequestId::of(req));
+        Response::build_from(Json(body).respond_to(req)?)
+            .status(status)
+            .ok()
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiKey {
+    type Error = ApiErr;
+
+    // NOTE API Request guard, the scope the key needs follows from the request's method
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        fn server_error() -> Outcome<ApiKey, ApiErr> {
+            Outcome::Error((
+                Status::InternalServerError,
+                ApiErr::Server("server error".to_string()),
+            ))
+        }
+        // ? Catchers don't get the guard's error, so it's cached for the catcher to respond with
+        let key_error = |e: ApiKeyErr| {
+            req.local_cache(|| catcher::GuardErr(Some(e.clone())));
+            Outcome::Error((e.status(), ApiErr::Key(e)))
+        };
+        let (api_key, info) = match lookup_key(req).await {
+            KeyLookup::Found(api_key, info) => (api_key.clone(), info.clone()),
+            KeyLookup::Invalid(e) => return key_error(e.clone()),
+            KeyLookup::ServerErr => return server_error(),
+        };
+        if info.is_expired() {
+            return key_error(ApiKeyErr::Expired(info.name));
+        }
+        let scope = ApiScope::for_method(req.method());
+        if !info.scopes.allows(scope) {
+            return key_error(ApiKeyErr::MissingScope {
+                name: info.name,
+                scope,
+            });
+        }
+
+        req.local_cache(|| KeyOwner(info.owner.clone()));
+        // ? Failing to record the usage shouldn't fail the request
+        if info.last_used_is_stale() {
+            let db = match req.guard::<&State<AppDatabase>>().await {
+                Outcome::Success(db) => db,
+                _ => return server_error(),
+            };
+            if let Err(e) = action::touch_api_key(&api_key, db.get_pool()).await {
+                tracing::warn!(error = %e, "failed to update api key usage");
+            }
+        }
+        Outcome::Success(api_key)
+    }
+}
+
+/// * Stored key sent with the request, the rate limiter needs it before the guard runs so it's only looked up once
+enum KeyLookup {
+    Found(ApiKey, ApiKeyInfo),
+    Invalid(ApiKeyErr),
+    ServerErr,
+}
+
+async fn lookup_key<'r>(req: &'r Request<'_>) -> &'r KeyLookup {
+    req.local_cache_async(async {
+        let Some(key) = req.headers().get_one(API_KEY_HEADER) else {
+            return KeyLookup::Invalid(ApiKeyErr::Missing);
+        };
+        let db = match req.guard::<&State<AppDatabase>>().await {
+            Outcome::Success(db) => db,
+            _ => return KeyLookup::ServerErr,
+        };
+        let api_key = match ApiKey::from_str(key) {
+            Ok(key) => key,
+            Err(e) => return KeyLookup::Invalid(e),
+        };
+        match action::get_api_key(&api_key, db.get_pool()).await {
+            Ok(info) => KeyLookup::Found(api_key, info),
+            Err(ServiceErr::NotFound) => KeyLookup::Invalid(ApiKeyErr::NotFound),
+            Err(_) => KeyLookup::ServerErr,
+        }
+    })
+    .await
+}
+
+/// * Key of the request if it exists and hasn't expired, whether it has the scope of the request or not
+pub async fn valid_key<'r>(req: &'r Request<'_>) -> Option<&'r ApiKey> {
+    match lookup_key(req).await {
+        KeyLookup::Found(api_key, info) if !info.is_expired() => Some(api_key),
+        _ => None,
+    }
+}
+
+/// * User that owns the request's API key, if any
+/// * Runs the API key guard, so routes that take it don't need the key itself
+#[derive(Clone, Debug)]
+pub struct KeyOwner(pub Option<UserId>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for KeyOwner {
+    type Error = ApiErr;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.guard::<ApiKey>().await {
+            Outcome::Success(_) => Outcome::Success(req.local_cache(|| KeyOwner(None)).clone()),
+            Outcome::Error(e) => Outcome::Error(e),
+            Outcome::Forward(status) => Outcome::Forward(status),
+        }
+    }
+}
+
+/// * Credentials for password protected clips and for editing clips, sent by API clients as headers
+/// * All of them are optional, so this guard never fails
+pub struct ClipCredentials {
+    pub password: Password,
+    pub token: Option<AccessToken>,
+    pub edit_secret: EditSecret,
+}
+
+impl ClipCredentials {
+    pub fn into_get_clip(
+        self,
+        shortcode: Shortcode,
+        cookies: &CookieJar<'_>,
+    ) -> service::ask::GetClip {
+        let mut req = service::ask::GetClip::from_cookies(shortcode, cookies);
+        req.password = self.password;
+        // ? A token sent as a header takes precedence over the one stored in the cookies
+        req.token = self.token.or(req.token);
+        req
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClipCredentials {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = req.headers();
+        Outcome::Success(Self {
+            password: headers
+                .get_one(PASSWORD_HEADER)
+                .and_then(|password| Password::new(password.to_owned()).ok())
+                .unwrap_or_default(),
+            token: headers.get_one(ACCESS_TOKEN_HEADER).map(AccessToken::from),
+            edit_secret: headers
+                .get_one(EDIT_SECRET_HEADER)
+                .map(|secret| EditSecret::new(secret.to_owned()))
+                .unwrap_or_default(),
+        })
+    }
+}
+
+type ModResult<T> = Result<Json<T>, ApiErr>;
+
+/// * Marks the responses of the legacy path as deprecated, with a link to the path that replaces it
+/// * e.g. `Deprecation: @1792281600`, `Sunset: Sun, 18 Apr 2027 00:00:00 GMT` and `Link: </api/v1/clip>; rel="successor-version"`
+pub struct LegacyHeaders;
+
+impl LegacyHeaders {
+    fn is_legacy(req: &Request<'_>) -> bool {
+        let mut segments = req.uri().path().segments();
+        // ? Compared by segment, so paths such as /api/clips aren't mistaken for the legacy path
+        LEGACY_BASE
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .all(|segment| segments.next() == Some(segment))
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for LegacyHeaders {
+    fn info(&self) -> Info {
+        Info {
+            name: "Legacy API deprecation headers",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        if !Self::is_legacy(req) {
+            return;
+        }
+        res.set_header(Header::new("Deprecation", format!("@{LEGACY_DEPRECATED}")));
+        res.set_header(Header::new("Sunset", LEGACY_SUNSET));
+        res.set_header(Header::new(
+            "Link",
+            format!(r#"<{}>; rel="successor-version""#, v1::BASE),
+        ));
+    }
+}
+
+pub mod catcher {
+    use super::{ApiKeyErr, ErrorResponse};
+    use crate::web::{logging::RequestSpan, request_id::RequestId};
+    use rocket::http::Status;
+    use rocket::serde::json::Json;
+    use rocket::{catch, catchers, Catcher, Request};
+
+    /// * Error of the API key guard, it's the most specific explanation a catcher can give
+    pub(super) struct GuardErr(pub Option<ApiKeyErr>);
+
+    fn respond(status: Status, req: &Request, code: &str, message: &str) -> Json<ErrorResponse> {
+        let request_id = RequestId::of(req);
+        match &req.local_cache(|| GuardErr(None)).0 {
+            Some(e) if e.status() == status => Json(e.clone().into_response(request_id)),
+            _ => Json(ErrorResponse::new(code, message.to_owned(), request_id)),
+        }
+    }
+
+    #[catch(default)]
+    fn default(status: Status, req: &Request) -> Json<ErrorResponse> {
+        tracing::error!(parent: RequestSpan::of(req), status = status.code, "request failed");
+        let code = status
+            .reason_lossy()
+            .to_lowercase()
+            .replace([' ', '-'], "_");
+        respond(status, req, &code, "something went wrong")
+    }
+
+    #[catch(500)]
+    fn internal_error(req: &Request) -> Json<ErrorResponse> {
+        tracing::error!(parent: RequestSpan::of(req), "internal error");
+        respond(
+            Status::InternalServerError,
+            req,
+            "internal_error",
+            "internal error",
+        )
+    }
+
+    #[catch(400)]
+    fn bad_request(req: &Request) -> Json<ErrorResponse> {
+        respond(
+            Status::BadRequest,
+            req,
+            "bad_request",
+            "the request is malformed",
+        )
+    }
+
+    #[catch(401)]
+    fn unauthorized(req: &Request) -> Json<ErrorResponse> {
+        respond(
+            Status::Unauthorized,
+            req,
+            "unauthorized",
+            "authentication failed",
+        )
+    }
+
+    #[catch(403)]
+    fn forbidden(req: &Request) -> Json<ErrorResponse> {
+        respond(
+            Status::Forbidden,
+            req,
+            "forbidden",
+            "the request isn't allowed",
+        )
+    }
+
+    #[catch(404)]
+    fn not_found(req: &Request) -> Json<ErrorResponse> {
+        respond(Status::NotFound, req, "not_found", "not found")
+    }
+
+    // ? Rocket answers with a 422 when a path parameter or the JSON body can't be parsed
+    #[catch(422)]
+    fn unprocessable_entity(req: &Request) -> Json<ErrorResponse> {
+        respond(
+            Status::UnprocessableEntity,
+            req,
+            "unprocessable_entity",
+            "the path or the body of the request is invalid",
+        )
+    }
+
+    pub fn catchers() -> Vec<Catcher> {
+        catchers![
+            bad_request,
+            unauthorized,
+            forbidden,
+            not_found,
+            unprocessable_entity,
+            internal_error,
+            default
+        ]
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::data::AppDatabase;
+    use crate::domain::ApiScopes;
+    use crate::service::{action, ask};
+    use crate::test::async_runtime;
+    use crate::web::request_id::REQUEST_ID_HEADER;
+    use crate::web::test::client;
+    use rocket::http::{ContentType, Header, Status};
+
+    fn api_key_header(key: &super::ApiKey) -> Header<'static> {
+        Header::new(super::API_KEY_HEADER, key.to_base64())
+    }
+
+    fn error_response(response: rocket::local::blocking::LocalResponse) -> super::ErrorResponse {
+        let request_id = response
+            .headers()
+            .get_one(REQUEST_ID_HEADER)
+            .map(str::to_owned);
+        let error = response.into_json::<super::ErrorResponse>().unwrap();
+        assert_eq!(Some(error.request_id.as_str()), request_id.as_deref());
+        error
+    }
+
+    #[test]
+    fn api_keys_are_scoped() {
+        let rt = async_runtime();
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        let req = ask::NewApiKey {
+            name: "reader".to_owned(),
+            scopes: "read".parse::<ApiScopes>().unwrap(),
+            expires: Default::default(),
+            owner: None,
+        };
+        let (key, info) = rt
+            .block_on(async { action::generate_api_key(req, db.get_pool()).await })
+            .unwrap();
+        assert!(info.last_used.is_none());
+
+        let response = client
+            .get("/api/v1/clip/missing")
+            .header(api_key_header(&key))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let info = rt
+            .block_on(async { action::get_api_key(&key, db.get_pool()).await })
+            .unwrap();
+        assert!(info.last_used.is_some());
+
+        let response = client
+            .post("/api/v1/clip")
+            .header(ContentType::JSON)
+            .header(api_key_header(&key))
+            .body(r#"{"content": "hello", "title": null, "expires": null, "password": null}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client.get("/api/v1/clip/missing").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        // * Keys are only created with `httpd keys create`
+        let response = client
+            .get("/api/v1/clip/key")
+            .header(api_key_header(&key))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn expired_api_keys_are_rejected() {
+        let rt = async_runtime();
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        let req = ask::NewApiKey {
+            name: "expiring".to_owned(),
+            scopes: "admin".parse::<ApiScopes>().unwrap(),
+            expires: "1h".parse().unwrap(),
+            owner: None,
+        };
+        let (key, info) = rt
+            .block_on(async { action::generate_api_key(req, db.get_pool()).await })
+            .unwrap();
+        assert!(info.scopes.allows(crate::domain::ApiScope::Delete));
+
+        let response = client
+            .get("/api/v1/clip/missing")
+            .header(api_key_header(&key))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        rt.block_on(async {
+            crate::data::test::execute(
+                "UPDATE api_keys SET expires = created WHERE name = 'expiring'",
+                db.get_pool(),
+            )
+            .await
+        })
+        .unwrap();
+        let response = client
+            .get("/api/v1/clip/missing")
+            .header(api_key_header(&key))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn new_clip_without_a_content_type_is_read_as_json() {
+        let rt = async_runtime();
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        let req = ask::NewApiKey {
+            name: "writer".to_owned(),
+            scopes: "create".parse::<ApiScopes>().unwrap(),
+            expires: Default::default(),
+            owner: None,
+        };
+        let (key, _) = rt
+            .block_on(async { action::generate_api_key(req, db.get_pool()).await })
+            .unwrap();
+
+        let response = client
+            .post("/api/v1/clip")
+            .header(api_key_header(&key))
+            .body(r#"{"content": "hello", "title": null, "expires": null, "password": null}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn errors_have_a_code_and_request_id() {
+        let rt = async_runtime();
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        let response = client
+            .get("/api/v1/clip/missing")
+            .header(Header::new(REQUEST_ID_HEADER, "proxy-id-1"))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let error = error_response(response);
+        assert_eq!(error.code, "api_key_missing");
+        assert_eq!(error.request_id.as_str(), "proxy-id-1");
+
+        let req = ask::NewApiKey {
+            name: "writer".to_owned(),
+            scopes: "create".parse::<ApiScopes>().unwrap(),
+            expires: Default::default(),
+            owner: None,
+        };
+        let (key, _) = rt
+            .block_on(async { action::generate_api_key(req, db.get_pool()).await })
+            .unwrap();
+
+        let response = client
+            .get("/api/v1/clip/missing")
+            .header(api_key_header(&key))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let error = error_response(response);
+        assert_eq!(error.code, "api_key_missing_scope");
+        assert_eq!(error.details.unwrap()["required_scope"], "read");
+
+        let response = client
+            .post("/api/v1/clip")
+            .header(ContentType::JSON)
+            .header(api_key_header(&key))
+            .body(r#"{"content": "", "title": null, "expires": null, "password": null}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(error_response(response).code, "empty_content");
+
+        // * JSON titles are held to the same limit as the ones posted from the form
+        let body = serde_json::json!({
+            "content": "hello", "title": "t".repeat(201), "expires": null, "password": null
+        });
+        let response = client
+            .post("/api/v1/clip")
+            .header(ContentType::JSON)
+            .header(api_key_header(&key))
+            .body(body.to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(error_response(response).code, "invalid_title");
+
+        let response = client
+            .post("/api/v1/clip")
+            .header(ContentType::JSON)
+            .header(api_key_header(&key))
+            .body("not json")
+            .dispatch();
+        let error = error_response(response);
+        assert!(["bad_request", "unprocessable_entity"].contains(&error.code.as_str()));
+
+        let response = client.get("/api/v1/clip/a/b/c/d").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(error_response(response).code, "not_found");
+    }
+
+    #[test]
+    fn clips_are_listed_and_searched_page_by_page() {
+        use super::v1::ClipPage;
+
+        let rt = async_runtime();
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+        let req = ask::NewApiKey {
+            name: "lister".to_owned(),
+            scopes: "read,create,update".parse::<ApiScopes>().unwrap(),
+            expires: Default::default(),
+            owner: None,
+        };
+        let (key, _) = rt
+            .block_on(async { action::generate_api_key(req, db.get_pool()).await })
+            .unwrap();
+
+        let clips = [
+            ("Rust notes", "ownership and borrowing", None, None),
+            ("Grocery list", "eggs and milk", None, None),
+            ("Secret rust plans", "borrowing the moon", Some("123"), None),
+            ("Burnt rust", "borrowing once", None, Some(1)),
+        ];
+        // ? The clip with limited views is created last
+        let mut burnt = serde_json::Value::Null;
+        for (title, content, password, max_views) in clips {
+            let body = serde_json::json!({
+                "title": title, "content": content, "expires": null, "password": password,
+                "max_views": max_views
+            });
+            let response = client
+                .post("/api/v1/clip")
+                .header(ContentType::JSON)
+                .header(api_key_header(&key))
+                .body(body.to_string())
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            burnt = response.into_json().unwrap();
+        }
+        // * Updates keep the content of a clip with limited views out of the index too
+        let body = serde_json::json!({
+            "shortcode": burnt["shortcode"], "title": "Burnt rust", "content": "borrowing twice",
+            "expires": null, "password": null, "edit_secret": burnt["edit_secret"]
+        });
+        let response = client
+            .put("/api/v1/clip")
+            .header(ContentType::JSON)
+            .header(api_key_header(&key))
+            .body(body.to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let list = |query: &str| {
+            let response = client
+                .get(format!("/api/v1/clip?{query}"))
+                .header(api_key_header(&key))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            response.into_json::<ClipPage>().unwrap()
+        };
+        let titles = |page: &ClipPage| {
+            page.clips
+                .iter()
+                .map(|clip| clip.title.clone().into_inner().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // * Protected clips and clips with limited views are listed, but only their titles are searchable and shown
+        let page = list("q=borrowing");
+        assert_eq!(titles(&page), ["Rust notes"]);
+        let page = list("q=rust");
+        assert_eq!(page.clips.len(), 3);
+        let secret = page.clips.iter().find(|clip| clip.protected).unwrap();
+        assert!(secret.content.is_none());
+        let burnt = page
+            .clips
+            .iter()
+            .find(|clip| clip.title.clone().into_inner().as_deref() == Some("Burnt rust"))
+            .unwrap();
+        assert!(burnt.content.is_none());
+        assert_eq!(list("title=LIST").clips.len(), 1);
+
+        let mut seen = vec![];
+        let mut query = "order=oldest&limit=2".to_owned();
+        loop {
+            let page = list(&query);
+            seen.extend(titles(&page));
+            match page.next {
+                Some(cursor) => query = format!("order=oldest&limit=2&cursor={cursor}"),
+                None => break,
+            }
+        }
+        assert_eq!(seen.len(), 4);
+        assert_eq!(seen, titles(&list("order=oldest")));
+
+        let response = client
+            .get("/api/v1/clip?limit=500")
+            .header(api_key_header(&key))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(error_response(response).code, "invalid_query");
+    }
+
+    #[test]
+    fn password_is_exchanged_for_an_access_token() {
+        let rt = async_runtime();
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+        let req = ask::NewApiKey {
+            name: "tokens".to_owned(),
+            scopes: "read,create".parse::<ApiScopes>().unwrap(),
+            expires: Default::default(),
+            owner: None,
+        };
+        let (key, _) = rt
+            .block_on(async { action::generate_api_key(req, db.get_pool()).await })
+            .unwrap();
+
+        let response = client
+            .post("/api/v1/clip")
+            .header(ContentType::JSON)
+            .header(api_key_header(&key))
+            .body(r#"{"content": "hello", "title": null, "expires": null, "password": "123"}"#)
+            .dispatch();
+        let clip = response
+            .into_json::<super::v1::NewClipResponse>()
+            .unwrap()
+            .clip;
+        let uri = format!("/api/v1/clip/{}", clip.shortcode.as_str());
+
+        let response = client.get(&uri).header(api_key_header(&key)).dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = client
+            .get(&uri)
+            .header(api_key_header(&key))
+            .header(Header::new(super::PASSWORD_HEADER, "123"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let token = response
+            .headers()
+            .get_one(super::ACCESS_TOKEN_HEADER)
+            .unwrap()
+            .to_owned();
+
+        // * The token unlocks the clip without the password, but doesn't get a new token
+        let response = client
+            .get(&uri)
+            .header(api_key_header(&key))
+            .header(Header::new(super::ACCESS_TOKEN_HEADER, token))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response
+            .headers()
+            .get_one(super::ACCESS_TOKEN_HEADER)
+            .is_none());
+    }
+
+    #[test]
+    fn legacy_path_is_a_deprecated_alias_of_v1() {
+        let rt = async_runtime();
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+        let req = ask::NewApiKey {
+            name: "legacy".to_owned(),
+            scopes: "read,create".parse::<ApiScopes>().unwrap(),
+            expires: Default::default(),
+            owner: None,
+        };
+        let (key, _) = rt
+            .block_on(async { action::generate_api_key(req, db.get_pool()).await })
+            .unwrap();
+
+        let response = client
+            .post(super::LEGACY_BASE)
+            .header(ContentType::JSON)
+            .header(api_key_header(&key))
+            .body(r#"{"content": "hello", "title": null, "expires": null, "password": null}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get_one("Sunset"),
+            Some(super::LEGACY_SUNSET)
+        );
+        let clip = response
+            .into_json::<super::v1::NewClipResponse>()
+            .unwrap()
+            .clip;
+
+        let response = client
+            .get(format!("{}/{}", super::v1::BASE, clip.shortcode.as_str()))
+            .header(api_key_header(&key))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.headers().get_one("Deprecation").is_none());
+        assert_eq!(
+            response
+                .into_json::<super::v1::Clip>()
+                .unwrap()
+                .content
+                .as_str(),
+            clip.content.as_str()
+        );
+
+        // * Errors of the legacy path are deprecated too
+        let response = client
+            .get(format!("{}/missing", super::LEGACY_BASE))
+            .header(api_key_header(&key))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(
+            response.headers().get_one("Deprecation"),
+            Some(format!("@{}", super::LEGACY_DEPRECATED).as_str())
+        );
+        assert_eq!(error_response(response).code, "not_found");
+    }
+}
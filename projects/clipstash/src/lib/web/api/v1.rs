@@ -0,0 +1,359 @@
+use super::{ApiErr, ApiKey, ClipCredentials, KeyOwner, ModResult, ACCESS_TOKEN_HEADER};
+use crate::{
+    data::AppDatabase,
+    domain::{
+        self,
+        clip::field::{self, Attachment, ShortcodeGenerator, TitleLimit},
+        Cursor,
+    },
+    service::{
+        self, action,
+        token::{AccessToken, AccessTokenSigner},
+    },
+    web::{form, hitcounter::HitCounter, openapi},
+    ServiceErr, Shortcode, Time,
+};
+use rocket::{
+    form::Form,
+    http::{CookieJar, Header},
+    request::Request,
+    response::{self, Responder},
+    serde::json::Json,
+    State,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// * Where the routes are mounted, they're also mounted at [`super::LEGACY_BASE`]
+pub const BASE: &str = "/api/v1/clip";
+
+// NOTE The handlers convert the domain types into the types below, so changing a domain type doesn't change the JSON of v1
+// NOTE A version that changes the JSON, e.g. to expose `clip_id`, gets its own module with its own types
+
+/// * A clip, encrypted clips hold ciphertext in `content`
+/// * The password, edit secret and owner of a clip are never sent
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct Clip {
+    #[schema(value_type = String)]
+    pub shortcode: field::Shortcode,
+    #[schema(value_type = String)]
+    pub content: field::Content,
+    // ? Encrypted clips hold ciphertext in `content`, only clients with the key can read them
+    #[serde(default)]
+    #[schema(value_type = bool, required = true)]
+    pub encrypted: field::Encrypted,
+    #[schema(value_type = Option<String>, required = true)]
+    pub title: field::Title,
+    #[schema(value_type = String, format = DateTime)]
+    pub posted: field::Posted,
+    #[schema(value_type = Option<String>, format = DateTime, required = true)]
+    pub expires: field::Expires,
+    #[serde(default)]
+    #[schema(schema_with = openapi::language, required = true)]
+    pub language: field::Language,
+    // ? Set for clips created from a file, the content holds the file name in that case
+    #[serde(default)]
+    #[schema(required = true)]
+    pub attachment: Option<Attachment>,
+    #[schema(value_type = u64, minimum = 0)]
+    pub hits: field::Hits,
+    #[serde(default)]
+    #[schema(value_type = Option<u32>, minimum = 1, required = true)]
+    pub max_views: field::MaxViews,
+    #[serde(default)]
+    #[schema(value_type = u64, minimum = 0, required = true)]
+    pub views: field::Hits,
+}
+
+impl From<domain::Clip> for Clip {
+    fn from(clip: domain::Clip) -> Self {
+        Self {
+            shortcode: clip.shortcode,
+            content: clip.content,
+            encrypted: clip.encrypted,
+            title: clip.title,
+            posted: clip.posted,
+            expires: clip.expires,
+            language: clip.language,
+            attachment: clip.attachment,
+            hits: clip.hits,
+            max_views: clip.max_views,
+            views: clip.views,
+        }
+    }
+}
+
+/// * A listed clip, the content of password protected clips and clips with limited views is left out
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct ClipSummary {
+    #[schema(value_type = String)]
+    pub shortcode: field::Shortcode,
+    #[schema(value_type = Option<String>, required = true)]
+    pub title: field::Title,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = String, required = false)]
+    pub content: Option<field::Content>,
+    pub protected: bool,
+    #[serde(default)]
+    #[schema(value_type = bool, required = true)]
+    pub encrypted: field::Encrypted,
+    #[schema(value_type = String, format = DateTime)]
+    pub posted: field::Posted,
+    #[schema(value_type = Option<String>, format = DateTime, required = true)]
+    pub expires: field::Expires,
+    #[serde(default)]
+    #[schema(schema_with = openapi::language, required = true)]
+    pub language: field::Language,
+    #[serde(default)]
+    #[schema(required = true)]
+    pub attachment: Option<Attachment>,
+    #[schema(value_type = u64, minimum = 0)]
+    pub hits: field::Hits,
+}
+
+impl From<domain::ClipSummary> for ClipSummary {
+    fn from(clip: domain::ClipSummary) -> Self {
+        Self {
+            shortcode: clip.shortcode,
+            title: clip.title,
+            content: clip.content,
+            protected: clip.protected,
+            encrypted: clip.encrypted,
+            posted: clip.posted,
+            expires: clip.expires,
+            language: clip.language,
+            attachment: clip.attachment,
+            hits: clip.hits,
+        }
+    }
+}
+
+/// * A page of listed clips, `next` is only set when there are more clips
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct ClipPage {
+    pub clips: Vec<ClipSummary>,
+    #[schema(schema_with = openapi::cursor, required = true)]
+    pub next: Option<Cursor>,
+}
+
+impl From<domain::ClipPage> for ClipPage {
+    fn from(page: domain::ClipPage) -> Self {
+        Self {
+            clips: page.clips.into_iter().map(ClipSummary::from).collect(),
+            next: page.next,
+        }
+    }
+}
+
+/// * A version of a clip, revisions are numbered from 1 and the latest one is the clip's current content
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct Revision {
+    #[schema(minimum = 1)]
+    pub revision: u32,
+    #[schema(value_type = String)]
+    pub content: field::Content,
+    #[schema(value_type = Option<String>, required = true)]
+    pub title: field::Title,
+    #[schema(value_type = Option<String>, format = DateTime, required = true)]
+    pub expires: field::Expires,
+    /// * When this revision was overwritten by an update, `null` for the current revision
+    #[schema(value_type = Option<String>, format = DateTime, required = true)]
+    pub replaced: Option<Time>,
+}
+
+impl From<domain::Revision> for Revision {
+    fn from(revision: domain::Revision) -> Self {
+        Self {
+            revision: revision.revision,
+            content: revision.content,
+            title: revision.title,
+            expires: revision.expires,
+            replaced: revision.replaced,
+        }
+    }
+}
+
+/// * The created clip along with its edit secret
+/// * The edit secret is only returned when the clip is created, it must be sent to update or delete the clip
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct NewClipResponse {
+    #[serde(flatten)]
+    pub clip: Clip,
+    pub edit_secret: String,
+}
+
+/// * A clip, along with an access token when it was unlocked with its password
+/// * The token is sent back in the `x-clip-token` header, so clients don't have to keep the password around
+pub struct ClipResponse {
+    pub clip: Clip,
+    pub token: Option<AccessToken>,
+}
+
+impl<'r> Responder<'r, 'static> for ClipResponse {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Json(self.clip).respond_to(req)?;
+        if let Some(token) = self.token {
+            response.set_header(Header::new(ACCESS_TOKEN_HEADER, token.into_inner()));
+        }
+        Ok(response)
+    }
+}
+
+/// * e.g. `GET /api/v1/clip?q=rust&order=newest&limit=20`, the `next` cursor of a page is passed as `cursor` to get the next one
+#[rocket::get("/?<query..>")]
+pub async fn list_clips(
+    query: form::ListClips,
+    database: &State<AppDatabase>,
+    _api_key: ApiKey,
+) -> ModResult<ClipPage> {
+    let req = service::ask::ListClips::try_from(query).map_err(ServiceErr::from)?;
+    let page = action::list_clips(req, database.get_pool()).await?;
+    Ok(Json(page.into()))
+}
+
+#[rocket::get("/<shortcode>")]
+pub async fn get_clip(
+    shortcode: Shortcode,
+    database: &State<AppDatabase>,
+    signer: &State<AccessTokenSigner>,
+    cookies: &CookieJar<'_>,
+    credentials: ClipCredentials,
+    hit_counter: &State<HitCounter>,
+    // NOTE _api_key is not used but it's needed to trigger the request guard
+    _api_key: ApiKey,
+) -> Result<ClipResponse, ApiErr> {
+    let req = credentials.into_get_clip(shortcode.clone(), cookies);
+    let sent_password = req.password.has_password();
+    let clip = action::get_clip(req, signer, database.get_pool()).await?;
+    hit_counter.hit(shortcode, 1);
+
+    // ? Tokens are only issued for the password, so a token can't be used to get a fresh one
+    let token = match sent_password && clip.password.has_password() {
+        true => Some(signer.issue(&clip)),
+        false => None,
+    };
+    Ok(ClipResponse {
+        clip: clip.into(),
+        token,
+    })
+}
+
+#[rocket::get("/<shortcode>/revisions")]
+pub async fn get_revisions(
+    shortcode: Shortcode,
+    database: &State<AppDatabase>,
+    signer: &State<AccessTokenSigner>,
+    cookies: &CookieJar<'_>,
+    credentials: ClipCredentials,
+    _api_key: ApiKey,
+) -> ModResult<Vec<Revision>> {
+    let req = credentials.into_get_clip(shortcode.clone(), cookies);
+    let revisions = action::get_revisions(req, signer, database.get_pool()).await?;
+    Ok(Json(revisions.into_iter().map(Revision::from).collect()))
+}
+
+#[rocket::get("/<shortcode>/revisions/<revision>")]
+pub async fn get_revision(
+    shortcode: Shortcode,
+    revision: u32,
+    database: &State<AppDatabase>,
+    signer: &State<AccessTokenSigner>,
+    cookies: &CookieJar<'_>,
+    credentials: ClipCredentials,
+    _api_key: ApiKey,
+) -> ModResult<Revision> {
+    let req = credentials.into_get_clip(shortcode.clone(), cookies);
+    let revision = action::get_revision(req, revision, signer, database.get_pool()).await?;
+    Ok(Json(revision.into()))
+}
+
+// NOTE Clients that don't send a content type still get their body parsed as JSON, multipart uploads are matched first
+#[rocket::post("/", data = "<req>", rank = 2)]
+pub async fn new_clip(
+    req: Json<service::ask::NewClip>,
+    database: &State<AppDatabase>,
+    shortcodes: &State<ShortcodeGenerator>,
+    title_limit: &State<TitleLimit>,
+    owner: KeyOwner,
+) -> ModResult<NewClipResponse> {
+    let req = service::ask::NewClip {
+        owner: owner.0,
+        ..req.into_inner()
+    };
+    let (clip, edit_secret) =
+        action::new_clip(req, shortcodes, title_limit, database.get_pool()).await?;
+    Ok(Json(NewClipResponse {
+        clip: clip.into(),
+        edit_secret: edit_secret.into_inner().unwrap_or_default(),
+    }))
+}
+
+/// * Same as `new_clip()`, but sent as a multipart form so a file can be uploaded
+#[rocket::post("/", format = "multipart/form-data", data = "<form>", rank = 1)]
+pub async fn upload_clip(
+    form: Form<form::NewClip>,
+    database: &State<AppDatabase>,
+    shortcodes: &State<ShortcodeGenerator>,
+    title_limit: &State<TitleLimit>,
+    owner: KeyOwner,
+) -> ModResult<NewClipResponse> {
+    let req = service::ask::NewClip::try_from(form.into_inner()).map_err(ServiceErr::from)?;
+    let req = service::ask::NewClip {
+        owner: owner.0,
+        ..req
+    };
+    let (clip, edit_secret) =
+        action::new_clip(req, shortcodes, title_limit, database.get_pool()).await?;
+    Ok(Json(NewClipResponse {
+        clip: clip.into(),
+        edit_secret: edit_secret.into_inner().unwrap_or_default(),
+    }))
+}
+
+#[rocket::put("/", data = "<req>")]
+pub async fn update_clip(
+    req: Json<service::ask::UpdateClip>,
+    database: &State<AppDatabase>,
+    title_limit: &State<TitleLimit>,
+    owner: KeyOwner,
+) -> ModResult<Clip> {
+    // ? The edit secret isn't needed for clips owned by the key's user
+    let req = service::ask::UpdateClip {
+        user: owner.0,
+        ..req.into_inner()
+    };
+    let clip = action::update_clip(req, title_limit, database.get_pool()).await?;
+    Ok(Json(clip.into()))
+}
+
+#[rocket::delete("/<shortcode>")]
+pub async fn delete_clip(
+    shortcode: Shortcode,
+    database: &State<AppDatabase>,
+    credentials: ClipCredentials,
+    hit_counter: &State<HitCounter>,
+    owner: KeyOwner,
+) -> ModResult<Clip> {
+    let req = service::ask::DeleteClip {
+        shortcode: shortcode.clone(),
+        edit_secret: credentials.edit_secret,
+        user: owner.0,
+    };
+    let clip = action::delete_clip(req, database.get_pool()).await?;
+    hit_counter.remove(shortcode);
+
+    Ok(Json(clip.into()))
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes!(
+        list_clips,
+        get_clip,
+        get_revisions,
+        get_revision,
+        new_clip,
+        upload_clip,
+        update_clip,
+        delete_clip
+    )
+}
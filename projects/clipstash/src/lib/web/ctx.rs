@@ -15,9 +15,18 @@ pub trait PageContext {
 }
 
 #[derive(Debug, Serialize)]
-// NOTE `{}` is necessary so this serializes properly
-#[derive(Default)]
-pub struct Home {}
+pub struct Home {
+    /// * Languages that can be picked in the form, as `(token, display name)` pairs
+    languages: &'static [(&'static str, &'static str)],
+}
+
+impl Default for Home {
+    fn default() -> Self {
+        Self {
+            languages: crate::domain::clip::field::LANGUAGES,
+        }
+    }
+}
 
 impl PageContext for Home {
     fn template_path(&self) -> &str {
@@ -31,6 +40,8 @@ impl PageContext for Home {
 #[derive(Debug, Serialize, Constructor)]
 pub struct ViewClip {
     pub clip: crate::domain::Clip,
+    /// * Syntax highlighted HTML of the content, `None` for plain text
+    pub highlighted: Option<String>,
 }
 
 impl PageContext for ViewClip {
@@ -55,3 +66,97 @@ impl PageContext for PasswordRequired {
         "Enter your password"
     }
 }
+
+#[derive(Debug, Serialize, Constructor)]
+pub struct ClipDiff {
+    shortcode: crate::Shortcode,
+    /// * Every revision number, used to pick the revisions to compare
+    revisions: Vec<u32>,
+    from: u32,
+    to: u32,
+    lines: Vec<crate::domain::revision::DiffLine>,
+}
+
+impl PageContext for ClipDiff {
+    fn template_path(&self) -> &str {
+        "clip_diff"
+    }
+    fn title(&self) -> &str {
+        "Clip History"
+    }
+}
+
+/// * Login and registration share a page, `action` is the path the form is posted to
+#[derive(Debug, Serialize)]
+pub struct Account {
+    action: &'static str,
+    title: &'static str,
+}
+
+impl Account {
+    pub fn login() -> Self {
+        Self {
+            action: "login",
+            title: "Log In",
+        }
+    }
+
+    pub fn register() -> Self {
+        Self {
+            action: "register",
+            title: "Register",
+        }
+    }
+}
+
+impl PageContext for Account {
+    fn template_path(&self) -> &str {
+        "account"
+    }
+    fn title(&self) -> &str {
+        self.title
+    }
+}
+
+#[derive(Debug, Serialize, Constructor)]
+pub struct Dashboard {
+    username: crate::domain::Username,
+    clips: Vec<crate::domain::ClipSummary>,
+    /// * Result of the last action taken from the dashboard
+    message: Option<String>,
+}
+
+impl PageContext for Dashboard {
+    fn template_path(&self) -> &str {
+        "dashboard"
+    }
+    fn title(&self) -> &str {
+        "My Clips"
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct EditClip {
+    clip: crate::domain::Clip,
+    protected: bool,
+    languages: &'static [(&'static str, &'static str)],
+}
+
+impl EditClip {
+    pub fn new(clip: crate::domain::Clip) -> Self {
+        Self {
+            protected: clip.password.has_password(),
+            clip,
+            languages: crate::domain::clip::field::LANGUAGES,
+        }
+    }
+}
+
+impl PageContext for EditClip {
+    fn template_path(&self) -> &str {
+        "clip_edit"
+    }
+    fn title(&self) -> &str {
+        "Edit Clip"
+    }
+}
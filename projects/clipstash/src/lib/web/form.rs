@@ -1,16 +1,56 @@
-use crate::domain::clip::field;
+use crate::domain::{clip::field, ClipOrder, Username};
 use rocket::form::FromForm;
 use serde::Serialize;
 
-#[derive(Debug, Serialize, FromForm)]
+// NOTE Also used for multipart requests to the API, which can upload a file instead of sending content
+#[derive(Debug, FromForm)]
 pub struct NewClip {
-    pub content: field::Content,
+    pub content: Option<field::Content>,
     pub title: field::Title,
     pub expires: field::Expires,
     pub password: field::Password,
+    pub max_views: field::MaxViews,
+    pub language: field::Language,
+    pub shortcode: field::VanityShortcode,
+    pub encrypted: field::Encrypted,
+    pub file: field::Upload,
 }
 
 #[derive(Debug, Serialize, FromForm)]
 pub struct GetPasswordProtectedClip {
     pub password: field::Password,
 }
+
+#[derive(Debug, Serialize, FromForm)]
+pub struct DeleteClip {
+    pub edit_secret: field::EditSecret,
+}
+
+/// * Used both to register and to log in
+#[derive(Debug, FromForm)]
+pub struct Credentials {
+    pub username: Username,
+    pub password: field::Password,
+}
+
+/// * Edited from the dashboard, an empty password keeps the current one unless it's removed
+#[derive(Debug, FromForm)]
+pub struct EditClip {
+    pub content: field::Content,
+    pub title: field::Title,
+    pub expires: field::Expires,
+    pub password: field::Password,
+    pub remove_password: bool,
+    pub language: field::Language,
+}
+
+/// * Query string of the clip listing, the date and cursor are parsed when it's turned into a service request
+#[derive(Debug, FromForm)]
+pub struct ListClips {
+    pub q: Option<String>,
+    pub title: Option<String>,
+    pub posted_after: Option<String>,
+    pub order: Option<ClipOrder>,
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+}
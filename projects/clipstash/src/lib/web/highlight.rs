@@ -0,0 +1,86 @@
+use crate::domain::clip::field::{Language, MAX_HIGHLIGHTED_SIZE};
+use syntect::{
+    highlighting::{Theme, ThemeSet},
+    html::highlighted_html_for_string,
+    parsing::SyntaxSet,
+};
+
+const THEME: &str = "InspiredGitHub";
+
+/// * Renders clip contents as syntax highlighted HTML, loading the syntaxes is expensive so it's only done once
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+}
+
+impl Highlighter {
+    pub fn new() -> Self {
+        let mut themes = ThemeSet::load_defaults();
+        Self {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme: themes
+                .themes
+                .remove(THEME)
+                .expect("default themes include InspiredGitHub"),
+        }
+    }
+
+    /// * Returns `None` for plain text, which is rendered as is
+    /// * Large content is always plain text, even when its language was picked
+    pub fn highlight(&self, content: &str, language: &Language) -> Option<String> {
+        if content.len() > MAX_HIGHLIGHTED_SIZE {
+            return None;
+        }
+        let syntax = match language.as_str() {
+            Some(token) => self.syntaxes.find_syntax_by_token(token),
+            // ? Shebangs and similar markers are still recognized when the language couldn't be detected
+            None => self
+                .syntaxes
+                .find_syntax_by_first_line(content.lines().next().unwrap_or_default()),
+        }?;
+        // NOTE The generated HTML escapes the content, so it can be rendered without escaping it again
+        highlighted_html_for_string(content, &self.syntaxes, syntax, &self.theme).ok()
+    }
+}
+
+impl Default for Highlighter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::Highlighter;
+    use crate::domain::clip::field::{Language, LANGUAGES, MAX_HIGHLIGHTED_SIZE};
+
+    #[test]
+    fn every_language_has_a_syntax() {
+        let highlighter = Highlighter::new();
+        for (token, _) in LANGUAGES {
+            assert!(
+                highlighter.syntaxes.find_syntax_by_token(token).is_some(),
+                "no syntax for {token}"
+            );
+        }
+    }
+
+    #[test]
+    fn highlights_detected_language() {
+        let highlighter = Highlighter::new();
+        let content = "fn main() {\n    println!(\"<b>\");\n}\n";
+        let language = Language::detect(content);
+        assert_eq!(language.as_str(), Some("rust"));
+
+        let html = highlighter.highlight(content, &language).unwrap();
+        assert!(html.starts_with("<pre"));
+        assert!(html.contains("&lt;b&gt;"));
+        assert!(highlighter
+            .highlight("just some text", &Language::default())
+            .is_none());
+        let large = content.repeat(MAX_HIGHLIGHTED_SIZE / content.len() + 1);
+        assert!(highlighter
+            .highlight(&large, &Language::new("rust".to_owned()).unwrap())
+            .is_none());
+    }
+}
@@ -1,118 +1,280 @@
-use crate::{
-    data::DatabasePool,
-    service::{self, ServiceErr},
-    Shortcode,
-};
-use crossbeam_channel::{unbounded, Sender, TryRecvError};
+use crate::{data::DatabasePool, metrics::METRICS, service, Shortcode};
 use parking_lot::Mutex;
-use std::{collections::HashMap, sync::Arc, time::Duration};
-use tokio::runtime::Handle;
-
-#[derive(Debug, thiserror::Error)]
-enum HitCountErr {
-    #[error("service error: {0}")]
-    Service(#[from] ServiceErr),
-    #[error("channel communication error: {0}")]
-    Channel(#[from] crossbeam_channel::SendError<HitCountMsg>),
-}
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{self, error::TrySendError},
+        watch,
+    },
+    task::JoinHandle,
+};
+use tracing::Instrument;
 
 enum HitCountMsg {
-    Commit,
     Hit(Shortcode, u32),
+    Remove(Shortcode),
 }
 
-type ModResult<T> = Result<T, HitCountErr>;
+/// * How the hit counter batches hits, hits are committed when either limit is reached
+#[derive(Clone, Copy, Debug)]
+pub struct HitCounterSettings {
+    /// * Longest time collected hits wait before they're committed
+    pub commit_interval: Duration,
+    /// * Most clips whose hits are collected before they're committed
+    pub batch_size: usize,
+    /// * Most messages waiting to be collected
+    pub queue_capacity: usize,
+}
 
-// NOTE The hit store type is a thread-safe, reference-counted, mutex-protected hashmap
-type HitStore = Arc<Mutex<HashMap<Shortcode, u32>>>;
+impl Default for HitCounterSettings {
+    fn default() -> Self {
+        Self {
+            commit_interval: Duration::from_secs(5),
+            batch_size: 500,
+            queue_capacity: 10_000,
+        }
+    }
+}
 
+/// * Collects the hits of clips and writes them to the database in batches
+// NOTE The performance gain from batching the hits is significant, from 400 RPS to 45000 RPS
+// NOTE Counting a hit must never slow a request down, so when the queue is full the hit is dropped and counted in the metrics
+// ? The queue only fills up when the database can't keep up, waiting for room would stall every request on it
 pub struct HitCounter {
-    tx: Sender<HitCountMsg>,
+    tx: mpsc::Sender<HitCountMsg>,
+    handle: Handle,
+    stop: watch::Sender<bool>,
+    task: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl HitCounter {
-    /// Will commit the current hit counts to the database every 5 seconds after the channel is empty
-    /// NOTE The performance gain from batching the hits is significant, from 400 RPS to 45000 RPS
-    pub fn new(pool: DatabasePool, handle: Handle) -> Self {
-        let (tx, rx) = unbounded::<HitCountMsg>();
-        let tx_clone = tx.clone();
-        let rx_clone = rx;
-
-        let _ = std::thread::spawn(move || {
-            println!("HitCounter thread spawned");
-            let store: HitStore = Arc::new(Mutex::new(HashMap::new()));
-            loop {
-                match rx_clone.try_recv() {
-                    Ok(msg) => {
-                        if let Err(e) =
-                            Self::process_msg(msg, store.clone(), handle.clone(), pool.clone())
-                        {
-                            eprintln!("hit counter error: {e}");
+    pub fn new(pool: DatabasePool, handle: Handle, settings: HitCounterSettings) -> Self {
+        let (tx, rx) = mpsc::channel(settings.queue_capacity);
+        let (stop, stopped) = watch::channel(false);
+        let task = handle.spawn(
+            Self::run(pool, settings, rx, stopped).instrument(tracing::info_span!("hit_counter")),
+        );
+        Self {
+            tx,
+            handle,
+            stop,
+            task: Mutex::new(Some(task)),
+        }
+    }
+
+    async fn run(
+        pool: DatabasePool,
+        settings: HitCounterSettings,
+        mut rx: mpsc::Receiver<HitCountMsg>,
+        mut stopped: watch::Receiver<bool>,
+    ) {
+        let mut hits = HashMap::new();
+        let mut interval = tokio::time::interval(settings.commit_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                msg = rx.recv() => match msg {
+                    Some(msg) => {
+                        Self::collect(&mut hits, msg);
+                        if hits.len() >= settings.batch_size {
+                            Self::commit(&mut hits, &pool).await;
+                            interval.reset();
                         }
                     }
-                    Err(e) => match e {
-                        TryRecvError::Empty => {
-                            std::thread::sleep(Duration::from_secs(5));
-                            if let Err(e) = tx_clone.send(HitCountMsg::Commit) {
-                                eprintln!("error sending commit msg to hits channel: {e}");
-                            }
-                        }
-                        _ => break,
-                    },
-                }
+                    None => break,
+                },
+                _ = interval.tick() => Self::commit(&mut hits, &pool).await,
+                _ = stopped.changed() => break,
             }
-        });
-        Self { tx }
+        }
+        // * Messages that were already queued are collected before the last commit
+        rx.close();
+        while let Some(msg) = rx.recv().await {
+            Self::collect(&mut hits, msg);
+        }
+        Self::commit(&mut hits, &pool).await;
+        tracing::debug!("hit counter stopped");
     }
 
-    pub fn hit(&self, shortcode: Shortcode, count: u32) {
-        // NOTE Sending a message to the channel to be processed by the background task
-        // ? This is more performant than directly writing to the database
-        if let Err(e) = self.tx.send(HitCountMsg::Hit(shortcode, count)) {
-            eprintln!("hit count error: {e}");
+    fn collect(hits: &mut HashMap<Shortcode, u32>, msg: HitCountMsg) {
+        match msg {
+            HitCountMsg::Hit(shortcode, count) => {
+                *hits.entry(shortcode).or_insert(0) += count;
+            }
+            HitCountMsg::Remove(shortcode) => {
+                hits.remove(&shortcode);
+            }
         }
     }
 
     /// Commit the hits to the database and clears the hit store
-    fn commit_hits(hits: HitStore, handle: Handle, pool: DatabasePool) -> ModResult<()> {
-        let hits: Vec<(Shortcode, u32)> = {
-            // NOTE `hits` will be dropped at the end of this block, releasing the lock
-            let mut hits = hits.lock();
-            let hits_vec = hits.iter().map(|(k, v)| (k.clone(), *v)).collect();
-            hits.clear();
-            hits_vec
+    async fn commit(hits: &mut HashMap<Shortcode, u32>, pool: &DatabasePool) {
+        if hits.is_empty() {
+            return;
+        }
+        let batch = hits.drain().collect::<Vec<_>>();
+        let start = Instant::now();
+        // ? Hits that fail to be committed are dropped, like the ones that don't fit in the queue
+        match service::action::increase_hit_counts(&batch, pool).await {
+            Ok(_) => METRICS.hit_commit(start.elapsed()),
+            Err(e) => {
+                tracing::error!(error = %e, clips = batch.len(), "failed to commit hits");
+                METRICS.hit_commit_failed();
+            }
+        }
+    }
+
+    /// * Commits the collected hits and waits for the task to stop, hits sent afterwards are dropped
+    /// * It blocks until the commit is done, so it must not be called from the runtime's tasks
+    pub fn shutdown(&self) {
+        let Some(task) = self.task.lock().take() else {
+            return;
         };
-        handle.block_on(async move {
-            let transaction = service::action::begin_transaction(&pool).await?;
-            for (shortcode, count) in hits {
-                if let Err(e) = service::action::increase_hit_count(&shortcode, count, &pool).await
-                {
-                    eprintln!("error increasing hit count: {e}");
-                };
+        let _ = self.stop.send(true);
+        if let Err(e) = self.handle.block_on(task) {
+            tracing::error!(error = %e, "hit counter task failed");
+        }
+    }
+
+    pub fn hit(&self, shortcode: Shortcode, count: u32) {
+        // NOTE Sending a message to the channel to be processed by the background task
+        // ? This is more performant than directly writing to the database
+        self.send(HitCountMsg::Hit(shortcode, count));
+    }
+
+    /// * Messages that are waiting to be collected
+    pub fn queue_depth(&self) -> usize {
+        self.tx.max_capacity() - self.tx.capacity()
+    }
+
+    /// Drops the hits that weren't committed yet, used when a clip is deleted
+    pub fn remove(&self, shortcode: Shortcode) {
+        self.send(HitCountMsg::Remove(shortcode));
+    }
+
+    fn send(&self, msg: HitCountMsg) {
+        match self.tx.try_send(msg) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                METRICS.hit_dropped();
+                tracing::warn!("hit counter queue is full, the hit was dropped");
             }
-            Ok(service::action::end_transaction(transaction).await?)
-        })
+            Err(TrySendError::Closed(_)) => {
+                tracing::warn!("hit counter is stopped, the hit was dropped");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::domain::clip::field::Content;
+    use crate::service::ask;
+    use crate::test::async_runtime;
+    use crate::web::test::{config, runtime, tracked};
+    use rocket::http::Status;
+
+    #[test]
+    fn hits_are_committed_on_shutdown() {
+        let rt = async_runtime();
+        let (mut config, database) = config();
+        let pool = config.database.get_pool().clone();
+        // * Nothing is committed on its own while the test runs
+        config.hit_counter = HitCounter::new(
+            pool.clone(),
+            runtime().handle().clone(),
+            HitCounterSettings {
+                commit_interval: Duration::from_secs(3600),
+                ..Default::default()
+            },
+        );
+        let req = ask::NewClip::new(Content::new("content").unwrap());
+        let (clip, _) = rt
+            .block_on(service::action::new_clip(
+                req,
+                &Default::default(),
+                &Default::default(),
+                &pool,
+            ))
+            .unwrap();
+
+        let client = tracked(config, database);
+        for _ in 0..2 {
+            let response = client
+                .get(format!("/clip/{}", clip.shortcode.as_str()))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
+        // ? The client is kept, stopping its runtime could drop the only connection of the in-memory database
+        crate::shutdown(client.rocket());
+
+        let stored: crate::Clip = rt
+            .block_on(crate::data::query::get_clip(clip.shortcode.clone(), &pool))
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(stored.hits.into_inner(), 2);
+        // ? The thread is gone, later hits are dropped instead of blocking
+        client
+            .rocket()
+            .state::<HitCounter>()
+            .unwrap()
+            .hit(clip.shortcode, 1);
     }
 
-    fn process_msg(
-        msg: HitCountMsg,
-        hits: HitStore,
-        handle: Handle,
-        pool: DatabasePool,
-    ) -> ModResult<()> {
-        match msg {
-            HitCountMsg::Commit => Self::commit_hits(hits, handle, pool)?,
-            HitCountMsg::Hit(shortcode, count) => {
-                let mut hitcount = hits.lock();
-                // let hitcount = hitcount.entry(shortcode).or_insert(0);
-                // *hitcount += count;
-                // ? Equivalent to the above code
-                hitcount
-                    .entry(shortcode)
-                    .and_modify(|hc| *hc += count)
-                    .or_insert(0);
+    #[test]
+    fn full_batches_are_committed_right_away() {
+        let rt = async_runtime();
+        for database in crate::data::test::test_dbs(runtime().handle()) {
+            let pool = database.get_pool().clone();
+            let hit_counter = HitCounter::new(
+                pool.clone(),
+                runtime().handle().clone(),
+                HitCounterSettings {
+                    commit_interval: Duration::from_secs(3600),
+                    batch_size: 2,
+                    queue_capacity: 16,
+                },
+            );
+            let mut shortcodes = vec![];
+            for _ in 0..2 {
+                let (clip, _) = rt
+                    .block_on(service::action::new_clip(
+                        ask::NewClip::new(Content::new("content").unwrap()),
+                        &Default::default(),
+                        &Default::default(),
+                        &pool,
+                    ))
+                    .unwrap();
+                shortcodes.push(clip.shortcode);
             }
-        };
-        Ok(())
+            for shortcode in &shortcodes {
+                hit_counter.hit(shortcode.clone(), 2);
+            }
+
+            let hits = |shortcode: &Shortcode| -> u64 {
+                let clip: crate::Clip = rt
+                    .block_on(crate::data::query::get_clip(shortcode.clone(), &pool))
+                    .unwrap()
+                    .try_into()
+                    .unwrap();
+                clip.hits.into_inner()
+            };
+            // ? The commit runs in the background, it's waited for instead of the hour long interval
+            let start = Instant::now();
+            while shortcodes.iter().any(|shortcode| hits(shortcode) != 2) {
+                assert!(
+                    start.elapsed() < Duration::from_secs(10),
+                    "hits weren't committed"
+                );
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            hit_counter.shutdown();
+        }
     }
 }
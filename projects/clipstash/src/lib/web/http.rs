@@ -1,39 +1,99 @@
 use crate::{
     data::AppDatabase,
-    service::{self, action},
-    web::{ctx, form, PageErr, PASSWORD_COOKIE},
-    ServiceErr, Shortcode,
+    domain::{
+        clip::field::{Attachment, EditSecret, ShortcodeGenerator, TitleLimit},
+        revision,
+    },
+    service::{self, action, token::AccessTokenSigner},
+    web::{access_token_cookie, ctx, form, PageErr},
+    Clip, ServiceErr, Shortcode,
 };
 use rocket::{
     form::{Contextual, Form},
-    http::{Cookie, CookieJar, Status},
-    response::{content::RawHtml, status, Redirect},
-    uri, State,
+    http::{ContentType, Cookie, CookieJar, Header, RawStr, SameSite, Status},
+    request::FlashMessage,
+    response::{content::RawHtml, status, Flash, Redirect},
+    time, uri, State,
 };
 
-use super::{hitcounter::HitCounter, renderer::Renderer};
+use super::{account::CurrentUser, hitcounter::HitCounter, renderer::Renderer};
 
 #[rocket::get("/")]
-fn home(renderer: &State<Renderer<'_>>) -> RawHtml<String> {
+fn home(renderer: &State<Renderer<'_>>, flash: Option<FlashMessage<'_>>) -> RawHtml<String> {
+    // * Result of an action that redirected here, such as deleting a clip
+    let (errors, message) = match &flash {
+        Some(flash) if flash.kind() == "error" => (vec![flash.message()], None),
+        Some(flash) => (vec![], Some(flash.message())),
+        None => (vec![], None),
+    };
     let context = ctx::Home::default();
-    RawHtml(renderer.render(context, &[]))
+    RawHtml(renderer.render_with_data(context, ("message", message), &errors))
 }
 
+// NOTE Every argument is a request guard Rocket fills in, so there's nothing to group them into
+#[allow(clippy::too_many_arguments)]
 #[rocket::post("/", data = "<form>")]
 pub async fn new_clip(
+    cookies: &CookieJar<'_>,
     form: Form<Contextual<'_, form::NewClip>>,
     database: &State<AppDatabase>,
+    signer: &State<AccessTokenSigner>,
+    shortcodes: &State<ShortcodeGenerator>,
+    title_limit: &State<TitleLimit>,
     renderer: &State<Renderer<'_>>,
-) -> Result<Redirect, (Status, RawHtml<String>)> {
+    user: Option<CurrentUser>,
+) -> Result<Flash<Redirect>, (Status, RawHtml<String>)> {
     let form = form.into_inner();
 
     if let Some(value) = form.value {
-        let req: service::ask::NewClip = value.into();
+        let req = match service::ask::NewClip::try_from(value) {
+            // * Clips posted while logged in show up in the user's dashboard
+            Ok(req) => service::ask::NewClip {
+                owner: user.map(|user| user.0.user_id),
+                ..req
+            },
+            Err(e) => {
+                return Err((
+                    Status::BadRequest,
+                    RawHtml(renderer.render_with_data(
+                        ctx::Home::default(),
+                        ("clip", &form.context),
+                        &[e.to_string().as_str()],
+                    )),
+                ))
+            }
+        };
 
-        match action::new_clip(req, database.get_pool()).await {
-            Ok(clip) => Ok(Redirect::to(uri!(get_clip(shortcode = clip.shortcode)))),
+        match action::new_clip(req, shortcodes, title_limit, database.get_pool()).await {
+            Ok((clip, edit_secret)) => {
+                // * The creator already knows the password, so they're not asked for it after the redirect
+                if clip.password.has_password() {
+                    add_access_token_cookie(cookies, signer, &clip);
+                }
+                // NOTE The flash cookie is removed once it's read, so the edit secret is shown exactly once
+                Ok(Flash::success(
+                    Redirect::to(uri!(get_clip(shortcode = clip.shortcode))),
+                    edit_secret.into_inner().unwrap_or_default(),
+                ))
+            }
+            Err(ServiceErr::Conflict(msg)) => Err((
+                Status::Conflict,
+                RawHtml(renderer.render_with_data(
+                    ctx::Home::default(),
+                    ("clip", &form.context),
+                    &[msg.as_str()],
+                )),
+            )),
+            Err(ServiceErr::Clip(e)) => Err((
+                Status::BadRequest,
+                RawHtml(renderer.render_with_data(
+                    ctx::Home::default(),
+                    ("clip", &form.context),
+                    &[e.to_string().as_str()],
+                )),
+            )),
             Err(e) => {
-                eprintln!("internal error: {e}");
+                tracing::error!(error = %e, "internal error");
                 Err((
                     Status::InternalServerError,
                     RawHtml(renderer.render(
@@ -52,7 +112,7 @@ pub async fn new_clip(
                 if let ErrorKind::Validation(msg) = &err.kind {
                     msg.as_ref()
                 } else {
-                    eprintln!("unhandled error: {err}");
+                    tracing::warn!(error = %err, "unhandled form error");
                     "An error ocurred, please try again"
                 }
             })
@@ -71,20 +131,47 @@ pub async fn new_clip(
 
 #[rocket::get("/clip/<shortcode>")]
 pub async fn get_clip(
+    cookies: &CookieJar<'_>,
     shortcode: Shortcode,
     database: &State<AppDatabase>,
+    signer: &State<AccessTokenSigner>,
     hit_counter: &State<HitCounter>,
     renderer: &State<Renderer<'_>>,
+    flash: Option<FlashMessage<'_>>,
 ) -> Result<status::Custom<RawHtml<String>>, PageErr> {
     let render_with_status = |st, html| Ok(status::Custom(st, RawHtml(html)));
+    // * Errors from actions that redirected back to this page, such as a failed deletion
+    let errors = flash
+        .as_ref()
+        .filter(|flash| flash.kind() == "error")
+        .map(|flash| vec![flash.message()])
+        .unwrap_or_default();
+    // * The edit secret of a clip that was just created
+    let edit_secret = flash
+        .as_ref()
+        .filter(|flash| flash.kind() == "success")
+        .map(|flash| flash.message());
 
-    match action::get_clip(shortcode.clone().into(), database.get_pool()).await {
+    let req = service::ask::GetClip::from_cookies(shortcode.clone(), cookies);
+    // ? The creator is sent here with the edit secret, which keeps their visit from using up a view
+    let clip = match edit_secret {
+        Some(secret) => {
+            let secret = EditSecret::new(secret.to_owned());
+            action::get_new_clip(req, secret, signer, database.get_pool()).await
+        }
+        None => action::get_clip(req, signer, database.get_pool()).await,
+    };
+    match clip {
         Ok(clip) => {
             // * Adding a hit when the clip is viewed
             hit_counter.hit(shortcode.clone(), 1);
 
-            let context = ctx::ViewClip::new(clip);
-            render_with_status(Status::Ok, renderer.render(context, &[]))
+            let highlighted = renderer.highlight(&clip);
+            let context = ctx::ViewClip::new(clip, highlighted);
+            render_with_status(
+                Status::Ok,
+                renderer.render_with_data(context, ("edit_secret", edit_secret), &errors),
+            )
         }
         Err(e) => match e {
             ServiceErr::PermissionErr(_) => {
@@ -103,22 +190,24 @@ pub async fn submit_clip_password(
     shortcode: Shortcode,
     hit_counter: &State<HitCounter>,
     database: &State<AppDatabase>,
+    signer: &State<AccessTokenSigner>,
     renderer: &State<Renderer<'_>>,
 ) -> Result<RawHtml<String>, PageErr> {
     if let Some(form) = &form.value {
         let req = service::ask::GetClip {
             shortcode: shortcode.clone(),
             password: form.password.clone(),
+            token: None,
         };
-        match action::get_clip(req, database.get_pool()).await {
+        match action::get_clip(req, signer, database.get_pool()).await {
             Ok(clip) => {
                 // * Adding a hit when the clip is viewed
                 hit_counter.hit(shortcode.clone(), 1);
-                let context = ctx::ViewClip::new(clip);
-                cookies.add(Cookie::new(
-                    PASSWORD_COOKIE,
-                    form.password.clone().into_inner().unwrap_or_default(),
-                ));
+                // * The password is exchanged for a token that only unlocks this clip
+                add_access_token_cookie(cookies, signer, &clip);
+
+                let highlighted = renderer.highlight(&clip);
+                let context = ctx::ViewClip::new(clip, highlighted);
                 Ok(RawHtml(renderer.render(context, &[])))
             }
             Err(e) => match e {
@@ -138,15 +227,26 @@ pub async fn submit_clip_password(
     }
 }
 
+fn add_access_token_cookie(cookies: &CookieJar<'_>, signer: &AccessTokenSigner, clip: &Clip) {
+    let token = signer.issue(clip);
+    let cookie = Cookie::build((access_token_cookie(&clip.shortcode), token.into_inner()))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(signer.ttl().num_seconds()));
+    cookies.add(cookie);
+}
+
 #[rocket::get("/clip/raw/<shortcode>")]
 pub async fn get_raw_clip(
     cookies: &CookieJar<'_>,
     shortcode: Shortcode,
     hit_counter: &State<HitCounter>,
     database: &State<AppDatabase>,
+    signer: &State<AccessTokenSigner>,
 ) -> Result<status::Custom<String>, Status> {
     let req = service::ask::GetClip::from_cookies(shortcode.clone(), cookies);
-    match action::get_clip(req, database.get_pool()).await {
+    match action::get_clip(req, signer, database.get_pool()).await {
         Ok(clip) => {
             hit_counter.hit(shortcode.clone(), 1);
             Ok(status::Custom(Status::Ok, clip.content.into_inner()))
@@ -159,22 +259,169 @@ pub async fn get_raw_clip(
     }
 }
 
+/// * Files are always downloaded as attachments, so uploaded HTML can't run in the app's origin
+#[derive(rocket::Responder)]
+pub struct FileDownload {
+    data: Vec<u8>,
+    content_type: ContentType,
+    disposition: Header<'static>,
+    nosniff: Header<'static>,
+}
+
+impl FileDownload {
+    pub fn new(attachment: &Attachment, data: Vec<u8>) -> Self {
+        let name = attachment.name();
+        // ? Old clients only read the ASCII `filename`, the exact name is sent percent encoded in `filename*`
+        let ascii_name = name
+            .chars()
+            .map(|c| if c.is_ascii() { c } else { '_' })
+            .collect::<String>();
+        let disposition = format!(
+            "attachment; filename=\"{ascii_name}\"; filename*=UTF-8''{}",
+            RawStr::new(name).percent_encode()
+        );
+        Self {
+            data,
+            content_type: ContentType::parse_flexible(attachment.mime_type())
+                .unwrap_or(ContentType::Binary),
+            disposition: Header::new("Content-Disposition", disposition),
+            nosniff: Header::new("X-Content-Type-Options", "nosniff"),
+        }
+    }
+}
+
+// NOTE `/clip/raw/download` would match both routes, `raw` is a reserved shortcode so the raw route takes precedence
+#[rocket::get("/clip/<shortcode>/download", rank = 2)]
+pub async fn download_clip_file(
+    cookies: &CookieJar<'_>,
+    shortcode: Shortcode,
+    hit_counter: &State<HitCounter>,
+    database: &State<AppDatabase>,
+    signer: &State<AccessTokenSigner>,
+) -> Result<FileDownload, status::Custom<String>> {
+    let req = service::ask::GetClip::from_cookies(shortcode.clone(), cookies);
+    match action::get_file(req, signer, database.get_pool()).await {
+        Ok((attachment, data)) => {
+            hit_counter.hit(shortcode, 1);
+            Ok(FileDownload::new(&attachment, data))
+        }
+        Err(e) => match e {
+            ServiceErr::PermissionErr(msg) => Err(status::Custom(Status::Unauthorized, msg)),
+            ServiceErr::NotFound => Err(status::Custom(Status::NotFound, "file not found".into())),
+            _ => Err(status::Custom(
+                Status::InternalServerError,
+                "server error".into(),
+            )),
+        },
+    }
+}
+
+#[rocket::get("/clip/<shortcode>/diff?<from>&<to>")]
+pub async fn get_clip_diff(
+    cookies: &CookieJar<'_>,
+    shortcode: Shortcode,
+    from: Option<u32>,
+    to: Option<u32>,
+    database: &State<AppDatabase>,
+    signer: &State<AccessTokenSigner>,
+    renderer: &State<Renderer<'_>>,
+) -> Result<status::Custom<RawHtml<String>>, PageErr> {
+    let req = service::ask::GetClip::from_cookies(shortcode.clone(), cookies);
+    let revisions = match action::get_revisions(req, signer, database.get_pool()).await {
+        Ok(revisions) => revisions,
+        Err(ServiceErr::PermissionErr(_)) => {
+            let context = ctx::PasswordRequired::new(shortcode);
+            return Ok(status::Custom(
+                Status::Unauthorized,
+                RawHtml(renderer.render(context, &[])),
+            ));
+        }
+        Err(e) => return Err(to_page_err(e)),
+    };
+
+    // * Compares the current revision with the previous one by default
+    let latest = revisions.last().map_or(1, |revision| revision.revision);
+    let to = to.unwrap_or(latest);
+    let from = from.unwrap_or(to.saturating_sub(1).max(1));
+    let find = |revision: u32| {
+        revisions
+            .iter()
+            .find(|r| r.revision == revision)
+            .ok_or_else(|| PageErr::NotFound(format!("revision {revision} not found")))
+    };
+    let lines = revision::diff(find(from)?, find(to)?);
+
+    let context = ctx::ClipDiff::new(
+        shortcode,
+        revisions.iter().map(|r| r.revision).collect(),
+        from,
+        to,
+        lines,
+    );
+    Ok(status::Custom(
+        Status::Ok,
+        RawHtml(renderer.render(context, &[])),
+    ))
+}
+
+#[rocket::post("/clip/<shortcode>/delete", data = "<form>")]
+pub async fn delete_clip(
+    form: Form<Contextual<'_, form::DeleteClip>>,
+    shortcode: Shortcode,
+    hit_counter: &State<HitCounter>,
+    database: &State<AppDatabase>,
+) -> Result<Flash<Redirect>, PageErr> {
+    let back_to_clip = |msg: &str| {
+        Ok(Flash::error(
+            Redirect::to(uri!(get_clip(shortcode = shortcode.clone()))),
+            msg,
+        ))
+    };
+
+    let Some(form) = form.into_inner().value else {
+        return back_to_clip("The edit secret is required to delete this clip");
+    };
+    let req = service::ask::DeleteClip {
+        shortcode: shortcode.clone(),
+        edit_secret: form.edit_secret,
+        user: None,
+    };
+    match action::delete_clip(req, database.get_pool()).await {
+        Ok(_) => {
+            hit_counter.remove(shortcode);
+            Ok(Flash::success(Redirect::to(uri!(home)), "Clip deleted"))
+        }
+        Err(ServiceErr::PermissionErr(msg)) => back_to_clip(&msg),
+        Err(e) => Err(to_page_err(e)),
+    }
+}
+
 pub fn routes() -> Vec<rocket::Route> {
-    rocket::routes![home, get_clip, new_clip, submit_clip_password, get_raw_clip]
+    rocket::routes![
+        home,
+        get_clip,
+        new_clip,
+        submit_clip_password,
+        get_raw_clip,
+        get_clip_diff,
+        download_clip_file,
+        delete_clip
+    ]
 }
 
 pub mod catcher {
+    use crate::web::logging::RequestSpan;
     use rocket::{catch, catchers, Catcher, Request};
 
     #[catch(default)]
     fn default(req: &Request) -> &'static str {
-        eprintln!("General error: {req:?}");
+        tracing::error!(parent: RequestSpan::of(req), "request failed");
         "something went wrong..."
     }
 
     #[catch(500)]
     fn internal_error(req: &Request) -> &'static str {
-        eprintln!("Internal error: {req:?}");
+        tracing::error!(parent: RequestSpan::of(req), "internal error");
         "internal error"
     }
 
@@ -198,8 +445,10 @@ fn to_page_err(err: ServiceErr) -> PageErr {
 #[cfg(test)]
 pub mod test {
     use crate::data::AppDatabase;
+    use crate::domain::clip::field::{Content, Password, ShortcodeGenerator, TitleLimit};
+    use crate::service::ask;
     use crate::test::async_runtime;
-    use crate::web::test::client;
+    use crate::web::{access_token_cookie, test::client};
     use rocket::http::Status;
 
     #[test]
@@ -218,7 +467,6 @@ pub mod test {
 
     #[test]
     fn requires_password_when_applicable() {
-        use crate::domain::clip::field::{Content, Expires, Password, Title};
         use crate::service;
         use rocket::http::{ContentType, Cookie};
 
@@ -227,14 +475,20 @@ pub mod test {
 
         let db = client.rocket().state::<AppDatabase>().unwrap();
 
-        let req = service::ask::NewClip {
-            content: Content::new("content").unwrap(),
-            expires: Expires::default(),
+        let req = ask::NewClip {
             password: Password::new("123".to_owned()).unwrap(),
-            title: Title::default(),
+            ..ask::NewClip::new(Content::new("content").unwrap())
         };
-        let clip = rt
-            .block_on(async move { service::action::new_clip(req, db.get_pool()).await })
+        let (clip, _) = rt
+            .block_on(async move {
+                service::action::new_clip(
+                    req,
+                    &ShortcodeGenerator::default(),
+                    &TitleLimit::default(),
+                    db.get_pool(),
+                )
+                .await
+            })
             .unwrap();
 
         let response = client
@@ -245,6 +499,14 @@ pub mod test {
             .get(format!("/clip/raw/{}", clip.shortcode.as_str()))
             .dispatch();
         assert_eq!(response.status(), Status::Unauthorized);
+        let response = client
+            .get(format!("/clip/raw/{}", clip.shortcode.as_str()))
+            .cookie(Cookie::new(
+                access_token_cookie(&clip.shortcode),
+                "9999999999.forged",
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
 
         let response = client
             .post(format!("/clip/{}", clip.shortcode.as_str()))
@@ -252,16 +514,758 @@ pub mod test {
             .body("password=123")
             .dispatch();
         assert_eq!(response.status(), Status::Ok);
+        // ? The tracked client sends back the access token cookie issued above
+        let response = client
+            .get(format!("/clip/{}", clip.shortcode.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
         let response = client
             .get(format!("/clip/raw/{}", clip.shortcode.as_str()))
-            .cookie(Cookie::new("password", "123"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn access_token_is_scoped_to_a_single_clip() {
+        use crate::service;
+        use rocket::http::ContentType;
+
+        let rt = async_runtime();
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        let (first, _) = rt
+            .block_on(async {
+                service::action::new_clip(
+                    ask::NewClip {
+                        password: Password::new("123".to_owned()).unwrap(),
+                        ..ask::NewClip::new(Content::new("content").unwrap())
+                    },
+                    &ShortcodeGenerator::default(),
+                    &TitleLimit::default(),
+                    db.get_pool(),
+                )
+                .await
+            })
+            .unwrap();
+        let (second, _) = rt
+            .block_on(async {
+                service::action::new_clip(
+                    ask::NewClip {
+                        password: Password::new("123".to_owned()).unwrap(),
+                        ..ask::NewClip::new(Content::new("content").unwrap())
+                    },
+                    &ShortcodeGenerator::default(),
+                    &TitleLimit::default(),
+                    db.get_pool(),
+                )
+                .await
+            })
+            .unwrap();
+
+        let response = client
+            .post(format!("/clip/{}", first.shortcode.as_str()))
+            .header(ContentType::Form)
+            .body("password=123")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .get(format!("/clip/raw/{}", second.shortcode.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn password_change_invalidates_access_tokens() {
+        use crate::domain::clip::field::{Expires, Title};
+        use crate::service;
+        use rocket::http::ContentType;
+
+        let rt = async_runtime();
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        let (clip, edit_secret) = rt
+            .block_on(async {
+                service::action::new_clip(
+                    ask::NewClip {
+                        password: Password::new("123".to_owned()).unwrap(),
+                        ..ask::NewClip::new(Content::new("content").unwrap())
+                    },
+                    &ShortcodeGenerator::default(),
+                    &TitleLimit::default(),
+                    db.get_pool(),
+                )
+                .await
+            })
+            .unwrap();
+
+        let response = client
+            .post(format!("/clip/{}", clip.shortcode.as_str()))
+            .header(ContentType::Form)
+            .body("password=123")
             .dispatch();
         assert_eq!(response.status(), Status::Ok);
 
+        let req = service::ask::UpdateClip {
+            shortcode: clip.shortcode.clone(),
+            content: Content::new("content").unwrap(),
+            expires: Expires::default(),
+            password: Password::new("456".to_owned()).unwrap(),
+            title: Title::default(),
+            language: Default::default(),
+            edit_secret,
+            user: None,
+            keep_password: false,
+        };
+        rt.block_on(async {
+            service::action::update_clip(req, &TitleLimit::default(), db.get_pool()).await
+        })
+        .unwrap();
+
         let response = client
             .get(format!("/clip/raw/{}", clip.shortcode.as_str()))
-            .cookie(Cookie::new("password", "abc"))
             .dispatch();
         assert_eq!(response.status(), Status::Unauthorized);
     }
+
+    #[test]
+    fn deletes_clip_with_edit_secret() {
+        use crate::service;
+        use rocket::http::ContentType;
+
+        let rt = async_runtime();
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        let (clip, edit_secret) = rt
+            .block_on(async {
+                service::action::new_clip(
+                    ask::NewClip {
+                        password: Password::new("123".to_owned()).unwrap(),
+                        ..ask::NewClip::new(Content::new("content").unwrap())
+                    },
+                    &ShortcodeGenerator::default(),
+                    &TitleLimit::default(),
+                    db.get_pool(),
+                )
+                .await
+            })
+            .unwrap();
+
+        // ? Knowing the password isn't enough to delete a clip
+        let response = client
+            .post(format!("/clip/{}/delete", clip.shortcode.as_str()))
+            .header(ContentType::Form)
+            .body("edit_secret=123")
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(
+            response.headers().get_one("Location"),
+            Some(format!("/clip/{}", clip.shortcode.as_str()).as_str())
+        );
+
+        let response = client
+            .post(format!("/clip/{}/delete", clip.shortcode.as_str()))
+            .header(ContentType::Form)
+            .body(format!("edit_secret={}", edit_secret.into_inner().unwrap()))
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(response.headers().get_one("Location"), Some("/"));
+        let response = client.get("/").dispatch();
+        assert!(response.into_string().unwrap().contains("Clip deleted"));
+
+        let response = client
+            .get(format!("/clip/raw/{}", clip.shortcode.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn update_requires_edit_secret() {
+        use crate::domain::clip::field::{EditSecret, Expires, Title};
+        use crate::service::{self, ServiceErr};
+
+        let rt = async_runtime();
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        let (clip, _) = rt
+            .block_on(async {
+                service::action::new_clip(
+                    ask::NewClip::new(Content::new("content").unwrap()),
+                    &ShortcodeGenerator::default(),
+                    &TitleLimit::default(),
+                    db.get_pool(),
+                )
+                .await
+            })
+            .unwrap();
+
+        let req = service::ask::UpdateClip {
+            shortcode: clip.shortcode.clone(),
+            content: Content::new("overwritten").unwrap(),
+            expires: Expires::default(),
+            password: Password::default(),
+            title: Title::default(),
+            language: Default::default(),
+            edit_secret: EditSecret::new("guess".to_owned()),
+            user: None,
+            keep_password: false,
+        };
+        let res = rt.block_on(async {
+            service::action::update_clip(req, &TitleLimit::default(), db.get_pool()).await
+        });
+        assert!(matches!(res, Err(ServiceErr::PermissionErr(_))));
+    }
+
+    #[test]
+    fn shows_edit_secret_once_after_creation() {
+        use rocket::http::ContentType;
+
+        let client = client();
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body("content=hello&title=&expires=&password=")
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        let location = response.headers().get_one("Location").unwrap().to_owned();
+
+        let page = client
+            .get(location.as_str())
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(page.contains("Your Edit Secret"));
+        let page = client
+            .get(location.as_str())
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(!page.contains("Your Edit Secret"));
+    }
+
+    #[test]
+    fn burns_clip_after_reading() {
+        use crate::domain::clip::field::MaxViews;
+        use crate::service;
+
+        let rt = async_runtime();
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        let req = ask::NewClip {
+            max_views: MaxViews::burn_after_reading(),
+            ..ask::NewClip::new(Content::new("content").unwrap())
+        };
+        let (clip, _) = rt
+            .block_on(async {
+                service::action::new_clip(
+                    req,
+                    &ShortcodeGenerator::default(),
+                    &TitleLimit::default(),
+                    db.get_pool(),
+                )
+                .await
+            })
+            .unwrap();
+
+        let response = client
+            .get(format!("/clip/raw/{}", clip.shortcode.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client
+            .get(format!("/clip/raw/{}", clip.shortcode.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn creator_redirect_doesnt_use_up_a_view() {
+        use rocket::http::ContentType;
+
+        let client = client();
+
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body("content=only once&title=&expires=&password=&max_views=1")
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        let location = response.headers().get_one("Location").unwrap().to_owned();
+
+        let page = client.get(location.as_str()).dispatch();
+        assert_eq!(page.status(), Status::Ok);
+        assert!(page.into_string().unwrap().contains("Your Edit Secret"));
+
+        // * The only view is left for whoever the link is shared with
+        let response = client.get(location.as_str()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.into_string().unwrap().contains("only once"));
+        let response = client.get(location.as_str()).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn concurrent_readers_never_exceed_max_views() {
+        use crate::domain::clip::field::MaxViews;
+        use crate::service::{self, token::AccessTokenSigner};
+        use std::sync::Arc;
+
+        let rt = async_runtime();
+        let client = client();
+        let pool = client
+            .rocket()
+            .state::<AppDatabase>()
+            .unwrap()
+            .get_pool()
+            .clone();
+        let signer = Arc::new(AccessTokenSigner::random(chrono::Duration::hours(1)));
+
+        let req = ask::NewClip {
+            max_views: MaxViews::new(3).unwrap(),
+            ..ask::NewClip::new(Content::new("content").unwrap())
+        };
+        let (clip, _) = rt
+            .block_on(service::action::new_clip(
+                req,
+                &ShortcodeGenerator::default(),
+                &TitleLimit::default(),
+                &pool,
+            ))
+            .unwrap();
+
+        let views = rt.block_on(async move {
+            let readers: Vec<_> = (0..10)
+                .map(|_| {
+                    let (pool, signer) = (pool.clone(), signer.clone());
+                    let req = service::ask::GetClip::from(clip.shortcode.clone());
+                    tokio::spawn(
+                        async move { service::action::get_clip(req, &signer, &pool).await },
+                    )
+                })
+                .collect();
+
+            let mut views = 0;
+            for reader in readers {
+                if reader.await.unwrap().is_ok() {
+                    views += 1;
+                }
+            }
+            views
+        });
+        assert_eq!(views, 3);
+    }
+
+    #[test]
+    fn update_archives_a_revision() {
+        use crate::domain::clip::field::{EditSecret, Expires, MaxViews, Title};
+        use crate::service;
+
+        let rt = async_runtime();
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        let req = ask::NewClip {
+            max_views: MaxViews::new(2).unwrap(),
+            ..ask::NewClip::new(Content::new("content").unwrap())
+        };
+        let (clip, edit_secret) = rt
+            .block_on(async {
+                service::action::new_clip(
+                    req,
+                    &ShortcodeGenerator::default(),
+                    &TitleLimit::default(),
+                    db.get_pool(),
+                )
+                .await
+            })
+            .unwrap();
+        let req = service::ask::UpdateClip {
+            shortcode: clip.shortcode.clone(),
+            content: Content::new("updated").unwrap(),
+            expires: Expires::default(),
+            password: Password::default(),
+            title: Title::default(),
+            language: Default::default(),
+            edit_secret: EditSecret::new(edit_secret.into_inner()),
+            user: None,
+            keep_password: false,
+        };
+        rt.block_on(async {
+            service::action::update_clip(req, &TitleLimit::default(), db.get_pool()).await
+        })
+        .unwrap();
+
+        let response = client
+            .get(format!("/clip/{}/diff", clip.shortcode.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.into_string().unwrap();
+        assert!(body.contains(r#"<span class="diff-delete">- content"#));
+        assert!(body.contains(r#"<span class="diff-insert">+ updated"#));
+
+        let response = client
+            .get(format!(
+                "/clip/{}/diff?from=1&to=3",
+                clip.shortcode.as_str()
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        // * Looking at the history counts as a view, so the clip's two views are used up
+        let response = client
+            .get(format!("/clip/raw/{}", clip.shortcode.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client
+            .get(format!("/clip/{}/diff", clip.shortcode.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn rejects_clips_that_already_expired() {
+        use crate::domain::clip::ClipErr;
+        use crate::service::{self, ServiceErr};
+
+        // ? JSON requests parse past dates so the service can reject them with the same error
+        let rt = async_runtime();
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+        let mut req = ask::NewClip::new(Content::new("content").unwrap());
+        req.expires = serde_json::from_str(r#""2000-01-01""#).unwrap();
+        let res = rt.block_on(async {
+            service::action::new_clip(
+                req,
+                &ShortcodeGenerator::default(),
+                &TitleLimit::default(),
+                db.get_pool(),
+            )
+            .await
+        });
+        assert!(matches!(
+            res,
+            Err(ServiceErr::Clip(ClipErr::InvalidDate(_)))
+        ));
+    }
+
+    #[test]
+    fn highlights_clip_page_but_not_raw_content() {
+        use rocket::http::ContentType;
+
+        let client = client();
+        let content = "fn main() {\n    let mut answer = 42;\n}";
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body(format!(
+                "content={}&title=&expires=&password=&language=",
+                rocket::http::RawStr::new(content).percent_encode()
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        let location = response.headers().get_one("Location").unwrap().to_owned();
+
+        let response = client.get(location.as_str()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.into_string().unwrap();
+        assert!(body.contains(r#"<span class="tag is-info is-light">rust</span>"#));
+        assert!(body.contains("<pre style="));
+
+        let shortcode = location.trim_start_matches("/clip/");
+        let response = client.get(format!("/clip/raw/{shortcode}")).dispatch();
+        assert_eq!(
+            response.into_string().unwrap(),
+            Content::new(content).unwrap().into_inner()
+        );
+    }
+
+    #[test]
+    fn encrypted_clips_only_store_ciphertext() {
+        use crate::domain::clip::field::{ClipKey, EditSecret, Encrypted};
+        use crate::service::{self, ServiceErr};
+        use rocket::http::ContentType;
+
+        let rt = async_runtime();
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+        let post = |content: &str| {
+            client
+                .post("/")
+                .header(ContentType::Form)
+                .body(format!(
+                    "content={}&title=&expires=&password=&encrypted=on",
+                    rocket::http::RawStr::new(content).percent_encode()
+                ))
+                .dispatch()
+        };
+
+        // * Plaintext is rejected, so a client that failed to encrypt doesn't leak the content
+        assert_eq!(post("fn main() {}").status(), Status::BadRequest);
+
+        let key = ClipKey::generate();
+        let ciphertext = key.encrypt("fn main() { let secret = 42; }").unwrap();
+        let response = post(ciphertext.as_str());
+        assert_eq!(response.status(), Status::SeeOther);
+        let location = response.headers().get_one("Location").unwrap().to_owned();
+
+        let body = client
+            .get(location.as_str())
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(body.contains(&format!(r#"data-ciphertext="{}""#, ciphertext.as_str())));
+        assert!(!body.contains("secret = 42"));
+        assert!(!body.contains("<pre style="));
+
+        let shortcode = location.trim_start_matches("/clip/");
+        let raw = client.get(format!("/clip/raw/{shortcode}")).dispatch();
+        let raw = Content::new(&raw.into_string().unwrap()).unwrap();
+        assert_eq!(
+            key.decrypt(&raw).unwrap().as_str(),
+            "fn main() { let secret = 42; }"
+        );
+
+        let req = ask::NewClip {
+            content: ciphertext,
+            encrypted: Encrypted::new(true),
+            ..ask::NewClip::new(Content::new("content").unwrap())
+        };
+        let (clip, edit_secret) = rt
+            .block_on(async {
+                service::action::new_clip(
+                    req,
+                    &ShortcodeGenerator::default(),
+                    &TitleLimit::default(),
+                    db.get_pool(),
+                )
+                .await
+            })
+            .unwrap();
+        assert!(clip.encrypted.is_set());
+        assert!(!clip.language.is_set());
+
+        let req = service::ask::UpdateClip {
+            shortcode: clip.shortcode.clone(),
+            content: Content::new("overwritten").unwrap(),
+            expires: clip.expires,
+            password: Default::default(),
+            title: clip.title,
+            language: Default::default(),
+            edit_secret: EditSecret::new(edit_secret.into_inner().unwrap()),
+            user: None,
+            keep_password: false,
+        };
+        let res = rt.block_on(async {
+            service::action::update_clip(req, &TitleLimit::default(), db.get_pool()).await
+        });
+        assert!(matches!(res, Err(ServiceErr::PermissionErr(_))));
+    }
+
+    #[test]
+    fn custom_shortcodes_are_validated_and_unique() {
+        use crate::domain::clip::field::VanityShortcode;
+        use crate::service::{self, ServiceErr};
+        use rocket::http::ContentType;
+        use std::str::FromStr;
+
+        let rt = async_runtime();
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+        let vanity_req = || ask::NewClip {
+            shortcode: VanityShortcode::from_str("my-snippet").unwrap(),
+            ..ask::NewClip::new(Content::new("content").unwrap())
+        };
+
+        let (clip, _) = rt
+            .block_on(async {
+                service::action::new_clip(
+                    vanity_req(),
+                    &ShortcodeGenerator::default(),
+                    &TitleLimit::default(),
+                    db.get_pool(),
+                )
+                .await
+            })
+            .unwrap();
+        assert_eq!(clip.shortcode.as_str(), "my-snippet");
+        let res = rt.block_on(async {
+            service::action::new_clip(
+                vanity_req(),
+                &ShortcodeGenerator::default(),
+                &TitleLimit::default(),
+                db.get_pool(),
+            )
+            .await
+        });
+        assert!(matches!(res, Err(ServiceErr::Conflict(_))));
+
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body("content=content&title=&expires=&password=&shortcode=my-snippet")
+            .dispatch();
+        assert_eq!(response.status(), Status::Conflict);
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body("content=content&title=&expires=&password=&shortcode=api")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let response = client.get("/clip/my~snippet").dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+    }
+
+    #[test]
+    fn uploads_and_downloads_files() {
+        use rocket::http::ContentType;
+
+        let client = client();
+        let data = [0x89, b'P', b'N', b'G', 0x00, 0xff];
+        let response = client
+            .post("/")
+            .header(multipart_content_type())
+            .body(multipart_body(
+                &[
+                    ("content", b""),
+                    ("title", b""),
+                    ("expires", b""),
+                    ("password", b""),
+                ],
+                Some(("shot.png", &data)),
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        let location = response.headers().get_one("Location").unwrap().to_owned();
+
+        let response = client.get(location.as_str()).dispatch();
+        let body = response.into_string().unwrap();
+        assert!(body.contains("shot.png (image/png, 6 bytes)"));
+
+        let response = client.get(format!("{location}/download")).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::PNG));
+        assert_eq!(
+            response.headers().get_one("Content-Disposition"),
+            Some("attachment; filename=\"shot.png\"; filename*=UTF-8''shot.png")
+        );
+        assert_eq!(response.into_bytes().unwrap(), data);
+
+        // ? Only downloads count towards the views of a clip with a file
+        let response = client
+            .post("/")
+            .header(multipart_content_type())
+            .body(multipart_body(
+                &[
+                    ("content", b""),
+                    ("title", b""),
+                    ("expires", b""),
+                    ("password", b""),
+                    ("max_views", b"1"),
+                ],
+                Some(("shot.png", &data)),
+            ))
+            .dispatch();
+        let location = response.headers().get_one("Location").unwrap().to_owned();
+        for _ in 0..2 {
+            let response = client.get(location.as_str()).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            assert!(response
+                .into_string()
+                .unwrap()
+                .contains("deleted after its file was downloaded 1 time(s)"));
+        }
+        let response = client.get(format!("{location}/download")).dispatch();
+        assert_eq!(response.into_bytes().unwrap(), data);
+        let response = client.get(format!("{location}/download")).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.get(location.as_str()).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        // ? Text clips don't have anything to download
+        let response = client
+            .post("/")
+            .header(multipart_content_type())
+            .body(multipart_body(
+                &[
+                    ("content", b"text"),
+                    ("title", b""),
+                    ("expires", b""),
+                    ("password", b""),
+                ],
+                None,
+            ))
+            .dispatch();
+        let location = response.headers().get_one("Location").unwrap().to_owned();
+        let response = client.get(format!("{location}/download")).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn rejects_files_over_the_upload_limit() {
+        let client = client();
+        let data = vec![0; 2 * 1024 * 1024];
+        let response = client
+            .post("/")
+            .header(multipart_content_type())
+            .body(multipart_body(
+                &[
+                    ("content", b"x"),
+                    ("title", b""),
+                    ("expires", b""),
+                    ("password", b""),
+                ],
+                Some(("big.bin", &data)),
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let response = client
+            .post("/")
+            .header(multipart_content_type())
+            .body(multipart_body(
+                &[
+                    ("content", b""),
+                    ("title", b""),
+                    ("expires", b""),
+                    ("password", b""),
+                ],
+                None,
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    const BOUNDARY: &str = "clipstash-boundary";
+
+    fn multipart_content_type() -> rocket::http::ContentType {
+        rocket::http::ContentType::new("multipart", "form-data").with_params(("boundary", BOUNDARY))
+    }
+
+    fn multipart_body(fields: &[(&str, &[u8])], file: Option<(&str, &[u8])>) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (name, value) in fields {
+            body.extend(
+                format!("--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n")
+                    .as_bytes(),
+            );
+            body.extend(*value);
+            body.extend(b"\r\n");
+        }
+        if let Some((file_name, data)) = file {
+            body.extend(
+                format!(
+                    "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{file_name}\"\r\nContent-Type: application/octet-stream\r\n\r\n"
+                )
+                .as_bytes(),
+            );
+            body.extend(data);
+            body.extend(b"\r\n");
+        }
+        body.extend(format!("--{BOUNDARY}--\r\n").as_bytes());
+        body
+    }
 }
@@ -0,0 +1,199 @@
+use crate::web::request_id::RequestId;
+use rocket::{
+    route::{Handler, Outcome},
+    Data, Request, Route,
+};
+use std::str::FromStr;
+use tracing::{level_filters::LevelFilter, Instrument, Level, Span, Subscriber};
+use tracing_subscriber::{fmt::MakeWriter, EnvFilter};
+
+/// * How log lines are written, `pretty` is meant to be read in a terminal and `json` by log collectors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(Self::Pretty),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown log format '{s}', expected pretty or json")),
+        }
+    }
+}
+
+// NOTE `RUST_LOG` directives such as `clipstash::data=debug` take precedence over the configured level
+pub fn subscriber<W>(format: LogFormat, level: Level, out: W) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::from_level(level).into())
+        .from_env_lossy();
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(out)
+        .with_ansi(false);
+    match format {
+        // ? Every span of a line is listed, so the lines of a request can be found by its ID
+        LogFormat::Json => Box::new(
+            builder
+                .json()
+                .with_current_span(false)
+                .with_span_list(true)
+                .finish(),
+        ),
+        LogFormat::Pretty => Box::new(builder.finish()),
+    }
+}
+
+/// * Span of a request, created the first time it's needed and then cached like the request's ID
+pub struct RequestSpan(Span);
+
+impl RequestSpan {
+    pub fn of<'r>(req: &'r Request<'_>) -> &'r Span {
+        &req.local_cache(|| {
+            Self(tracing::info_span!(
+                "request",
+                request_id = %RequestId::of(req),
+                method = %req.method(),
+                uri = %req.uri()
+            ))
+        })
+        .0
+    }
+}
+
+/// * Runs a route's handler, including its guards, in the span of the request
+#[derive(Clone)]
+struct Traced(Box<dyn Handler>);
+
+#[rocket::async_trait]
+impl Handler for Traced {
+    async fn handle<'r>(&self, req: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        let span = RequestSpan::of(req).clone();
+        self.0.handle(req, data).instrument(span).await
+    }
+}
+
+// NOTE Fairings can't wrap the future of a handler, so the routes are wrapped when they're mounted instead
+pub fn traced<R: Into<Vec<Route>>>(routes: R) -> Vec<Route> {
+    routes
+        .into()
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(Traced(route.handler));
+            route
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use parking_lot::Mutex;
+    use serde_json::Value;
+    use std::{io::Write, sync::Arc};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Buffer {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.lock().clone())
+                .unwrap()
+                .lines()
+                .map(ToOwned::to_owned)
+                .collect()
+        }
+
+        fn subscriber(&self, format: LogFormat) -> Box<dyn Subscriber + Send + Sync> {
+            let buffer = self.clone();
+            subscriber(format, Level::INFO, move || buffer.clone())
+        }
+    }
+
+    fn capture<F: FnOnce()>(format: LogFormat, f: F) -> Vec<String> {
+        let buffer = Buffer::default();
+        tracing::subscriber::with_default(buffer.subscriber(format), f);
+        buffer.lines()
+    }
+
+    #[test]
+    fn json_lines_carry_the_fields_of_their_spans() {
+        let lines = capture(LogFormat::Json, || {
+            let request = tracing::info_span!("request", request_id = "abc123");
+            let _request = request.enter();
+            let query = tracing::info_span!("get_clip");
+            query.in_scope(|| tracing::error!(error = "boom", attempt = 2, "internal error"));
+        });
+        assert_eq!(lines.len(), 1);
+        let line: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(line["level"], "ERROR");
+        assert_eq!(line["fields"]["message"], "internal error");
+        assert_eq!(line["fields"]["error"], "boom");
+        assert_eq!(line["fields"]["attempt"], 2);
+        assert_eq!(line["spans"][0]["name"], "request");
+        assert_eq!(line["spans"][0]["request_id"], "abc123");
+        assert_eq!(line["spans"][1]["name"], "get_clip");
+    }
+
+    #[test]
+    fn pretty_lines_and_levels() {
+        let lines = capture(LogFormat::Pretty, || {
+            tracing::info_span!("request", request_id = %"abc123").in_scope(|| {
+                tracing::debug!("filtered out");
+                tracing::warn!(hits = 3, "slow commit");
+            });
+        });
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains(" WARN request{request_id=abc123}: "));
+        assert!(lines[0].ends_with("slow commit hits=3"));
+    }
+
+    #[rocket::get("/logged")]
+    fn logged() -> &'static str {
+        tracing::info!("handled");
+        "ok"
+    }
+
+    #[test]
+    fn request_handlers_run_in_the_request_span() {
+        let buffer = Buffer::default();
+        let rocket = rocket::build().mount("/", traced(rocket::routes![logged]));
+        tracing::subscriber::with_default(buffer.subscriber(LogFormat::Json), || {
+            crate::test::async_runtime().block_on(async {
+                let client = rocket::local::asynchronous::Client::untracked(rocket)
+                    .await
+                    .unwrap();
+                client
+                    .get("/logged")
+                    .header(rocket::http::Header::new("x-request-id", "traced-request"))
+                    .dispatch()
+                    .await;
+            })
+        });
+        let lines = buffer.lines();
+        assert_eq!(lines.len(), 1);
+        let line: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(line["fields"]["message"], "handled");
+        assert_eq!(line["spans"][0]["request_id"], "traced-request");
+        assert_eq!(line["spans"][0]["method"], "GET");
+        assert_eq!(line["spans"][0]["uri"], "/logged");
+    }
+}
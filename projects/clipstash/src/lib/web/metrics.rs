@@ -0,0 +1,113 @@
+use crate::{
+    data::AppDatabase,
+    metrics::{Gauges, METRICS},
+    web::{hitcounter::HitCounter, ratelimit},
+};
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::{ContentType, Status},
+    Data, Request, Response, State,
+};
+use std::time::Instant;
+
+/// * Reads the gauges from the app's state when the metrics are scraped
+fn sample(hit_counter: &HitCounter, database: &AppDatabase) -> Gauges {
+    let pool = database.get_pool();
+    Gauges {
+        hit_queue: hit_counter.queue_depth(),
+        pool_size: pool.size() as usize,
+        pool_idle: pool.num_idle(),
+    }
+}
+
+/// * Start of a request, cached in the request so its duration can be measured once it's answered
+struct RequestStart(Instant);
+
+/// * Records the count and latency of every request
+pub struct RequestMetrics;
+
+#[rocket::async_trait]
+impl Fairing for RequestMetrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        req.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let start = req.local_cache(|| RequestStart(Instant::now()));
+        // ? Requests that didn't match any route are grouped together, their paths are made up by clients
+        // ? Every request goes through the rate limiter's catch-all routes first, which forward the ones within the limits
+        let route = match req.route() {
+            Some(route)
+                if ratelimit::is_catch_all(route) && res.status() != Status::TooManyRequests =>
+            {
+                "unmatched"
+            }
+            Some(route) => route.uri.as_str(),
+            None => "unmatched",
+        };
+        METRICS.request(
+            req.method().as_str(),
+            route,
+            res.status().code,
+            start.0.elapsed(),
+        );
+    }
+}
+
+// NOTE The endpoint isn't authenticated, like most scrape targets it's meant to be kept away from the public by the proxy
+#[rocket::get("/metrics")]
+pub fn metrics(
+    hit_counter: &State<HitCounter>,
+    database: &State<AppDatabase>,
+) -> (ContentType, String) {
+    let gauges = sample(hit_counter, database);
+    // ? Version 0.0.4 of the text format is the one Prometheus expects from scrape targets
+    let content_type = ContentType::new("text", "plain").with_params(("version", "0.0.4"));
+    (content_type, METRICS.render(&gauges))
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![metrics]
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::web::test::client;
+
+    #[test]
+    fn requests_are_counted_by_route() {
+        let client = client();
+        assert_eq!(client.get("/").dispatch().status(), Status::Ok);
+        let response = client.get("/clip/abc/missing/path").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.patch("/clip/abc/missing/path").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.get("/clip/missingclip").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client.get("/metrics").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.into_string().unwrap();
+        // * The metrics are global, other tests may have recorded requests too
+        assert!(
+            body.contains(r#"clipstash_http_requests_total{method="GET",route="/",status="200"} "#)
+        );
+        // * Paths that no route matched are grouped together, whether the rate limiter's catch-all routes forwarded them or not
+        assert!(body.contains(r#"method="PATCH",route="unmatched",status="404""#));
+        assert!(body.contains(r#"method="GET",route="unmatched",status="404""#));
+        assert!(!body.contains(r#"route="/<_..>",status="404""#));
+        assert!(body.contains(r#"method="GET",route="/clip/<shortcode>",status="404""#));
+        assert!(!body.contains("/clip/abc/missing/path"));
+        assert!(body.contains("# TYPE clipstash_http_request_duration_seconds histogram"));
+        assert!(body.contains("clipstash_hit_counter_queue_depth "));
+        assert!(body.contains("clipstash_db_pool_connections "));
+    }
+}
@@ -1,11 +1,25 @@
+pub mod account;
 pub mod api;
 pub mod ctx;
 pub mod form;
+pub mod highlight;
 pub mod hitcounter;
 pub mod http;
+pub mod logging;
+pub mod metrics;
+pub mod openapi;
+pub mod ratelimit;
 pub mod renderer;
+pub mod request_id;
 
-pub const PASSWORD_COOKIE: &str = "password";
+use crate::Shortcode;
+
+pub const ACCESS_TOKEN_COOKIE_PREFIX: &str = "clip_token_";
+
+// NOTE Each clip gets its own cookie, so unlocking a clip doesn't overwrite the access to another one
+pub fn access_token_cookie(shortcode: &Shortcode) -> String {
+    format!("{ACCESS_TOKEN_COOKIE_PREFIX}{}", shortcode.as_str())
+}
 
 #[derive(rocket::Responder)]
 pub enum PageErr {
@@ -33,33 +47,82 @@ impl From<serde_json::Error> for PageErr {
 
 #[cfg(test)]
 pub mod test {
+    use crate::data::test::TestDb;
     use crate::test::async_runtime;
-    use crate::RocketConfig;
+    use crate::{AppDatabase, RocketConfig};
     use rocket::local::blocking::Client;
+    use std::sync::OnceLock;
+    use tokio::runtime::Runtime;
+
+    // NOTE The background tasks and the pool's connection live on this runtime, so it must outlive every test
+    pub fn runtime() -> &'static Runtime {
+        static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+        RUNTIME.get_or_init(async_runtime)
+    }
+
+    /// * A client along with the database of its test
+    // ? The fields are dropped in order, so the client shuts down before the database is dropped
+    pub struct TestClient {
+        client: Client,
+        _database: TestDb,
+    }
+
+    impl std::ops::Deref for TestClient {
+        type Target = Client;
+
+        fn deref(&self) -> &Self::Target {
+            &self.client
+        }
+    }
 
-    pub fn config() -> RocketConfig {
+    // ? The database is returned on its own, `RocketConfig` only holds a handle to it
+    pub fn config() -> (RocketConfig, TestDb) {
+        use crate::service::token::AccessTokenSigner;
         use crate::web::{hitcounter::HitCounter, renderer::Renderer};
 
-        let rt = async_runtime();
+        let rt = runtime();
         let renderer = Renderer::new("templates/".into());
         let database = crate::data::test::new_db(rt.handle());
+        let settings = crate::Config {
+            max_upload_size: rocket::data::ByteUnit::Mebibyte(1),
+            ..Default::default()
+        };
         let maintenance = crate::domain::maintenance::Maintenance::spawn(
             database.get_pool().clone(),
             rt.handle().clone(),
+            settings.maintenance_interval,
+        );
+        let hit_counter = HitCounter::new(
+            database.get_pool().clone(),
+            rt.handle().clone(),
+            settings.hit_counter(),
         );
-        let hit_counter = HitCounter::new(database.get_pool().clone(), rt.handle().clone());
+        let token_signer = AccessTokenSigner::random(chrono::Duration::hours(1));
 
-        RocketConfig {
+        let config = RocketConfig {
             renderer,
-            database,
+            database: AppDatabase::clone(&database),
             hit_counter,
             maintenance,
-        }
+            token_signer,
+            shortcodes: Default::default(),
+            title_limit: Default::default(),
+            rate_limiter: Default::default(),
+            config: settings,
+        };
+        (config, database)
     }
 
-    pub fn client() -> Client {
-        let config = config();
+    pub fn tracked(config: RocketConfig, database: TestDb) -> TestClient {
         let rocket = crate::rocket(config);
-        Client::tracked(rocket).expect("failed to build rocket instance")
+        TestClient {
+            client: Client::tracked(rocket).expect("failed to build rocket instance"),
+            _database: database,
+        }
+    }
+
+    pub fn client() -> TestClient {
+        let (config, database) = config();
+        tracked(config, database)
     }
 }
@@ -0,0 +1,493 @@
+use crate::{
+    domain::{
+        clip::field::{Attachment, LANGUAGES},
+        ApiScope, ClipOrder, Cursor,
+    },
+    service::ask,
+    web::api::{
+        self, v1, ACCESS_TOKEN_HEADER, API_KEY_HEADER, EDIT_SECRET_HEADER, PASSWORD_HEADER,
+    },
+};
+use rocket::{serde::json::Json, Route};
+use serde_json::{json, Map, Value};
+use utoipa::{
+    openapi::{Object, ObjectBuilder, SchemaType},
+    ToSchema,
+};
+
+/// * Operations that aren't described here are left out of the document, the drift test catches them
+/// * Paths, methods, request content types and scopes come from the routes themselves
+struct Operation {
+    summary: &'static str,
+    parameters: Vec<Value>,
+    request: Option<&'static str>,
+    response: Value,
+    /// * Headers of successful responses, by name and description
+    response_headers: Vec<(&'static str, &'static str)>,
+}
+
+fn describe(route_name: &str) -> Option<Operation> {
+    let shortcode = || path_parameter("shortcode", json!({"type": "string"}));
+    let credentials = || {
+        vec![
+            header_parameter(PASSWORD_HEADER, "Password of a password protected clip"),
+            header_parameter(
+                ACCESS_TOKEN_HEADER,
+                "Access token issued when the password was verified",
+            ),
+        ]
+    };
+    let operation = match route_name {
+        "list_clips" => Operation {
+            summary: "Lists the available clips, the `next` cursor of a page is sent as `cursor` to get the following one",
+            parameters: vec![
+                query_parameter("q", json!({"type": "string"}), "Searches the title and content"),
+                query_parameter("title", json!({"type": "string"}), "Only matches the title"),
+                query_parameter(
+                    "posted_after",
+                    json!({"type": "string"}),
+                    "RFC 3339 timestamp or `YYYY-MM-DD` date",
+                ),
+                query_parameter("order", order_schema(), "Order of the clips, `newest` by default"),
+                query_parameter("cursor", json!({"type": "string"}), "`next` cursor of the previous page"),
+                query_parameter(
+                    "limit",
+                    json!({"type": "integer", "minimum": 1, "maximum": crate::domain::listing::MAX_PAGE_SIZE}),
+                    "Clips per page",
+                ),
+            ],
+            request: None,
+            response: reference("ClipPage"),
+            response_headers: vec![],
+        },
+        "get_clip" => Operation {
+            summary: "Gets a clip, reading a clip with limited views consumes one of them",
+            parameters: [vec![shortcode()], credentials()].concat(),
+            request: None,
+            response: reference("Clip"),
+            response_headers: vec![(
+                ACCESS_TOKEN_HEADER,
+                "Access token for the clip, only sent when its password was verified",
+            )],
+        },
+        "get_revisions" => Operation {
+            summary: "Every revision of a clip, from the oldest to the current one, consumes a view like reading the clip",
+            parameters: [vec![shortcode()], credentials()].concat(),
+            request: None,
+            response: json!({"type": "array", "items": reference("Revision")}),
+            response_headers: vec![],
+        },
+        "get_revision" => Operation {
+            summary: "A single revision of a clip, consumes a view like reading the clip",
+            parameters: [
+                vec![
+                    shortcode(),
+                    path_parameter("revision", json!({"type": "integer", "minimum": 1})),
+                ],
+                credentials(),
+            ]
+            .concat(),
+            request: None,
+            response: reference("Revision"),
+            response_headers: vec![],
+        },
+        // ? Both routes share a path and method, their request content types are merged
+        "new_clip" | "upload_clip" => Operation {
+            summary: "Creates a clip, the edit secret is only returned here",
+            parameters: vec![],
+            request: Some("NewClip"),
+            response: reference("NewClipResponse"),
+            response_headers: vec![],
+        },
+        "update_clip" => Operation {
+            summary: "Updates a clip, the previous version is kept as a revision",
+            parameters: vec![],
+            request: Some("UpdateClip"),
+            response: reference("Clip"),
+            response_headers: vec![],
+        },
+        "delete_clip" => Operation {
+            summary: "Deletes a clip",
+            parameters: vec![
+                shortcode(),
+                header_parameter(
+                    EDIT_SECRET_HEADER,
+                    "Edit secret of the clip, not needed with a key of the clip's owner",
+                ),
+            ],
+            request: None,
+            response: reference("Clip"),
+            response_headers: vec![],
+        },
+        _ => return None,
+    };
+    Some(operation)
+}
+
+fn reference(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{name}") })
+}
+
+fn path_parameter(name: &str, schema: Value) -> Value {
+    json!({"name": name, "in": "path", "required": true, "schema": schema})
+}
+
+fn query_parameter(name: &str, schema: Value, description: &str) -> Value {
+    json!({"name": name, "in": "query", "required": false, "schema": schema, "description": description})
+}
+
+fn header_parameter(name: &str, description: &str) -> Value {
+    json!({"name": name, "in": "header", "required": false, "schema": {"type": "string"}, "description": description})
+}
+
+fn order_schema() -> Value {
+    let orders = [ClipOrder::Newest, ClipOrder::Oldest, ClipOrder::Hits].map(|o| o.to_string());
+    json!({"type": "string", "enum": orders})
+}
+
+// NOTE Rocket's `<name>` segments become OpenAPI's `{name}`, the trailing slash of the mount point is dropped
+fn openapi_path(base: &str, route: &Route) -> String {
+    let path = route
+        .uri
+        .path()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| match segment.strip_prefix('<') {
+            Some(name) => format!("{{{}}}", name.trim_end_matches('>')),
+            None => segment.to_owned(),
+        })
+        .collect::<Vec<_>>();
+    match path.is_empty() {
+        true => base.to_owned(),
+        false => format!("{base}/{}", path.join("/")),
+    }
+}
+
+fn paths(base: &str, routes: &[Route]) -> Map<String, Value> {
+    let mut paths = Map::new();
+    for route in routes {
+        let Some(name) = route.name.as_deref() else {
+            continue;
+        };
+        let Some(operation) = describe(name) else {
+            continue;
+        };
+        let method = route.method.as_str().to_lowercase();
+        let entry = paths
+            .entry(openapi_path(base, route))
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .expect("paths are objects")
+            .entry(method)
+            .or_insert_with(|| {
+                let scope = ApiScope::for_method(route.method);
+                json!({
+                    "operationId": name,
+                    "summary": operation.summary,
+                    "description": format!("Requires an API key with the `{scope}` scope"),
+                    "parameters": operation.parameters,
+                    "security": [{"api_key": []}],
+                    "responses": {
+                        "200": {
+                            "description": "Success",
+                            "content": {"application/json": {"schema": operation.response}}
+                        },
+                        "default": {
+                            "description": "Error",
+                            "content": {"application/json": {"schema": reference("ErrorResponse")}}
+                        }
+                    }
+                })
+            });
+        for (header, description) in &operation.response_headers {
+            entry["responses"]["200"]["headers"][*header] =
+                json!({"description": description, "schema": string()});
+        }
+        if let Some(request) = operation.request {
+            let media_type = route
+                .format
+                .as_ref()
+                .map(|format| format.to_string())
+                .unwrap_or_else(|| "application/json".to_owned());
+            // ? Uploads send the same fields as a multipart form, along with the file
+            let schema = match media_type.as_str() {
+                "multipart/form-data" => json!({
+                    "allOf": [reference(request), {
+                        "type": "object",
+                        "properties": {"file": {"type": "string", "format": "binary"}}
+                    }]
+                }),
+                _ => reference(request),
+            };
+            entry["requestBody"]["required"] = json!(true);
+            entry["requestBody"]["content"][media_type] = json!({ "schema": schema });
+        }
+    }
+    paths
+}
+
+fn string() -> Value {
+    json!({"type": "string"})
+}
+
+// NOTE The functions below are used by the `schema_with` attributes of the types, for schemas that can't be derived
+
+/// * Languages that can be picked, `null` has the language detected from the content
+pub fn language() -> Object {
+    ObjectBuilder::new()
+        .schema_type(SchemaType::String)
+        .nullable(true)
+        .enum_values(Some(LANGUAGES.iter().map(|(token, _)| *token)))
+        .build()
+}
+
+// ? Requests also accept durations, which are turned into a timestamp by the server
+pub fn expires() -> Object {
+    ObjectBuilder::new()
+        .schema_type(SchemaType::String)
+        .nullable(true)
+        .description(Some(
+            "RFC 3339 timestamp, `YYYY-MM-DD` date or duration such as `2h` or `7d`",
+        ))
+        .example(Some(json!("7d")))
+        .build()
+}
+
+pub fn cursor() -> Object {
+    let example = Cursor {
+        order: ClipOrder::Newest,
+        sort_key: 1_700_000_000,
+        clip_id: "00000000-0000-0000-0000-000000000000".to_owned(),
+    };
+    ObjectBuilder::new()
+        .schema_type(SchemaType::String)
+        .nullable(true)
+        .description(Some("Opaque position in the listing"))
+        .example(Some(json!(example.to_string())))
+        .build()
+}
+
+/// * Schemas of the API's JSON bodies, derived from the types the handlers take and return
+fn schemas() -> Map<String, Value> {
+    [
+        v1::Clip::schema(),
+        v1::NewClipResponse::schema(),
+        Attachment::schema(),
+        ask::NewClip::schema(),
+        ask::UpdateClip::schema(),
+        v1::ClipSummary::schema(),
+        v1::ClipPage::schema(),
+        v1::Revision::schema(),
+        api::ErrorResponse::schema(),
+    ]
+    .into_iter()
+    .map(|(name, schema)| {
+        let mut schema = serde_json::to_value(schema).expect("schemas serialize to JSON");
+        strip_bullets(&mut schema);
+        (name.to_owned(), schema)
+    })
+    .collect()
+}
+
+// ? Descriptions come from doc comments, which are written as `* ` bullets
+fn strip_bullets(schema: &mut Value) {
+    match schema {
+        Value::Object(schema) => {
+            if let Some(Value::String(description)) = schema.get_mut("description") {
+                *description = description
+                    .lines()
+                    .map(|line| line.trim_start_matches("* "))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+            }
+            schema.values_mut().for_each(strip_bullets);
+        }
+        Value::Array(values) => values.iter_mut().for_each(strip_bullets),
+        _ => {}
+    }
+}
+
+/// * OpenAPI 3 description of the JSON API, a copy is kept in `openapi.json` for client generators
+pub fn spec() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "ClipStash API",
+            "version": env!("CARGO_PKG_VERSION")
+        },
+        "paths": paths(api::v1::BASE, &api::v1::routes()),
+        "components": {
+            "schemas": schemas(),
+            "securitySchemes": {
+                "api_key": {"type": "apiKey", "in": "header", "name": API_KEY_HEADER}
+            }
+        }
+    })
+}
+
+#[rocket::get("/openapi.json")]
+fn openapi() -> Json<Value> {
+    Json(spec())
+}
+
+pub fn routes() -> Vec<Route> {
+    rocket::routes![openapi]
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{schemas, spec};
+    use crate::web::{api, test::client};
+    use rocket::http::Status;
+    use serde_json::{json, Map, Value};
+    use std::collections::BTreeSet;
+
+    /// * Properties and required fields of an object schema, the parts of `allOf` are merged
+    fn fields(
+        schema: &Value,
+        schemas: &Map<String, Value>,
+    ) -> (Map<String, Value>, BTreeSet<String>) {
+        if let Some(name) = schema["$ref"].as_str() {
+            let name = name.trim_start_matches("#/components/schemas/");
+            return fields(&schemas[name], schemas);
+        }
+        if let Some(parts) = schema["allOf"].as_array() {
+            return parts.iter().map(|part| fields(part, schemas)).fold(
+                Default::default(),
+                |(mut properties, mut required), (part_properties, part_required)| {
+                    properties.extend(part_properties);
+                    required.extend(part_required);
+                    (properties, required)
+                },
+            );
+        }
+        let properties = schema["properties"]
+            .as_object()
+            .cloned()
+            .unwrap_or_default();
+        let required = schema["required"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|name| name.as_str().unwrap().to_owned())
+            .collect();
+        (properties, required)
+    }
+
+    /// * Example value of a schema, nullable values are never null so every field is exercised
+    fn example(schema: &Value, schemas: &Map<String, Value>) -> Value {
+        if let Some(name) = schema["$ref"].as_str() {
+            let name = name.trim_start_matches("#/components/schemas/");
+            return example(&schemas[name], schemas);
+        }
+        if !schema["example"].is_null() {
+            return schema["example"].clone();
+        }
+        if let Some(values) = schema["enum"].as_array() {
+            return values[0].clone();
+        }
+        if schema["allOf"].is_array() || schema["type"] == "object" {
+            let (properties, _) = fields(schema, schemas);
+            return Value::Object(
+                properties
+                    .iter()
+                    .map(|(name, schema)| (name.clone(), example(schema, schemas)))
+                    .collect(),
+            );
+        }
+        match (schema["type"].as_str(), schema["format"].as_str()) {
+            (Some("array"), _) => json!([example(&schema["items"], schemas)]),
+            (Some("string"), Some("date-time")) => json!("2030-01-01T00:00:00Z"),
+            (Some("string"), _) => json!("example"),
+            (Some("integer"), _) => json!(1),
+            (Some("boolean"), _) => json!(false),
+            _ => panic!("no example for {schema}"),
+        }
+    }
+
+    // ? Deserializes the example into the schema's type and serializes it back, like the API does
+    fn round_trip(name: &str, value: Value) -> Result<Value, serde_json::Error> {
+        use crate::{domain, service::ask};
+        use serde_json::{from_value, to_value};
+        match name {
+            "Clip" => to_value(from_value::<api::v1::Clip>(value)?),
+            "NewClipResponse" => to_value(from_value::<api::v1::NewClipResponse>(value)?),
+            "Attachment" => to_value(from_value::<domain::clip::field::Attachment>(value)?),
+            "NewClip" => to_value(from_value::<ask::NewClip>(value)?),
+            "UpdateClip" => to_value(from_value::<ask::UpdateClip>(value)?),
+            "ClipSummary" => to_value(from_value::<api::v1::ClipSummary>(value)?),
+            "ClipPage" => to_value(from_value::<api::v1::ClipPage>(value)?),
+            "Revision" => to_value(from_value::<api::v1::Revision>(value)?),
+            "ErrorResponse" => to_value(from_value::<api::ErrorResponse>(value)?),
+            _ => panic!("no type for the {name} schema"),
+        }
+    }
+
+    /// * Required fields of requests are the ones serde can't do without
+    /// * Required fields of responses are the ones that are always serialized
+    const REQUESTS: &[&str] = &["NewClip", "UpdateClip"];
+
+    #[test]
+    fn schemas_match_the_serialized_types() {
+        let schemas = schemas();
+        for (name, schema) in &schemas {
+            let (properties, required) = fields(schema, &schemas);
+
+            let full = example(schema, &schemas);
+            let serialized = round_trip(name, full.clone())
+                .unwrap_or_else(|e| panic!("the {name} example doesn't deserialize: {e}"));
+            assert_eq!(
+                serialized
+                    .as_object()
+                    .unwrap()
+                    .keys()
+                    .collect::<BTreeSet<_>>(),
+                properties.keys().collect::<BTreeSet<_>>(),
+                "the properties of {name} don't match its serialized fields"
+            );
+
+            for property in properties.keys() {
+                let mut partial = full.clone();
+                partial.as_object_mut().unwrap().remove(property);
+                let is_required = match round_trip(name, partial) {
+                    Err(_) => true,
+                    Ok(_) if REQUESTS.contains(&name.as_str()) => false,
+                    Ok(serialized) => serialized.get(property).is_some(),
+                };
+                assert_eq!(
+                    is_required,
+                    required.contains(property),
+                    "{name}.{property} is required by serde but not by the schema, or the other way around"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_route_is_documented() {
+        let spec = spec();
+        for route in api::v1::routes() {
+            let path = super::openapi_path(api::v1::BASE, &route);
+            let method = route.method.as_str().to_lowercase();
+            assert!(
+                spec["paths"][&path][&method].is_object(),
+                "{method} {path} isn't described in the OpenAPI document"
+            );
+        }
+    }
+
+    #[test]
+    fn checked_in_spec_is_up_to_date() {
+        let generated = serde_json::to_string_pretty(&spec()).unwrap();
+        let checked_in = std::fs::read_to_string("openapi.json").unwrap_or_default();
+        assert!(
+            generated.trim() == checked_in.trim(),
+            "openapi.json is out of date, regenerate it with `cargo run --bin httpd -- openapi > openapi.json`"
+        );
+
+        let client = client();
+        let response = client.get("/api/openapi.json").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_json::<Value>(), Some(spec()));
+    }
+}
@@ -0,0 +1,427 @@
+use crate::{
+    domain::ApiScope,
+    web::{
+        api::{self, ErrorResponse},
+        request_id::RequestId,
+    },
+};
+use parking_lot::Mutex;
+use rocket::{
+    http::{ContentType, Header, Status},
+    request::{FromRequest, Outcome, Request},
+    Responder,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum RateLimitErr {
+    #[error("invalid rate limit '{0}', expected e.g. create=30/m")]
+    Invalid(String),
+}
+
+/// * `capacity` requests are allowed in a burst, then tokens are refilled evenly over `period`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    capacity: u32,
+    period: Duration,
+}
+
+impl RateLimit {
+    pub fn new(capacity: u32, period: Duration) -> Self {
+        Self { capacity, period }
+    }
+
+    fn refill_rate(&self) -> f64 {
+        self.capacity as f64 / self.period.as_secs_f64()
+    }
+}
+
+impl FromStr for RateLimit {
+    type Err = RateLimitErr;
+    /// * Parses limits such as `10/s`, `30/m` or `1000/h`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || RateLimitErr::Invalid(s.to_owned());
+        let (capacity, unit) = s.trim().split_once('/').ok_or_else(invalid)?;
+        let capacity = capacity.parse::<u32>().map_err(|_| invalid())?;
+        let period = match unit {
+            "s" => Duration::from_secs(1),
+            "m" => Duration::from_secs(60),
+            "h" => Duration::from_secs(60 * 60),
+            _ => return Err(invalid()),
+        };
+        match capacity {
+            0 => Err(invalid()),
+            _ => Ok(Self::new(capacity, period)),
+        }
+    }
+}
+
+/// * Limits for each scope, requests in scopes without a limit are never limited
+/// * The scope of a request follows from its method, the same way API keys are checked
+#[derive(Clone, Debug, Default)]
+pub struct RateLimits(HashMap<ApiScope, RateLimit>);
+
+impl RateLimits {
+    pub fn with(mut self, scope: ApiScope, limit: RateLimit) -> Self {
+        self.0.insert(scope, limit);
+        self
+    }
+
+    fn get(&self, scope: ApiScope) -> Option<&RateLimit> {
+        self.0.get(&scope)
+    }
+}
+
+impl FromStr for RateLimits {
+    type Err = RateLimitErr;
+    /// * Parses comma separated limits, e.g. `read=300/m,create=30/m`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|limit| !limit.is_empty())
+            .try_fold(Self::default(), |limits, limit| {
+                let invalid = || RateLimitErr::Invalid(limit.to_owned());
+                let (scope, rate) = limit.split_once('=').ok_or_else(invalid)?;
+                let scope = ApiScope::from_str(scope.trim()).map_err(|_| invalid())?;
+                Ok(limits.with(scope, rate.parse()?))
+            })
+    }
+}
+
+/// * API requests are limited by their key, everything else and requests with an invalid key by the client's IP
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum RateKey {
+    ApiKey(String),
+    Ip(Option<IpAddr>),
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+type BucketKey = (RateKey, ApiScope);
+
+// NOTE The oldest bucket is evicted to make room once there are this many, a full bucket is the same as a missing one
+const MAX_BUCKETS: usize = 10_000;
+// ? Only this many of the oldest buckets are looked at for an idle one, which bounds the work done under the lock
+const EVICTION_CANDIDATES: usize = 8;
+
+/// * Buckets along with the order they were created in
+#[derive(Default)]
+struct Buckets {
+    buckets: HashMap<BucketKey, Bucket>,
+    created: VecDeque<BucketKey>,
+}
+
+impl Buckets {
+    fn get_or_create(
+        &mut self,
+        key: BucketKey,
+        limit: &RateLimit,
+        limits: &RateLimits,
+        now: Instant,
+    ) -> &mut Bucket {
+        if !self.buckets.contains_key(&key) {
+            if self.buckets.len() >= MAX_BUCKETS {
+                self.evict(limits, now);
+            }
+            self.created.push_back(key.clone());
+        }
+        self.buckets.entry(key).or_insert(Bucket {
+            tokens: limit.capacity as f64,
+            updated: now,
+        })
+    }
+
+    /// * Evicts the oldest idle bucket, or the oldest one when the candidates are all still refilling
+    fn evict(&mut self, limits: &RateLimits, now: Instant) {
+        let idle = self
+            .created
+            .iter()
+            .take(EVICTION_CANDIDATES)
+            .position(|key| {
+                self.buckets.get(key).is_none_or(|bucket| {
+                    limits
+                        .get(key.1)
+                        .is_none_or(|limit| now.duration_since(bucket.updated) >= limit.period)
+                })
+            });
+        if let Some(key) = self.created.remove(idle.unwrap_or(0)) {
+            self.buckets.remove(&key);
+        }
+    }
+}
+
+/// * Token bucket rate limiter, the buckets live in memory so limits reset when the server restarts
+#[derive(Default)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            buckets: Mutex::new(Buckets::default()),
+        }
+    }
+
+    /// * Takes a token from the bucket, or returns how long to wait for the next one
+    pub fn check(&self, key: RateKey, scope: ApiScope, now: Instant) -> Result<(), Duration> {
+        let Some(limit) = self.limits.get(scope) else {
+            return Ok(());
+        };
+
+        let mut buckets = self.buckets.lock();
+        let bucket = buckets.get_or_create((key, scope), limit, &self.limits, now);
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.refill_rate()).min(limit.capacity as f64);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / limit.refill_rate(),
+            ))
+        }
+    }
+}
+
+/// * Request guard that only succeeds when the client ran out of requests, otherwise the request is forwarded
+pub struct LimitExceeded {
+    retry_after: Duration,
+    // ? API clients get a JSON error with the request's ID
+    api: Option<RequestId>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LimitExceeded {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let path = req.uri().path();
+        let Some(limiter) = req.rocket().state::<RateLimiter>() else {
+            return Outcome::Forward(Status::NotFound);
+        };
+        if path.starts_with("/static/") {
+            return Outcome::Forward(Status::NotFound);
+        }
+
+        let api = path
+            .starts_with("/api/")
+            .then(|| RequestId::of(req).clone());
+        // ? Keys are only trusted once they're found, otherwise every made up key would get its own bucket
+        let api_key = match api {
+            Some(_) => api::valid_key(req).await,
+            None => None,
+        };
+        let key = match api_key {
+            Some(api_key) => RateKey::ApiKey(api_key.to_base64()),
+            None => RateKey::Ip(req.client_ip()),
+        };
+        match limiter.check(key, ApiScope::for_method(req.method()), Instant::now()) {
+            // ? The forwarded status is what's left when no other route matches either
+            Ok(()) => Outcome::Forward(Status::NotFound),
+            Err(retry_after) => Outcome::Success(Self { retry_after, api }),
+        }
+    }
+}
+
+#[derive(Responder)]
+#[response(status = 429)]
+pub struct TooManyRequests {
+    body: (ContentType, String),
+    retry_after: Header<'static>,
+}
+
+impl From<LimitExceeded> for TooManyRequests {
+    fn from(limit: LimitExceeded) -> Self {
+        // ? Retry-After only supports whole seconds, rounding down would make clients retry too early
+        let seconds = limit.retry_after.as_secs_f64().ceil().max(1.0) as u64;
+        let message = format!("too many requests, retry in {seconds} seconds");
+        let body = match limit.api {
+            Some(request_id) => {
+                let error = ErrorResponse::new("rate_limited", message, &request_id)
+                    .with_details(serde_json::json!({ "retry_after": seconds }));
+                (
+                    ContentType::JSON,
+                    serde_json::to_string(&error).unwrap_or_default(),
+                )
+            }
+            None => (ContentType::Plain, message),
+        };
+        Self {
+            body,
+            retry_after: Header::new("Retry-After", seconds.to_string()),
+        }
+    }
+}
+
+#[rocket::get("/<_..>")]
+fn limit_get(limit: LimitExceeded) -> TooManyRequests {
+    limit.into()
+}
+
+#[rocket::post("/<_..>")]
+fn limit_post(limit: LimitExceeded) -> TooManyRequests {
+    limit.into()
+}
+
+#[rocket::put("/<_..>")]
+fn limit_put(limit: LimitExceeded) -> TooManyRequests {
+    limit.into()
+}
+
+#[rocket::delete("/<_..>")]
+fn limit_delete(limit: LimitExceeded) -> TooManyRequests {
+    limit.into()
+}
+
+// NOTE Ranks below the automatic ones (-12 to -1) make these routes run before any other one
+// NOTE The macros only accept positive ranks, so the rank is set on the routes themselves
+const RANK: isize = -20;
+
+/// * Whether the route is one of the catch-all routes below, which only answer requests that are out of limits
+/// * When one of them is the request's route and the status isn't 429, it forwarded and no other route matched
+pub fn is_catch_all(route: &rocket::Route) -> bool {
+    route.rank == RANK
+}
+
+/// * Catch-all routes that answer with a 429 once the client is out of requests
+/// * Their guard forwards requests that are within the limits to the actual routes
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![limit_get, limit_post, limit_put, limit_delete]
+        .into_iter()
+        .map(|mut route| {
+            route.rank = RANK;
+            route
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::data::AppDatabase;
+    use crate::service::{action, ask};
+    use crate::test::async_runtime;
+    use rocket::http::Status;
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let limiter = RateLimiter::new("create=2/s".parse().unwrap());
+        let key = || RateKey::Ip(None);
+        let now = Instant::now();
+
+        assert!(limiter.check(key(), ApiScope::Create, now).is_ok());
+        assert!(limiter.check(key(), ApiScope::Create, now).is_ok());
+        let retry_after = limiter.check(key(), ApiScope::Create, now).unwrap_err();
+        assert_eq!(retry_after, Duration::from_millis(500));
+        // * Other scopes have their own bucket, and scopes without a limit are never limited
+        assert!(limiter.check(key(), ApiScope::Read, now).is_ok());
+
+        let later = now + Duration::from_millis(500);
+        assert!(limiter.check(key(), ApiScope::Create, later).is_ok());
+        assert!(limiter.check(key(), ApiScope::Create, later).is_err());
+        let ip = RateKey::Ip(Some("10.0.0.1".parse().unwrap()));
+        assert!(limiter.check(ip, ApiScope::Create, later).is_ok());
+    }
+
+    #[test]
+    fn oldest_buckets_are_evicted() {
+        let limiter = RateLimiter::new("create=1/m".parse().unwrap());
+        let ip = |i: usize| RateKey::Ip(Some(IpAddr::from((i as u32).to_be_bytes())));
+        let now = Instant::now();
+
+        for i in 0..=MAX_BUCKETS {
+            assert!(limiter.check(ip(i), ApiScope::Create, now).is_ok());
+        }
+        assert_eq!(limiter.buckets.lock().buckets.len(), MAX_BUCKETS);
+        // * Every bucket is still refilling, so the oldest one was evicted and its client starts over
+        assert!(limiter.check(ip(0), ApiScope::Create, now).is_ok());
+        assert!(limiter
+            .check(ip(MAX_BUCKETS), ApiScope::Create, now)
+            .is_err());
+
+        // * Idle buckets are evicted before the ones that are still refilling
+        let later = now + Duration::from_secs(60);
+        assert!(limiter.check(ip(2), ApiScope::Create, later).is_ok());
+        assert!(limiter
+            .check(ip(MAX_BUCKETS + 1), ApiScope::Create, later)
+            .is_ok());
+        assert!(limiter.check(ip(2), ApiScope::Create, later).is_err());
+        assert!(limiter.check(ip(3), ApiScope::Create, later).is_ok());
+        assert_eq!(limiter.buckets.lock().buckets.len(), MAX_BUCKETS);
+    }
+
+    #[test]
+    fn limited_requests_get_429_with_retry_after() {
+        let (mut config, database) = crate::web::test::config();
+        config.rate_limiter = RateLimiter::new("create=1/m".parse().unwrap());
+        let client = crate::web::test::tracked(config, database);
+        let post = |ip: &str| {
+            client
+                .post("/")
+                .remote(format!("{ip}:8000").parse().unwrap())
+                .header(ContentType::Form)
+                .body("content=hello&title=&expires=&password=")
+                .dispatch()
+        };
+
+        assert_eq!(post("10.0.0.1").status(), Status::SeeOther);
+        let response = post("10.0.0.1");
+        assert_eq!(response.status(), Status::TooManyRequests);
+        assert_eq!(response.headers().get_one("Retry-After"), Some("60"));
+        assert_eq!(post("10.0.0.2").status(), Status::SeeOther);
+
+        // * The IP a client claims to have is ignored unless `ip_header` is set
+        let response = client
+            .post("/")
+            .remote("10.0.0.1:8000".parse().unwrap())
+            .header(Header::new("X-Real-IP", "10.0.0.9"))
+            .header(ContentType::Form)
+            .body("content=hello&title=&expires=&password=")
+            .dispatch();
+        assert_eq!(response.status(), Status::TooManyRequests);
+
+        // * API requests are limited by key, regardless of the client's IP
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+        let req = ask::NewApiKey {
+            name: "writer".to_owned(),
+            scopes: "create".parse().unwrap(),
+            expires: Default::default(),
+            owner: None,
+        };
+        let (key, _) = async_runtime()
+            .block_on(async { action::generate_api_key(req, db.get_pool()).await })
+            .unwrap();
+        let post_api = |ip: &str, key: String| {
+            client
+                .post("/api/v1/clip")
+                .remote(format!("{ip}:8000").parse().unwrap())
+                .header(Header::new(api::API_KEY_HEADER, key))
+                .dispatch()
+        };
+        let response = post_api("10.0.0.3", key.to_base64());
+        assert_ne!(response.status(), Status::TooManyRequests);
+        let response = post_api("10.0.0.4", key.to_base64());
+        assert_eq!(response.status(), Status::TooManyRequests);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+
+        // * Made up keys don't get a bucket of their own, they're limited by IP
+        let response = post_api("10.0.0.5", "made-up".to_owned());
+        assert_ne!(response.status(), Status::TooManyRequests);
+        let response = post_api("10.0.0.5", "another".to_owned());
+        assert_eq!(response.status(), Status::TooManyRequests);
+        assert!(client.get("/").dispatch().status() == Status::Ok);
+    }
+}
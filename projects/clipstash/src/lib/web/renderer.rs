@@ -1,4 +1,7 @@
-use crate::web::ctx;
+use crate::{
+    web::{ctx, highlight::Highlighter},
+    Clip,
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum RenderErr {
@@ -6,7 +9,8 @@ pub enum RenderErr {
     Render(#[from] handlebars::RenderError),
 }
 
-pub struct Renderer<'a>(handlebars::Handlebars<'a>);
+// ? The highlighter is kept here since it's only used to render pages
+pub struct Renderer<'a>(handlebars::Handlebars<'a>, Highlighter);
 
 impl<'a> Renderer<'a> {
     pub fn new(template_dir: std::path::PathBuf) -> Self {
@@ -14,7 +18,15 @@ impl<'a> Renderer<'a> {
         renderer
             .register_templates_directory(".hbs", &template_dir)
             .expect("failed to register handlebars templates");
-        Self(renderer)
+        Self(renderer, Highlighter::new())
+    }
+
+    /// * Encrypted clips are decrypted by the browser, so they're never highlighted
+    pub fn highlight(&self, clip: &Clip) -> Option<String> {
+        match clip.encrypted.is_set() {
+            true => None,
+            false => self.1.highlight(clip.content.as_str(), &clip.language),
+        }
     }
 
     // NOTE `String` is the serialized HTML that will be displayed to the client
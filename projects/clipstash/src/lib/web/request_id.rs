@@ -0,0 +1,77 @@
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::Header,
+    request::{FromRequest, Outcome, Request},
+    Response,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_LENGTH: usize = 64;
+
+/// * Identifies a single request, it's sent back in every response and in the body of API errors
+/// * IDs sent by a proxy in the `x-request-id` header are kept, so they can be matched with the proxy's logs
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RequestId(String);
+
+impl RequestId {
+    fn new(incoming: Option<&str>) -> Self {
+        match incoming {
+            Some(id) if Self::is_valid(id) => Self(id.to_owned()),
+            _ => Self(uuid::Uuid::new_v4().simple().to_string()),
+        }
+    }
+
+    fn is_valid(id: &str) -> bool {
+        !id.is_empty()
+            && id.len() <= MAX_LENGTH
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    /// * The ID is created the first time it's needed and then cached for the rest of the request
+    pub fn of<'r>(req: &'r Request<'_>) -> &'r Self {
+        req.local_cache(|| Self::new(req.headers().get_one(REQUEST_ID_HEADER)))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r RequestId {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(RequestId::of(req))
+    }
+}
+
+/// * Adds the request's ID to every response
+pub struct RequestIdHeader;
+
+#[rocket::async_trait]
+impl Fairing for RequestIdHeader {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request ID header",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        res.set_header(Header::new(
+            REQUEST_ID_HEADER,
+            RequestId::of(req).as_str().to_owned(),
+        ));
+    }
+}
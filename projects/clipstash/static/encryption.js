@@ -0,0 +1,57 @@
+// End-to-end encryption of clips, the key only ever lives in the URL fragment which browsers don't send to the server
+// The ciphertext is the base64url encoded AES-256-GCM nonce followed by the encrypted content, like clipclient's
+var ClipEncryption = (function () {
+  var NONCE_LENGTH = 12;
+
+  function toBase64Url(bytes) {
+    var binary = '';
+    bytes.forEach(function (b) { binary += String.fromCharCode(b); });
+    return btoa(binary).replace(/\+/g, '-').replace(/\//g, '_').replace(/=+$/, '');
+  }
+
+  function fromBase64Url(value) {
+    var base64 = value.trim().replace(/-/g, '+').replace(/_/g, '/');
+    while (base64.length % 4) {
+      base64 += '=';
+    }
+    return Uint8Array.from(atob(base64), function (c) { return c.charCodeAt(0); });
+  }
+
+  function importKey(raw, usage) {
+    return crypto.subtle.importKey('raw', raw, 'AES-GCM', false, [usage]);
+  }
+
+  return {
+    // WebCrypto is only available on secure origins, i.e. HTTPS or localhost
+    isSupported: function () {
+      return !!(window.crypto && window.crypto.subtle);
+    },
+
+    encrypt: function (plaintext) {
+      var raw = crypto.getRandomValues(new Uint8Array(32));
+      var nonce = crypto.getRandomValues(new Uint8Array(NONCE_LENGTH));
+      return importKey(raw, 'encrypt')
+        .then(function (key) {
+          return crypto.subtle.encrypt({ name: 'AES-GCM', iv: nonce }, key, new TextEncoder().encode(plaintext));
+        })
+        .then(function (encrypted) {
+          var bytes = new Uint8Array(NONCE_LENGTH + encrypted.byteLength);
+          bytes.set(nonce);
+          bytes.set(new Uint8Array(encrypted), NONCE_LENGTH);
+          return { ciphertext: toBase64Url(bytes), key: toBase64Url(raw) };
+        });
+    },
+
+    decrypt: function (ciphertext, key) {
+      var bytes = fromBase64Url(ciphertext);
+      return importKey(fromBase64Url(key), 'decrypt')
+        .then(function (key) {
+          return crypto.subtle.decrypt(
+            { name: 'AES-GCM', iv: bytes.slice(0, NONCE_LENGTH) }, key, bytes.slice(NONCE_LENGTH));
+        })
+        .then(function (plaintext) {
+          return new TextDecoder().decode(plaintext);
+        });
+    },
+  };
+})();